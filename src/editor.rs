@@ -1,26 +1,44 @@
-use crossterm::event::{read, Event};
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use crossterm::event::{poll, read, Event};
 
 mod editor_command;
 use editor_command::{
-    CmdlineModeCommand, CmdlineSubmode, Direction, EditorMode, InsertModeCommand, NormalModeCommand,
+    CmdlineModeCommand, CmdlineModeKeyParser, CmdlineSubmode, ConfirmModeCommand, Direction,
+    EditorMode, InsertModeCommand, Key, NormalModeCommand, NormalModeKeyParser, Operator,
+    RecoveryModeCommand, SplitDirection, VisualModeCommand, VisualModeKeyParser, VisualSubmode,
+    WindowCommand,
 };
 
 mod terminal;
-use terminal::{Size, Terminal};
+use terminal::{Position, Size, Terminal};
 
 mod status_bar;
-use status_bar::StatusBar;
 
 mod window;
-use window::Window;
+use window::{Selection, TextLocation, Window};
+
+mod layout;
+use layout::Layout;
 
 mod buffer;
+use buffer::Buffer;
+
+mod buffer_list;
+use buffer_list::BufferList;
 
 mod command_bar;
 use command_bar::CommandBar;
 
+mod cmdline_history;
+use cmdline_history::CmdlineHistory;
+
+mod completion;
+
 mod cmdline_commands;
-use cmdline_commands::CmdlineCommands;
+use cmdline_commands::{BufferSpecifier, CmdlineCommands, LineRange, Substitution};
 
 mod annotated_string;
 
@@ -29,16 +47,37 @@ mod highlighter;
 mod filetype;
 use filetype::FileType;
 
+mod syntax;
+
+mod colorscheme;
+
+mod registers;
+use registers::Registers;
+
+mod options;
+use options::{BufferOptions, EditorOptions};
+
+mod search_pattern;
+use search_pattern::SearchPattern;
+
+mod substitute;
+use substitute::{SubstituteMatch, SubstituteSession};
+
+mod swap_file;
+use swap_file::SwapFile;
+
 #[derive(Default, Eq, PartialEq, Debug)]
 pub struct DocumentStatus {
     total_lines: usize,
     current_line_index: usize,
     file_type: FileType,
     is_modified: bool,
+    is_readonly: bool,
     file_name: Option<String>,
+    file_options: BufferOptions,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub enum SearchDirection {
     Forward,
     Backward,
@@ -47,26 +86,52 @@ pub enum SearchDirection {
 pub struct RenderContext {
     pub file_type: FileType,
     pub enable_search_highlighting: bool,
-    pub search_pattern: String,
+    pub search_pattern: Option<SearchPattern>,
+    pub selection: Option<Selection>,
+    // line index and byte range of the match being confirmed by `:s///c`
+    pub current_match: Option<(usize, usize, usize)>,
 }
 
 impl RenderContext {
-    pub fn get_search_highlight_pattern(&self) -> Option<&str> {
+    pub fn get_search_highlight_pattern(&self) -> Option<&SearchPattern> {
         if self.enable_search_highlighting {
-            Some(&self.search_pattern)
+            self.search_pattern.as_ref()
         } else {
             None
         }
     }
 }
 
+// View and search state before typing a search pattern, restored when the search is canceled.
+struct IncrementalSearch {
+    cursor_location: TextLocation,
+    scroll_offset: Position,
+    search_pattern: Option<SearchPattern>,
+    enable_search_highlighting: bool,
+    // the cursor is on a match of the pattern being typed
+    found: bool,
+}
+
 pub struct Editor {
     should_quit: bool,
     mode: EditorMode,
-    window: Window,
-    status_bar: StatusBar,
+    normal_mode_key_parser: NormalModeKeyParser,
+    visual_mode_key_parser: VisualModeKeyParser,
+    cmdline_mode_key_parser: CmdlineModeKeyParser,
+    layout: Layout,
+    buffers: BufferList,
     command_bar: CommandBar,
     render_context: RenderContext,
+    registers: Registers,
+    options: EditorOptions,
+    // `:s///c` waiting for confirmation of the match
+    pending_substitution: Option<(SubstituteSession, SubstituteMatch)>,
+    // swap file found when the current buffer was opened
+    pending_recovery: Option<PathBuf>,
+    // insert mode is resumed after a command typed after `CTRL-O`
+    resume_insert_mode: bool,
+    // search pattern being typed after `/`
+    incremental_search: Option<IncrementalSearch>,
 }
 
 impl Editor {
@@ -76,28 +141,62 @@ impl Editor {
             let _ = Terminal::terminate(); // explicitly ignore errors in terminate()
             current_hook(panic_info);
         }));
-        let Size { height, .. } = Terminal::size().expect("Coud not get terminal size!");
-        let status_bar_height = 1;
-        let message_bar_height = 1;
-        let view = Window::new(status_bar_height + message_bar_height);
-        Self {
+        let size = Terminal::size().expect("Coud not get terminal size!");
+        let buffers = BufferList::default();
+        let layout = Layout::new(Window::new(Rc::clone(&buffers.current().buffer)));
+        let mut editor = Self {
             should_quit: false,
             mode: EditorMode::NormalMode,
-            window: view,
-            status_bar: StatusBar::new(height - status_bar_height - message_bar_height),
-            command_bar: CommandBar::new(height - message_bar_height),
+            normal_mode_key_parser: NormalModeKeyParser::default(),
+            visual_mode_key_parser: VisualModeKeyParser::default(),
+            cmdline_mode_key_parser: CmdlineModeKeyParser::default(),
+            layout,
+            buffers,
+            command_bar: CommandBar::default(),
             render_context: RenderContext {
                 file_type: FileType::Text,
                 enable_search_highlighting: true,
-                search_pattern: String::from(""),
+                search_pattern: None,
+                selection: None,
+                current_match: None,
             },
+            registers: Registers::default(),
+            options: EditorOptions::default(),
+            pending_substitution: None,
+            pending_recovery: None,
+            resume_insert_mode: false,
+            incremental_search: None,
+        };
+        editor.resize(size).expect("Could not arrange windows!");
+        editor.command_bar.set_history(CmdlineHistory::load());
+        if let Some(err) = syntax::load_errors().first() {
+            editor.command_bar.set_error_message(err);
         }
+        // the user's `default.colorscheme` replaces the built-in one
+        if let Err(err) = colorscheme::set_current("default") {
+            editor.command_bar.set_error_message(&err);
+        }
+        editor
+    }
+    // Lays out windows and the command bar for the terminal size.
+    fn resize(&mut self, size: Size) -> Result<(), std::io::Error> {
+        let message_bar_height = 1;
+        let height = size.height.saturating_sub(message_bar_height);
+        // windows tile the area above the command bar
+        self.layout.set_area(
+            Position::default(),
+            Size {
+                height,
+                width: size.width,
+            },
+        )?;
+        self.command_bar.set_area(height, size.width);
+        Ok(())
     }
     pub fn load_file(&mut self, filename: &str) {
-        self.window.load_file(&filename);
-        let status = self.window.get_status();
-        self.render_context.file_type = status.file_type;
-        self.status_bar.update_status(status);
+        if let Err(err) = self.edit_file(filename) {
+            self.command_bar.set_error_message(&err.to_string());
+        }
     }
     pub fn run(&mut self) {
         Terminal::initialize().unwrap();
@@ -111,46 +210,132 @@ impl Editor {
             if self.should_quit {
                 break;
             }
+            if !poll(swap_file::UPDATE_TIME)? {
+                // user is idle
+                self.update_swap_files(true);
+                continue;
+            }
             let event = read()?;
             self.evaluate_evnet(&event)?;
-            let status = self.window.get_status();
-            self.status_bar.update_status(status);
+            self.sync_windows()?;
+            self.update_swap_files(false);
         }
+        self.remove_swap_files();
+        // losing the history is not worth an error on exit
+        let _ = self.command_bar.get_history().save();
         Ok(())
     }
     fn evaluate_evnet(&mut self, event: &Event) -> Result<(), std::io::Error> {
+        if let Event::Resize(width, height) = event {
+            self.resize(Size {
+                height: usize::from(*height),
+                width: usize::from(*width),
+            })?;
+            // remove contents drawn for the old size
+            return Terminal::clear_screen();
+        }
+        // swap file prompt is kept until answered
+        let is_recovery_mode = matches!(self.mode, EditorMode::RecoveryMode);
+        if !is_recovery_mode && self.command_bar.clear_multiline_message() {
+            // message was drawn over the window
+            self.layout.set_needs_redraw();
+        }
+        let Some(key) = Key::from_event(event) else {
+            return Ok(());
+        };
+        let was_insert_mode = matches!(self.mode, EditorMode::InsertMode);
         match self.mode {
-            EditorMode::NormalMode => self.evaluate_evnet_in_normal_mode(event)?,
-            EditorMode::InsertMode => self.evaluate_evnet_in_insert_mode(event)?,
-            EditorMode::CmdlineMode(_) => self.evalueate_event_in_cmdline_mode(event)?,
+            EditorMode::NormalMode => self.evaluate_evnet_in_normal_mode(key)?,
+            EditorMode::InsertMode => self.evaluate_evnet_in_insert_mode(key)?,
+            EditorMode::CmdlineMode(_) => self.evalueate_event_in_cmdline_mode(key)?,
+            EditorMode::VisualMode(_) => self.evaluate_event_in_visual_mode(key)?,
+            EditorMode::ConfirmMode => self.evaluate_event_in_confirm_mode(key)?,
+            EditorMode::RecoveryMode => self.evaluate_event_in_recovery_mode(key)?,
+        }
+        if self.resume_insert_mode && !was_insert_mode {
+            match self.mode {
+                EditorMode::NormalMode if !self.normal_mode_key_parser.is_pending() => {
+                    self.resume_insert_mode = false;
+                    self.enter_insert_mode();
+                }
+                // commands such as `o` entered insert mode by themselves
+                EditorMode::InsertMode => self.resume_insert_mode = false,
+                // wait until visual or cmdline mode finishes
+                _ => (),
+            }
         }
         Ok(())
     }
-    fn evaluate_evnet_in_normal_mode(&mut self, event: &Event) -> Result<(), std::io::Error> {
-        let command = NormalModeCommand::from_key_event(event);
+    fn evaluate_evnet_in_normal_mode(&mut self, key: Key) -> Result<(), std::io::Error> {
+        let command = self.normal_mode_key_parser.parse(key);
         match command {
-            NormalModeCommand::CursorMove(direction) => {
-                self.window.handle_move(direction, false)?;
+            NormalModeCommand::CursorMove(direction, count) => {
+                self.layout
+                    .current_mut()
+                    .handle_move(direction, count, false)?;
             }
+            NormalModeCommand::Operate {
+                operator,
+                target,
+                count,
+                register,
+            } => {
+                if operator == Operator::Change {
+                    self.enter_insert_mode();
+                }
+                let content = self
+                    .layout
+                    .current_mut()
+                    .apply_operator(operator, target, count)?;
+                if operator == Operator::Yank {
+                    self.report_yank(&content.text);
+                    self.registers.yank(register, content);
+                } else {
+                    self.registers.delete(register, content);
+                }
+            }
+            NormalModeCommand::Put {
+                after,
+                count,
+                register,
+            } => match self.registers.get(register) {
+                Some(content) => {
+                    self.layout.current_mut().put(content, after, count)?;
+                }
+                None => {
+                    let name = register.unwrap_or('"');
+                    self.command_bar
+                        .set_error_message(&format!("Nothing in register {}", name));
+                }
+            },
             NormalModeCommand::EnterInsertMode => {
-                self.mode = EditorMode::InsertMode;
+                self.enter_insert_mode();
             }
             NormalModeCommand::EnterInsertModeAppend => {
-                self.window.handle_move(Direction::Right, true)?;
-                self.mode = EditorMode::InsertMode;
+                self.layout
+                    .current_mut()
+                    .handle_move(Direction::Right, 1, true)?;
+                self.enter_insert_mode();
             }
             NormalModeCommand::EnterInsertModeBeginNewLineAbove => {
-                self.window.begin_newline_above()?;
-                self.mode = EditorMode::InsertMode;
+                self.enter_insert_mode();
+                self.layout.current_mut().begin_newline_above()?;
             }
             NormalModeCommand::EnterInsertModeBeginNewLineBelow => {
-                self.window.begin_newline_below()?;
-                self.mode = EditorMode::InsertMode;
+                self.enter_insert_mode();
+                self.layout.current_mut().begin_newline_below()?;
             }
             NormalModeCommand::EnterCmdlineMode(submode) => {
                 self.mode = EditorMode::CmdlineMode(submode);
                 self.command_bar.clear_cmdline();
                 self.command_bar.set_cmdline_prompt(submode);
+                if let CmdlineSubmode::Search = submode {
+                    self.start_incremental_search();
+                }
+            }
+            NormalModeCommand::EnterVisualMode(submode) => {
+                self.layout.current_mut().start_selection(submode);
+                self.set_visual_mode(submode);
             }
             NormalModeCommand::SearchNext => {
                 self.execute_search(SearchDirection::Forward)?;
@@ -158,54 +343,200 @@ impl Editor {
             NormalModeCommand::SearchPrev => {
                 self.execute_search(SearchDirection::Backward)?;
             }
+            NormalModeCommand::Undo => {
+                self.layout.current_mut().undo()?;
+            }
+            NormalModeCommand::Window(command, count) => {
+                self.execute_window_command(command, count)?;
+            }
+            NormalModeCommand::Redo => {
+                self.layout.current_mut().redo()?;
+            }
             NormalModeCommand::Nop => (),
         }
         Ok(())
     }
-    fn evaluate_evnet_in_insert_mode(&mut self, event: &Event) -> Result<(), std::io::Error> {
-        let command = InsertModeCommand::from_key_event(event);
+    fn enter_insert_mode(&mut self) {
+        // Everything typed until leaving insert mode is undone at once.
+        self.layout.current_mut().begin_undo_group();
+        self.mode = EditorMode::InsertMode;
+    }
+    fn evaluate_evnet_in_insert_mode(&mut self, key: Key) -> Result<(), std::io::Error> {
+        let command = InsertModeCommand::from_key(key);
         match command {
             InsertModeCommand::LeaveInsertMode => {
                 self.mode = EditorMode::NormalMode;
-                self.window.normalize_cursor_position(false)?;
+                self.layout.current_mut().end_undo_group();
+                self.layout.current_mut().normalize_cursor_position(false)?;
             }
             InsertModeCommand::Insert(c) => {
-                self.window.insert_char(c)?;
+                self.layout.current_mut().insert_char(c)?;
             }
             InsertModeCommand::Backspace => {
-                self.window.handle_backspace()?;
+                self.layout.current_mut().handle_backspace()?;
+            }
+            InsertModeCommand::Delete => {
+                self.layout.current_mut().handle_delete()?;
             }
             InsertModeCommand::InsertNewLine => {
-                self.window.insert_newline()?;
+                self.layout.current_mut().insert_newline()?;
+            }
+            InsertModeCommand::CursorMove(direction) => {
+                self.layout.current_mut().handle_move(direction, 1, true)?;
+            }
+            InsertModeCommand::MoveToLineEnd => {
+                self.layout.current_mut().move_past_line_end()?;
+            }
+            InsertModeCommand::ScrollPage { forward } => {
+                self.layout.current_mut().scroll_page(forward, true)?;
+            }
+            InsertModeCommand::DeleteWord => {
+                self.layout.current_mut().delete_word_before_cursor()?;
+            }
+            InsertModeCommand::DeleteLine => {
+                self.layout.current_mut().delete_line_before_cursor()?;
+            }
+            InsertModeCommand::Indent | InsertModeCommand::Dedent => {
+                let increase = matches!(command, InsertModeCommand::Indent);
+                let EditorOptions {
+                    shiftwidth,
+                    expandtab,
+                    ..
+                } = self.options;
+                self.layout
+                    .current_mut()
+                    .shift_indent(increase, shiftwidth, expandtab)?;
+            }
+            InsertModeCommand::ExecuteNormalModeCommand => {
+                // the cursor may stay past the end of line
+                self.mode = EditorMode::NormalMode;
+                self.layout.current_mut().end_undo_group();
+                self.resume_insert_mode = true;
             }
             InsertModeCommand::Nop => (),
         }
         Ok(())
     }
-    fn evalueate_event_in_cmdline_mode(&mut self, event: &Event) -> Result<(), std::io::Error> {
-        let command = CmdlineModeCommand::from_key_event(event);
+    fn evaluate_event_in_visual_mode(&mut self, key: Key) -> Result<(), std::io::Error> {
+        let command = self.visual_mode_key_parser.parse(key);
+        match command {
+            VisualModeCommand::CursorMove(direction, count) => {
+                self.layout
+                    .current_mut()
+                    .handle_move(direction, count, false)?;
+            }
+            VisualModeCommand::SwitchSubmode(submode) => match self.mode {
+                EditorMode::VisualMode(current) if current == submode => {
+                    self.leave_visual_mode();
+                }
+                _ => {
+                    self.layout.current_mut().set_selection_kind(submode);
+                    self.set_visual_mode(submode);
+                }
+            },
+            VisualModeCommand::SwapAnchor => {
+                self.layout.current_mut().swap_selection_anchor()?;
+            }
+            VisualModeCommand::Delete => {
+                let content = self.layout.current_mut().delete_selection()?;
+                self.registers.delete(None, content);
+                self.leave_visual_mode();
+            }
+            VisualModeCommand::Yank => {
+                let content = self.layout.current_mut().yank_selection()?;
+                self.leave_visual_mode();
+                self.report_yank(&content.text);
+                self.registers.yank(None, content);
+            }
+            VisualModeCommand::Change => {
+                self.leave_visual_mode();
+                self.enter_insert_mode();
+                let content = self.layout.current_mut().change_selection()?;
+                self.registers.delete(None, content);
+            }
+            VisualModeCommand::EnterCmdlineMode => {
+                self.layout.current_mut().clear_selection();
+                self.mode = EditorMode::CmdlineMode(CmdlineSubmode::Cmdline);
+                self.command_bar.clear_cmdline();
+                self.command_bar.set_cmdline_prompt(CmdlineSubmode::Cmdline);
+                for c in "'<,'>".chars() {
+                    self.command_bar.insert_char(c);
+                }
+            }
+            VisualModeCommand::LeaveVisualMode => {
+                self.layout.current_mut().clear_selection();
+                self.leave_visual_mode();
+            }
+            VisualModeCommand::Nop => (),
+        }
+        Ok(())
+    }
+    fn report_yank(&mut self, text: &str) {
+        let n_lines = text.lines().count();
+        if n_lines > 2 {
+            self.command_bar
+                .set_message(&format!("{} lines yanked", n_lines));
+        }
+    }
+    fn set_visual_mode(&mut self, submode: VisualSubmode) {
+        self.mode = EditorMode::VisualMode(submode);
+        let indicator = match submode {
+            VisualSubmode::Characterwise => "-- VISUAL --",
+            VisualSubmode::Linewise => "-- VISUAL LINE --",
+            VisualSubmode::Blockwise => "-- VISUAL BLOCK --",
+        };
+        self.command_bar.set_message(indicator);
+    }
+    fn leave_visual_mode(&mut self) {
+        self.mode = EditorMode::NormalMode;
+        self.command_bar.clear_cmdline();
+    }
+    fn evalueate_event_in_cmdline_mode(&mut self, key: Key) -> Result<(), std::io::Error> {
+        let command = self.cmdline_mode_key_parser.parse(key);
+        let orig_cmdline = self.command_bar.get_raw_cmdline();
         match command {
             CmdlineModeCommand::LeaveCmdlineMode => {
                 self.mode = EditorMode::NormalMode;
                 self.command_bar.clear_cmdline();
+                self.cancel_incremental_search()?;
             }
             CmdlineModeCommand::Execute => {
-                // if let EditorMode::CmdlineMode(submode) = self.mode {}
-                match self.mode {
-                    EditorMode::CmdlineMode(CmdlineSubmode::Cmdline) => {
+                let EditorMode::CmdlineMode(submode) = self.mode else {
+                    panic!("You should be in cmdline mode here.")
+                };
+                self.command_bar.record_history();
+                // commands such as `:s///c` may switch to another mode
+                self.mode = EditorMode::NormalMode;
+                match submode {
+                    CmdlineSubmode::Cmdline => {
                         self.parse_and_execute_cmdline_command()?;
                     }
-                    EditorMode::CmdlineMode(CmdlineSubmode::Search) => {
+                    CmdlineSubmode::Search => {
                         let pattern = self.command_bar.get_raw_cmdline();
-                        self.render_context.search_pattern = pattern;
-                        self.execute_search(SearchDirection::Forward)?;
                         self.command_bar.clear_cmdline();
-                    }
-                    _ => {
-                        panic!("You should be in cmdline mode here.")
+                        // the cursor is already on the match found while typing
+                        if let Some(state) = self.incremental_search.take() {
+                            if state.found {
+                                self.render_context.enable_search_highlighting = true;
+                                return Ok(());
+                            }
+                            self.layout
+                                .current_mut()
+                                .restore_view(state.cursor_location, state.scroll_offset)?;
+                        }
+                        // empty pattern repeats the last search
+                        if !pattern.is_empty() {
+                            match SearchPattern::new(&pattern, &self.options) {
+                                Ok(pattern) => self.render_context.search_pattern = Some(pattern),
+                                Err(msg) => {
+                                    self.command_bar.set_error_message(&msg);
+                                    return Ok(());
+                                }
+                            }
+                        }
+                        self.execute_search(SearchDirection::Forward)?;
                     }
                 }
-                self.mode = EditorMode::NormalMode;
             }
             CmdlineModeCommand::Insert(c) => {
                 self.command_bar.insert_char(c);
@@ -213,18 +544,71 @@ impl Editor {
             CmdlineModeCommand::Backspace => {
                 self.command_bar.handle_backspace();
             }
+            CmdlineModeCommand::Delete => {
+                self.command_bar.handle_delete();
+            }
+            CmdlineModeCommand::CursorMove(direction) => {
+                self.command_bar.move_cursor(direction);
+            }
+            CmdlineModeCommand::DeleteWord => {
+                self.command_bar.delete_word_before_cursor();
+            }
+            CmdlineModeCommand::DeleteLine => {
+                self.command_bar.delete_before_cursor(0);
+            }
+            CmdlineModeCommand::BrowseHistory { older } => {
+                self.command_bar.browse_history(older);
+            }
+            CmdlineModeCommand::Complete { forward } => {
+                self.command_bar.complete(forward, &self.buffers.names());
+            }
+            CmdlineModeCommand::StepMatch { forward } => {
+                let direction = if forward {
+                    SearchDirection::Forward
+                } else {
+                    SearchDirection::Backward
+                };
+                if let (Some(state), Some(pattern)) = (
+                    self.incremental_search.as_mut(),
+                    self.render_context.search_pattern.as_ref(),
+                ) {
+                    if state.found {
+                        self.layout
+                            .current_mut()
+                            .move_to_match(pattern, direction)?;
+                    }
+                }
+            }
+            CmdlineModeCommand::InsertRegister(name) => {
+                if let Some(content) = self.registers.get(Some(name)) {
+                    // the command line has a single line
+                    let text = content.text.lines().collect::<Vec<_>>().join(" ");
+                    self.command_bar.insert_str(&text);
+                }
+            }
+            CmdlineModeCommand::InsertWordUnderCursor => {
+                if let Some(word) = self.layout.current().get_word_under_cursor() {
+                    self.command_bar.insert_str(&word);
+                }
+            }
             CmdlineModeCommand::Nop => (),
         }
+        if let EditorMode::CmdlineMode(CmdlineSubmode::Search) = self.mode {
+            if self.command_bar.get_raw_cmdline() != orig_cmdline {
+                self.update_incremental_search()?;
+            }
+        }
         Ok(())
     }
     fn parse_and_execute_cmdline_command(&mut self) -> Result<(), std::io::Error> {
-        let raw_cmdline = self.command_bar.get_current_cmdline();
-        if raw_cmdline.len() >= 1 {
+        let raw_cmdline = self.command_bar.get_raw_cmdline();
+        if !raw_cmdline.trim().is_empty() {
             let cmd = CmdlineCommands::parse_cmdline(&raw_cmdline);
             match cmd {
                 Ok(cmd) => {
-                    self.execute_cmdline_command(cmd)?;
+                    // clear before execution so that commands can leave messages
                     self.command_bar.clear_cmdline();
+                    self.execute_cmdline_command(cmd)?;
                 }
                 Err(msg) => {
                     self.command_bar.set_error_message(&msg);
@@ -236,42 +620,708 @@ impl Editor {
     fn execute_cmdline_command(&mut self, cmd: CmdlineCommands) -> Result<(), std::io::Error> {
         match cmd {
             CmdlineCommands::Quit => {
-                self.should_quit = true;
+                self.quit_window()?;
             }
-            CmdlineCommands::Write => {
-                self.window.save_buffer()?;
+            CmdlineCommands::Write { force } => {
+                let backupext = self.options.get_backupext();
+                let result = self.layout.current_mut().save_buffer(force, backupext);
+                if let Err(err) = result {
+                    self.command_bar.set_error_message(&err.to_string());
+                }
             }
             CmdlineCommands::Saveas(filename) => {
-                self.window.save_buffer_with_filename(&filename)?;
+                let backupext = self.options.get_backupext();
+                let result = self
+                    .layout
+                    .current_mut()
+                    .save_buffer_with_filename(&filename, backupext);
+                if let Err(err) = result {
+                    self.command_bar.set_error_message(&err.to_string());
+                }
             }
             CmdlineCommands::StopHighlighting => {
                 self.render_context.enable_search_highlighting = false;
-                self.window.set_needs_redraw();
+                self.layout.set_needs_redraw();
+            }
+            CmdlineCommands::Set(args) => {
+                for arg in args {
+                    // buffer-local options are tried first
+                    let result = match self.layout.current_mut().set_buffer_option(&arg) {
+                        Some(result) => result,
+                        None => self.options.set(&arg),
+                    };
+                    if let Err(msg) = result {
+                        self.command_bar.set_error_message(&msg);
+                        return Ok(());
+                    }
+                }
             }
+            CmdlineCommands::Substitute(range, substitution) => {
+                if let Err(msg) = self.start_substitute(range, &substitution) {
+                    self.command_bar.set_error_message(&msg);
+                }
+            }
+            CmdlineCommands::Edit {
+                filename: Some(filename),
+                force,
+            } => {
+                let buffer = Rc::clone(&self.buffers.current().buffer);
+                let is_modified = buffer.borrow().modified;
+                if is_modified && force {
+                    // `!` discards changes to the buffer being left
+                    if !self.revert_buffer()? {
+                        return Ok(());
+                    }
+                } else if is_modified && !self.is_shown_in_other_windows(&buffer) {
+                    // modified buffer is not hidden unless `!` is given
+                    self.command_bar
+                        .set_error_message("No write since last change (add ! to override)");
+                    return Ok(());
+                }
+                self.edit_file(&filename)?;
+            }
+            CmdlineCommands::Edit {
+                filename: None,
+                force,
+            } => {
+                let is_modified = self.buffers.current().buffer.borrow().modified;
+                if is_modified && !force {
+                    self.command_bar
+                        .set_error_message("No write since last change (add ! to override)");
+                } else {
+                    self.reload_buffer()?;
+                }
+            }
+            CmdlineCommands::BufferNext => {
+                self.switch_to_buffer(self.buffers.next_index(1))?;
+            }
+            CmdlineCommands::BufferPrev => {
+                self.switch_to_buffer(self.buffers.prev_index(1))?;
+            }
+            CmdlineCommands::Buffer(buffer) => match self.buffers.find(&buffer) {
+                Ok(idx) => self.switch_to_buffer(idx)?,
+                Err(msg) => self.command_bar.set_error_message(&msg),
+            },
+            CmdlineCommands::ListBuffers => {
+                self.list_buffers();
+            }
+            CmdlineCommands::BufferDelete { buffer, force } => {
+                if let Err(msg) = self.delete_buffer(buffer.as_ref(), force) {
+                    self.command_bar.set_error_message(&msg);
+                }
+            }
+            CmdlineCommands::Split(direction, filename) => {
+                if let Err(msg) = self.split_window(direction) {
+                    self.command_bar.set_error_message(&msg);
+                    return Ok(());
+                }
+                if let Some(filename) = filename {
+                    self.edit_file(&filename)?;
+                }
+            }
+            CmdlineCommands::Close => {
+                if let Err(msg) = self.layout.close() {
+                    self.command_bar.set_error_message(&msg);
+                }
+                self.focus_changed();
+            }
+            CmdlineCommands::Only => {
+                self.layout.only()?;
+            }
+            CmdlineCommands::Colorscheme(None) => {
+                self.command_bar.set_message(&colorscheme::current_name());
+            }
+            CmdlineCommands::Colorscheme(Some(name)) => match colorscheme::set_current(&name) {
+                Ok(()) => self.layout.set_needs_redraw(),
+                Err(msg) => self.command_bar.set_error_message(&msg),
+            },
+        }
+        Ok(())
+    }
+    fn execute_window_command(
+        &mut self,
+        command: WindowCommand,
+        count: usize,
+    ) -> Result<(), std::io::Error> {
+        match command {
+            WindowCommand::Split(direction) => {
+                if let Err(msg) = self.split_window(direction) {
+                    self.command_bar.set_error_message(&msg);
+                }
+            }
+            WindowCommand::Focus(direction) => self.layout.focus(direction, count),
+            WindowCommand::FocusNext => self.layout.focus_next(count),
+            WindowCommand::FocusPrev => self.layout.focus_prev(count),
+            WindowCommand::Close => {
+                if let Err(msg) = self.layout.close() {
+                    self.command_bar.set_error_message(&msg);
+                }
+            }
+            WindowCommand::Quit => self.quit_window()?,
+            WindowCommand::Only => self.layout.only()?,
+            WindowCommand::Grow(direction) => {
+                self.layout.resize(direction, count as isize)?;
+            }
+            WindowCommand::Shrink(direction) => {
+                self.layout.resize(direction, -(count as isize))?;
+            }
+            WindowCommand::Equalize => self.layout.equalize()?,
+        }
+        self.focus_changed();
+        Ok(())
+    }
+    // Splits the current window into two windows showing the same buffer.
+    fn split_window(&mut self, direction: SplitDirection) -> Result<(), String> {
+        let window = self.layout.current().duplicate();
+        self.layout.split(direction, window)
+    }
+    // Closes the current window, or quits if it is the last one.
+    fn quit_window(&mut self) -> Result<(), std::io::Error> {
+        if self.layout.len() == 1 {
+            self.should_quit = true;
+        } else if let Err(msg) = self.layout.close() {
+            self.command_bar.set_error_message(&msg);
+        }
+        self.focus_changed();
+        Ok(())
+    }
+    // Makes the buffer of the current window the current buffer.
+    fn focus_changed(&mut self) {
+        let buffer = Rc::clone(self.layout.current().get_buffer());
+        if let Some(idx) = self.buffers.find_by_buffer(&buffer) {
+            self.buffers.set_current(idx);
+        }
+    }
+    // Lets other windows showing the current buffer follow edits
+    // made in the current window.
+    fn sync_windows(&mut self) -> Result<(), std::io::Error> {
+        let buffer = Rc::clone(self.layout.current().get_buffer());
+        let changes = buffer.borrow_mut().take_changes();
+        if changes.is_empty() {
+            return Ok(());
+        }
+        for (is_current, window) in self.layout.windows_mut() {
+            if !is_current && Rc::ptr_eq(window.get_buffer(), &buffer) {
+                window.adjust_for_changes(&changes)?;
+            }
+        }
+        Ok(())
+    }
+    // Opens a file in a new buffer, or switches to the buffer if already opened.
+    // Failure to read the file is shown in the command bar.
+    fn edit_file(&mut self, filename: &str) -> Result<(), std::io::Error> {
+        if let Some(idx) = self.buffers.find_by_filename(filename) {
+            return self.switch_to_buffer(idx);
+        }
+        let current = Rc::clone(&self.buffers.current().buffer);
+        let is_shown_elsewhere = self.is_shown_in_other_windows(&current);
+        let is_unused = {
+            let current = current.borrow();
+            current.get_filename().is_none()
+                && !current.modified
+                && current.is_empty()
+                && !is_shown_elsewhere
+        };
+        let is_new = !std::path::Path::new(filename).exists();
+        if is_unused {
+            // reuse the initial empty buffer
+            let result = current.borrow_mut().load_file(filename);
+            if let Err(err) = result {
+                self.command_bar.set_error_message(&err.to_string());
+                return Ok(());
+            }
+            self.show_buffer(self.buffers.current_index())?;
+        } else {
+            let mut buffer = Buffer::default();
+            if let Err(err) = buffer.load_file(filename) {
+                self.command_bar.set_error_message(&err.to_string());
+                return Ok(());
+            }
+            let idx = self.buffers.add(buffer);
+            self.switch_to_buffer(idx)?;
+        }
+        if is_new {
+            self.command_bar
+                .set_message(&format!("\"{}\" [New]", filename));
+        }
+        self.check_swap_file(filename);
+        Ok(())
+    }
+    fn is_shown_in_other_windows(&self, buffer: &Rc<RefCell<Buffer>>) -> bool {
+        self.layout
+            .windows()
+            .iter()
+            .any(|(is_current, window)| !is_current && Rc::ptr_eq(window.get_buffer(), buffer))
+    }
+    // Reads the file of the current buffer again, discarding changes.
+    fn reload_buffer(&mut self) -> Result<(), std::io::Error> {
+        let buffer = Rc::clone(&self.buffers.current().buffer);
+        let result = buffer.borrow_mut().reload();
+        if let Err(err) = result {
+            self.command_bar.set_error_message(&err.to_string());
+            return Ok(());
+        }
+        self.reset_windows_showing(&buffer)
+    }
+    // Discards changes to the current buffer even if it has no file.
+    // Returns false if the file cannot be read.
+    fn revert_buffer(&mut self) -> Result<bool, std::io::Error> {
+        let buffer = Rc::clone(&self.buffers.current().buffer);
+        let result = buffer.borrow_mut().revert();
+        if let Err(err) = result {
+            self.command_bar.set_error_message(&err.to_string());
+            return Ok(false);
+        }
+        self.reset_windows_showing(&buffer)?;
+        Ok(true)
+    }
+    // Updates windows after contents of the buffer are replaced.
+    fn reset_windows_showing(
+        &mut self,
+        buffer: &Rc<RefCell<Buffer>>,
+    ) -> Result<(), std::io::Error> {
+        // cursors may be out of the new contents
+        for (_, window) in self.layout.windows_mut() {
+            if Rc::ptr_eq(window.get_buffer(), buffer) {
+                let cursor_location = window.get_cursor_location();
+                let scroll_offset = window.get_scroll_offset();
+                window.set_buffer(Rc::clone(buffer), cursor_location, scroll_offset)?;
+            }
+        }
+        Ok(())
+    }
+    // Asks what to do if a swap file of the file is left by a crashed editor.
+    // If the file is being edited by another editor, it is opened read-only.
+    fn check_swap_file(&mut self, filename: &str) {
+        if !self.options.swapfile {
+            return;
+        }
+        let path = swap_file::swap_path(filename, &self.options.directory);
+        if !path.exists() {
+            return;
+        }
+        if let Some(pid) = swap_file::running_owner(&path) {
+            // swap file of the other editor is left as it is
+            self.buffers.current().buffer.borrow_mut().readonly = true;
+            self.command_bar.set_error_message(&format!(
+                "\"{}\" is being edited by process {}. Opened read-only",
+                filename, pid
+            ));
+            return;
+        }
+        self.command_bar
+            .set_multiline_message(vec![format!("Found a swap file \"{}\"", path.display())]);
+        self.command_bar
+            .set_message("[r]ecover, [o]pen read-only, [d]elete it: ");
+        self.pending_recovery = Some(path);
+        self.mode = EditorMode::RecoveryMode;
+    }
+    fn evaluate_event_in_recovery_mode(&mut self, key: Key) -> Result<(), std::io::Error> {
+        let command = RecoveryModeCommand::from_key(key);
+        if let RecoveryModeCommand::Nop = command {
+            return Ok(());
+        }
+        self.mode = EditorMode::NormalMode;
+        if self.command_bar.clear_multiline_message() {
+            self.layout.set_needs_redraw();
+        }
+        let Some(path) = self.pending_recovery.take() else {
+            return Ok(());
+        };
+        let buffer = Rc::clone(&self.buffers.current().buffer);
+        match command {
+            RecoveryModeCommand::Recover => match swap_file::read_lines(&path) {
+                Ok(lines) => {
+                    buffer.borrow_mut().recover(lines);
+                    self.reset_windows_showing(&buffer)?;
+                    // the swap file is overwritten as the buffer is modified
+                    self.command_bar.set_message(&format!(
+                        "Recovered from \"{}\". Write the buffer to keep the changes",
+                        path.display()
+                    ));
+                }
+                Err(err) => {
+                    buffer.borrow_mut().readonly = true;
+                    self.command_bar
+                        .set_error_message(&format!("\"{}\" {}", path.display(), err));
+                }
+            },
+            RecoveryModeCommand::ReadOnly | RecoveryModeCommand::Nop => {
+                buffer.borrow_mut().readonly = true;
+            }
+            RecoveryModeCommand::Delete => {
+                if let Err(err) = std::fs::remove_file(&path) {
+                    self.command_bar
+                        .set_error_message(&format!("\"{}\" {}", path.display(), err));
+                }
+            }
+        }
+        Ok(())
+    }
+    // Writes swap files of modified buffers, and removes those of unmodified ones.
+    // Swap files are written less frequently unless `idle` is true.
+    fn update_swap_files(&mut self, idle: bool) {
+        let mut error = None;
+        for entry in self.buffers.iter_mut() {
+            let buffer = entry.buffer.borrow();
+            // read-only buffer may have a swap file of another editor
+            let path = buffer
+                .get_filename()
+                .filter(|_| self.options.swapfile && buffer.modified && !buffer.readonly)
+                .map(|filename| swap_file::swap_path(&filename, &self.options.directory));
+            let Some(path) = path else {
+                if let Some(swap_file) = entry.swap_file.take() {
+                    swap_file.remove();
+                }
+                continue;
+            };
+            if entry
+                .swap_file
+                .as_ref()
+                .is_some_and(|swap_file| swap_file.get_path() != path)
+            {
+                // file is renamed by `:saveas`
+                if let Some(swap_file) = entry.swap_file.take() {
+                    swap_file.remove();
+                }
+            }
+            let swap_file = entry.swap_file.get_or_insert_with(|| SwapFile::new(path));
+            if swap_file.needs_update(&buffer, idle) {
+                let was_failing = swap_file.is_failing();
+                if let Err(err) = swap_file.write(&buffer) {
+                    // not to repeat the same error
+                    if !was_failing {
+                        error = Some(format!(
+                            "Unable to write swap file \"{}\": {}",
+                            swap_file.get_path().display(),
+                            err
+                        ));
+                    }
+                }
+            }
+        }
+        if let Some(msg) = error {
+            self.command_bar.set_error_message(&msg);
+        }
+    }
+    fn remove_swap_files(&mut self) {
+        for entry in self.buffers.iter_mut() {
+            if let Some(swap_file) = entry.swap_file.take() {
+                swap_file.remove();
+            }
+        }
+    }
+    // Shows another buffer, saving cursor and scroll offset of the current one.
+    fn switch_to_buffer(&mut self, idx: usize) -> Result<(), std::io::Error> {
+        let current = self.buffers.current_mut();
+        current.cursor_location = self.layout.current_mut().get_cursor_location();
+        current.scroll_offset = self.layout.current_mut().get_scroll_offset();
+        self.show_buffer(idx)
+    }
+    fn show_buffer(&mut self, idx: usize) -> Result<(), std::io::Error> {
+        self.buffers.set_current(idx);
+        let entry = self.buffers.get(idx);
+        self.layout.current_mut().set_buffer(
+            Rc::clone(&entry.buffer),
+            entry.cursor_location,
+            entry.scroll_offset,
+        )
+    }
+    fn list_buffers(&mut self) {
+        let current_idx = self.buffers.current_index();
+        let windows = self.layout.windows();
+        let lines = self
+            .buffers
+            .iter()
+            .enumerate()
+            .map(|(idx, entry)| {
+                let buffer = entry.buffer.borrow();
+                let is_shown = windows
+                    .iter()
+                    .any(|(_, window)| Rc::ptr_eq(window.get_buffer(), &entry.buffer));
+                let (indicator, line_idx) = if idx == current_idx {
+                    ("%a", self.layout.current().get_cursor_location().line_idx)
+                } else if is_shown {
+                    (" a", entry.cursor_location.line_idx)
+                } else {
+                    (" h", entry.cursor_location.line_idx)
+                };
+                format!(
+                    "{:>3} {} {} \"{}\" line {}",
+                    entry.number,
+                    indicator,
+                    if buffer.modified { "+" } else { " " },
+                    buffer.get_filename().unwrap_or(String::from("[No Name]")),
+                    line_idx + 1
+                )
+            })
+            .collect();
+        self.command_bar.set_multiline_message(lines);
+    }
+    // Closes buffer `number` or the current buffer.
+    // Modified buffer is closed only if `force` is true.
+    fn delete_buffer(
+        &mut self,
+        buffer: Option<&BufferSpecifier>,
+        force: bool,
+    ) -> Result<(), String> {
+        let idx = match buffer {
+            Some(buffer) => self.buffers.find(buffer)?,
+            None => self.buffers.current_index(),
+        };
+        let entry = self.buffers.get(idx);
+        if entry.buffer.borrow().modified && !force {
+            return Err(format!(
+                "No write since last change for buffer {} (add ! to override)",
+                entry.number
+            ));
+        }
+        let removed = Rc::clone(&entry.buffer);
+        self.buffers.remove(idx);
+        // windows showing the removed buffer show the current one instead
+        let entry = self.buffers.current();
+        for (_, window) in self.layout.windows_mut() {
+            if Rc::ptr_eq(window.get_buffer(), &removed) {
+                window
+                    .set_buffer(
+                        Rc::clone(&entry.buffer),
+                        entry.cursor_location,
+                        entry.scroll_offset,
+                    )
+                    .map_err(|err| err.to_string())?;
+            }
+        }
+        Ok(())
+    }
+    fn start_substitute(
+        &mut self,
+        range: Option<LineRange>,
+        substitution: &Substitution,
+    ) -> Result<(), String> {
+        let status = self.layout.current_mut().get_status();
+        let lines = match range {
+            Some(range) => range.resolve(
+                status.current_line_index,
+                status.total_lines,
+                self.layout.current_mut().get_visual_lines(),
+            )?,
+            None => (status.current_line_index, status.current_line_index),
+        };
+        // empty pattern uses the last search pattern
+        let mut pattern = match (
+            &self.render_context.search_pattern,
+            substitution.pattern.is_empty(),
+        ) {
+            (_, false) => substitution.pattern.clone(),
+            (Some(last), true) => String::from(last.as_str()),
+            (None, true) => return Err("No previous regular expression".to_string()),
+        };
+        match substitution.ignore_case {
+            Some(true) => pattern.insert_str(0, r"\c"),
+            Some(false) => pattern.insert_str(0, r"\C"),
+            None => (),
+        }
+        let pattern = SearchPattern::new(&pattern, &self.options)?;
+        // the pattern is also used by `n` and highlighted
+        self.render_context.search_pattern = Some(pattern.clone());
+        self.render_context.enable_search_highlighting = true;
+        self.layout.set_needs_redraw();
+        let mut session = SubstituteSession::new(
+            pattern,
+            &substitution.replacement,
+            substitution.global,
+            lines,
+        );
+        // all substitutions are undone at once
+        self.layout.current_mut().begin_undo_group();
+        if substitution.confirm {
+            self.confirm_next_substitution(session)
+                .map_err(|err| err.to_string())?;
+        } else {
+            self.layout
+                .current_mut()
+                .replace_all_substitute_matches(&mut session);
+            self.finish_substitute(&session)
+                .map_err(|err| err.to_string())?;
+        }
+        Ok(())
+    }
+    // Shows the next match and asks whether to replace it.
+    fn confirm_next_substitution(
+        &mut self,
+        mut session: SubstituteSession,
+    ) -> Result<(), std::io::Error> {
+        match self
+            .layout
+            .current_mut()
+            .find_substitute_match(&mut session)?
+        {
+            Some(m) => {
+                self.render_context.current_match = Some((m.line_idx, m.start, m.end));
+                self.command_bar.set_message(&format!(
+                    "replace with {} (y/n/a/q/l)?",
+                    m.replacement.escape_debug()
+                ));
+                self.pending_substitution = Some((session, m));
+                self.mode = EditorMode::ConfirmMode;
+            }
+            None => self.finish_substitute(&session)?,
+        }
+        Ok(())
+    }
+    fn finish_substitute(&mut self, session: &SubstituteSession) -> Result<(), std::io::Error> {
+        self.layout.current_mut().end_undo_group();
+        self.layout.current_mut().finish_substitute(session)?;
+        self.render_context.current_match = None;
+        self.mode = EditorMode::NormalMode;
+        if session.is_substituted() {
+            self.command_bar.set_message(&session.report());
+        } else if session.has_matched() {
+            // every match was skipped
+            self.command_bar.clear_cmdline();
+        } else {
+            self.command_bar.set_error_message(&format!(
+                "Pattern not found: {}",
+                session.pattern().as_str()
+            ));
         }
         Ok(())
     }
+    fn evaluate_event_in_confirm_mode(&mut self, key: Key) -> Result<(), std::io::Error> {
+        let command = ConfirmModeCommand::from_key(key);
+        if let ConfirmModeCommand::Nop = command {
+            return Ok(());
+        }
+        let Some((mut session, m)) = self.pending_substitution.take() else {
+            self.mode = EditorMode::NormalMode;
+            return Ok(());
+        };
+        match command {
+            ConfirmModeCommand::Yes => {
+                self.layout
+                    .current_mut()
+                    .replace_substitute_match(&mut session, &m);
+                self.confirm_next_substitution(session)?;
+            }
+            ConfirmModeCommand::No => {
+                self.layout
+                    .current_mut()
+                    .skip_substitute_match(&mut session, &m);
+                self.confirm_next_substitution(session)?;
+            }
+            ConfirmModeCommand::All => {
+                self.layout
+                    .current_mut()
+                    .replace_substitute_match(&mut session, &m);
+                self.layout
+                    .current_mut()
+                    .replace_all_substitute_matches(&mut session);
+                self.finish_substitute(&session)?;
+            }
+            ConfirmModeCommand::Last => {
+                self.layout
+                    .current_mut()
+                    .replace_substitute_match(&mut session, &m);
+                self.finish_substitute(&session)?;
+            }
+            ConfirmModeCommand::Quit | ConfirmModeCommand::Nop => {
+                self.finish_substitute(&session)?;
+            }
+        }
+        Ok(())
+    }
+    fn start_incremental_search(&mut self) {
+        let window = self.layout.current();
+        self.incremental_search = Some(IncrementalSearch {
+            cursor_location: window.get_cursor_location(),
+            scroll_offset: window.get_scroll_offset(),
+            search_pattern: self.render_context.search_pattern.clone(),
+            enable_search_highlighting: self.render_context.enable_search_highlighting,
+            found: false,
+        });
+    }
+    // Moves the cursor to the first match of the pattern being typed, and highlights matches.
+    fn update_incremental_search(&mut self) -> Result<(), std::io::Error> {
+        let Some(state) = self.incremental_search.as_mut() else {
+            return Ok(());
+        };
+        let window = self.layout.current_mut();
+        window.restore_view(state.cursor_location, state.scroll_offset)?;
+        // highlighting is shown in all windows
+        self.layout.set_needs_redraw();
+        let pattern = self.command_bar.get_raw_cmdline();
+        if pattern.is_empty() {
+            state.found = false;
+            self.render_context.search_pattern = state.search_pattern.clone();
+            self.render_context.enable_search_highlighting = state.enable_search_highlighting;
+            return Ok(());
+        }
+        // a pattern being typed may be invalid, such as `(` before `)`
+        self.render_context.search_pattern = SearchPattern::new(&pattern, &self.options).ok();
+        self.render_context.enable_search_highlighting = true;
+        state.found = match &self.render_context.search_pattern {
+            Some(pattern) => self
+                .layout
+                .current_mut()
+                .move_to_match(pattern, SearchDirection::Forward)?,
+            None => false,
+        };
+        Ok(())
+    }
+    fn cancel_incremental_search(&mut self) -> Result<(), std::io::Error> {
+        let Some(state) = self.incremental_search.take() else {
+            return Ok(());
+        };
+        self.layout
+            .current_mut()
+            .restore_view(state.cursor_location, state.scroll_offset)?;
+        self.render_context.search_pattern = state.search_pattern;
+        self.render_context.enable_search_highlighting = state.enable_search_highlighting;
+        self.layout.set_needs_redraw();
+        Ok(())
+    }
     fn execute_search(&mut self, direction: SearchDirection) -> Result<(), std::io::Error> {
         self.render_context.enable_search_highlighting = true;
-        self.window.search(
-            self.render_context.get_search_highlight_pattern(),
-            direction,
-        )?;
+        // highlighting is shown in all windows
+        self.layout.set_needs_redraw();
+        let Some(pattern) = &self.render_context.search_pattern else {
+            return Ok(());
+        };
+        if !self
+            .layout
+            .current_mut()
+            .move_to_match(pattern, direction)?
+        {
+            self.command_bar
+                .set_error_message(&format!("Pattern not found: {}", pattern.as_str()));
+        }
         Ok(())
     }
     fn refresh_screen(&mut self) -> Result<(), std::io::Error> {
         if self.should_quit {
             Terminal::clear_screen()?;
-            print!("Goodbye!\r\n");
+            Terminal::print("Goodbye!\r\n")?;
         } else {
-            let status = self.window.get_status();
-            self.render_context.file_type = status.file_type;
-            self.window.render(&self.render_context)?;
-            self.status_bar.render()?;
+            // the wildmenu is drawn over the status line of a window
+            if self.command_bar.take_closed_wildmenu() {
+                self.layout.set_needs_redraw();
+            }
+            let current_match = self.render_context.current_match.take();
+            for (is_current, window) in self.layout.windows_mut() {
+                self.render_context.file_type = window.get_status().file_type;
+                self.render_context.selection = window.get_selection();
+                // match being confirmed is highlighted only in the current window
+                self.render_context.current_match = current_match.filter(|_| is_current);
+                window.render(&self.render_context, is_current)?;
+            }
+            self.render_context.current_match = current_match;
             self.command_bar.render()?;
-            let pos = self.window.get_relative_position();
+            let pos = match self.mode {
+                EditorMode::CmdlineMode(_) => self.command_bar.get_cursor_position(),
+                _ => self.layout.current().get_screen_position(),
+            };
             Terminal::move_cursor_to(pos)?;
         }
-        Ok(())
+        Terminal::flush()
     }
 }
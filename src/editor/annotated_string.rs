@@ -1,86 +1,83 @@
 use crossterm::style::Color;
 use unicode_segmentation::UnicodeSegmentation;
 
-#[derive(Clone, Copy, Debug)]
+use super::colorscheme;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum Style {
     SearchHit,
+    // match being confirmed by `:s///c`
+    CurrentMatch,
+    Selection,
+    // status line of windows other than the current one
+    InactiveStatusLine,
+    // completion selected in the wildmenu
+    WildMenu,
     Digit,
     String,
     Keywords,
     TypeName,
     VarinatName,
     Comment,
+    Lifetime,
+    // attribute such as `#[derive(Debug)]`
+    Attribute,
+    // macro invocation such as `println!`
+    Macro,
+    // character literal such as `'a'`
+    Char,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct DrawingOptions {
     pub foreground_color: Color,
     pub background_color: Color,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+// Terminal's own colors without attributes.
+impl Default for DrawingOptions {
+    fn default() -> Self {
+        Self {
+            foreground_color: Color::Reset,
+            background_color: Color::Reset,
+            bold: false,
+            italic: false,
+            underline: false,
+        }
+    }
 }
 
 impl Style {
-    pub fn get_drawing_options(&self) -> DrawingOptions {
-        match self {
-            Self::SearchHit => DrawingOptions {
-                foreground_color: Color::White,
-                background_color: Color::Rgb {
-                    r: 104,
-                    g: 83,
-                    b: 0,
-                },
-            },
-            Self::Digit => DrawingOptions {
-                foreground_color: Color::Rgb {
-                    r: 234,
-                    g: 156,
-                    b: 88,
-                },
-                background_color: Color::Reset,
-            },
-            Self::String => DrawingOptions {
-                foreground_color: Color::Rgb {
-                    r: 136,
-                    g: 178,
-                    b: 152,
-                },
-                background_color: Color::Reset,
-            },
-            Self::Keywords => DrawingOptions {
-                foreground_color: Color::Rgb {
-                    r: 151,
-                    g: 118,
-                    b: 216,
-                },
-                background_color: Color::Reset,
-            },
-            Self::TypeName => DrawingOptions {
-                foreground_color: Color::Rgb {
-                    r: 215,
-                    g: 189,
-                    b: 108,
-                },
-                background_color: Color::Reset,
-            },
-            Self::VarinatName => DrawingOptions {
-                foreground_color: Color::Rgb {
-                    r: 234,
-                    g: 156,
-                    b: 88,
-                },
-                background_color: Color::Reset,
-            },
-            Self::Comment => DrawingOptions {
-                foreground_color: Color::Rgb {
-                    r: 116,
-                    g: 128,
-                    b: 145,
-                },
-                background_color: Color::Reset,
-            },
+    // Returns the style named in syntax definitions and colorschemes.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "search_hit" => Some(Self::SearchHit),
+            "current_match" => Some(Self::CurrentMatch),
+            "selection" => Some(Self::Selection),
+            "inactive_status_line" => Some(Self::InactiveStatusLine),
+            "wildmenu" => Some(Self::WildMenu),
+            "number" => Some(Self::Digit),
+            "string" => Some(Self::String),
+            "keyword" => Some(Self::Keywords),
+            "type" => Some(Self::TypeName),
+            "constant" => Some(Self::VarinatName),
+            "comment" => Some(Self::Comment),
+            "lifetime" => Some(Self::Lifetime),
+            "attribute" => Some(Self::Attribute),
+            "macro" => Some(Self::Macro),
+            "char" => Some(Self::Char),
+            _ => None,
         }
     }
+    pub fn get_drawing_options(&self) -> DrawingOptions {
+        colorscheme::drawing_options(*self)
+    }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Annotation {
     style: Style,
     // byte index
@@ -119,9 +116,6 @@ impl AnnotatedString {
     pub fn add_annotation(&mut self, annot: Annotation) {
         self.annots.push(annot);
     }
-    pub fn get_str(&self) -> &str {
-        &self.string
-    }
     pub fn get_annotations(&self) -> &Vec<Annotation> {
         &self.annots
     }
@@ -165,6 +159,22 @@ impl AnnotatedString {
             annots,
         }
     }
+    // Replaces the whole string, stretching annotations over the new one.
+    pub fn replace_str(&self, s: &str) -> Self {
+        let annots = self
+            .annots
+            .iter()
+            .map(|annot| Annotation {
+                style: annot.style,
+                start_idx: 0,
+                end_idx: s.len(),
+            })
+            .collect();
+        Self {
+            string: String::from(s),
+            annots,
+        }
+    }
     pub fn push_annot_str(&mut self, rhs: &AnnotatedString) {
         let orig_len = self.string.len();
         self.string.push_str(&rhs.string);
@@ -181,7 +191,7 @@ impl AnnotatedString {
             })
         }
     }
-    pub fn into_segments(&self) -> Vec<Segment> {
+    pub fn to_segments(&self) -> Vec<Segment> {
         let mut result = vec![];
         for (idx, s) in self.string.grapheme_indices(true) {
             let mut style = None;
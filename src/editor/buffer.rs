@@ -1,122 +1,1021 @@
-use std::io::Write;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::rc::Rc;
+
+use ropey::Rope;
+use unicode_segmentation::UnicodeSegmentation;
 
 use super::window::TextLocation;
 
-use super::highlighter::HighlighterBundler;
+use super::highlighter::{HighlightCache, HighlighterBundler};
 
 mod line;
 pub use line::{Line, LineView};
 
 pub mod grapheme;
 
+mod error;
+pub use error::BufferError;
+
+mod file;
+pub use file::write_atomically;
+use file::DiskState;
+
+mod history;
+pub use history::Edit;
+use history::History;
+
 use crate::editor::filetype::FileType;
+use crate::editor::options::{BufferOptions, FileFormat};
+use crate::editor::search_pattern::SearchPattern;
+use crate::editor::SearchDirection;
+
+// Maximum number of lines kept in the line cache
+const LINE_CACHE_SIZE: usize = 1024;
 
 #[derive(Default)]
 pub struct Buffer {
-    pub lines: Vec<Line>,
+    // lines joined with '\n', without newline at the end of the last line
+    text: Rope,
+    // false for a buffer without any line, which is distinct from
+    // a buffer with a single empty line although `text` is empty in both
+    has_lines: bool,
+    // lines built from `text` on demand, keyed by line index
+    line_cache: RefCell<HashMap<usize, Rc<Line>>>,
+    highlight_cache: RefCell<HighlightCache>,
     filename: Option<String>,
     pub modified: bool,
+    history: History,
+    // edits not yet seen by other windows showing this buffer
+    changes: Vec<Edit>,
+    pub options: BufferOptions,
+    // state of the file when it was read or written last time
+    disk_state: Option<DiskState>,
+    // incremented on every change of the contents
+    changedtick: usize,
+    // writing is refused unless forced
+    pub readonly: bool,
 }
 
 impl Buffer {
     pub fn is_empty(&self) -> bool {
-        self.lines.is_empty()
+        !self.has_lines
     }
-    pub fn load_file(&mut self, filename: &str) {
-        let contents = std::fs::read_to_string(filename).expect("cannot open file");
-        let lines: Vec<_> = contents.lines().map(Line::from_str).collect();
-        self.lines = lines;
+    fn set_text(&mut self, text: Rope, has_lines: bool) {
+        self.text = text;
+        self.has_lines = has_lines;
+        self.line_cache.get_mut().clear();
+        self.highlight_cache.get_mut().clear();
+    }
+    // Missing file is opened as an empty buffer, and created when saved.
+    pub fn load_file(&mut self, filename: &str) -> Result<(), BufferError> {
+        let contents = match std::fs::read_to_string(filename) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(BufferError::from_io(filename, err)),
+        };
+        self.options = BufferOptions::default();
+        let contents = match contents.strip_prefix('\u{feff}') {
+            Some(contents) => {
+                self.options.bomb = true;
+                contents
+            }
+            None => &contents,
+        };
+        let fileformat = FileFormat::detect(contents);
+        self.options.fileformat = fileformat;
+        let body = match contents.strip_suffix('\n') {
+            Some(body) => body,
+            None => {
+                self.options.endofline = contents.is_empty();
+                contents
+            }
+        };
+        let body = match fileformat {
+            FileFormat::Dos => {
+                let body = body.strip_suffix('\r').unwrap_or(body);
+                Cow::Owned(body.replace("\r\n", "\n"))
+            }
+            FileFormat::Unix => Cow::Borrowed(body),
+        };
+        self.set_text(Rope::from_str(&body), !contents.is_empty());
+        self.history = History::default();
+        self.changes.clear();
+        self.changedtick += 1;
+        self.modified = false;
+        self.disk_state = DiskState::read(Path::new(filename));
         self.filename = Some(String::from(filename));
+        Ok(())
     }
-    pub fn save(&mut self) -> Result<(), std::io::Error> {
-        if let Some(filename) = &self.filename.clone() {
-            self.save_as_filename(filename)?;
+    // Discards changes and reads the file again.
+    pub fn reload(&mut self) -> Result<(), BufferError> {
+        let filename = self.filename.clone().ok_or(BufferError::NoFilename)?;
+        self.load_file(&filename)
+    }
+    // Discards changes, reading the file again or emptying a buffer without a file.
+    pub fn revert(&mut self) -> Result<(), BufferError> {
+        if self.filename.is_some() {
+            return self.reload();
         }
+        self.set_text(Rope::new(), false);
+        self.history = History::default();
+        self.changes.clear();
+        self.changedtick += 1;
+        self.modified = false;
         Ok(())
     }
-    pub fn save_as_filename(&mut self, filename: &str) -> Result<(), std::io::Error> {
-        let mut file = std::fs::File::create(filename)?;
-        for line in &self.lines {
-            writeln!(file, "{line}")?;
+    // Writes the buffer to its file. Unless `force` is true, fails if
+    // the file has been changed by other programs since it was read.
+    pub fn save(&mut self, force: bool, backupext: Option<&str>) -> Result<(), BufferError> {
+        let filename = self.filename.clone().ok_or(BufferError::NoFilename)?;
+        if self.readonly && !force {
+            return Err(BufferError::ReadOnly);
+        }
+        if !force
+            && self.disk_state.is_some()
+            && DiskState::read(Path::new(&filename)) != self.disk_state
+        {
+            return Err(BufferError::ChangedOnDisk(filename));
+        }
+        self.save_as_filename(&filename, backupext)
+    }
+    // Copy of the old file is made with `backupext` appended to the name if given.
+    pub fn save_as_filename(
+        &mut self,
+        filename: &str,
+        backupext: Option<&str>,
+    ) -> Result<(), BufferError> {
+        let mut contents = String::new();
+        if self.options.bomb {
+            contents.push('\u{feff}');
+        }
+        let line_ending = self.options.fileformat.line_ending();
+        for chunk in self.text.chunks() {
+            match self.options.fileformat {
+                FileFormat::Dos => contents.push_str(&chunk.replace('\n', line_ending)),
+                FileFormat::Unix => contents.push_str(chunk),
+            }
+        }
+        if self.has_lines && (self.options.endofline || self.options.fixendofline) {
+            contents.push_str(line_ending);
+        }
+        file::write_atomically(Path::new(filename), contents.as_bytes(), backupext)
+            .map_err(|err| BufferError::from_io(filename, err))?;
+        self.disk_state = DiskState::read(Path::new(filename));
+        if self.has_lines && self.options.fixendofline {
+            self.options.endofline = true;
         }
         self.filename = Some(filename.to_string());
+        self.history.mark_saved();
         self.modified = false;
         Ok(())
     }
+    // Replaces contents with lines recovered from a swap file.
+    pub fn recover(&mut self, lines: Vec<String>) {
+        self.set_text(Rope::from_str(&lines.join("\n")), !lines.is_empty());
+        self.history = History::default();
+        self.history.clear_saved_mark();
+        self.changes.clear();
+        self.changedtick += 1;
+        self.modified = true;
+    }
+    pub fn get_changedtick(&self) -> usize {
+        self.changedtick
+    }
+    // Applies an argument of `:set` command if it is a buffer-local option.
+    // Changing how the file is written makes the buffer modified.
+    pub fn set_option(&mut self, arg: &str) -> Option<Result<(), String>> {
+        let old_options = self.options;
+        let result = self.options.set(arg);
+        if self.options != old_options {
+            // options are not restored by undo
+            self.history.clear_saved_mark();
+            self.modified = true;
+        }
+        result
+    }
     pub fn get_filetype(&self) -> FileType {
         self.filename
             .as_deref()
-            .map_or(FileType::Text, |s| FileType::from_filename(s))
+            .map_or(FileType::Text, FileType::from_filename)
+    }
+    // Returns the line at `line_idx`, building it from the text if not cached.
+    pub fn get_line(&self, line_idx: usize) -> Option<Rc<Line>> {
+        if let Some(line) = self.line_cache.borrow().get(&line_idx) {
+            return Some(Rc::clone(line));
+        }
+        let line = Rc::new(self.build_line(line_idx)?);
+        let mut cache = self.line_cache.borrow_mut();
+        if cache.len() >= LINE_CACHE_SIZE {
+            cache.clear();
+        }
+        cache.insert(line_idx, Rc::clone(&line));
+        Some(line)
+    }
+    fn build_line(&self, line_idx: usize) -> Option<Line> {
+        if line_idx >= self.get_n_lines() {
+            return None;
+        }
+        let line = Cow::from(self.text.line(line_idx));
+        Some(Line::from_str(line.strip_suffix('\n').unwrap_or(&line)))
+    }
+    // Drops cached lines which are changed by an edit starting at `line_idx`.
+    fn invalidate_lines(&mut self, line_idx: usize, multiline: bool) {
+        self.highlight_cache.get_mut().invalidate(line_idx);
+        let cache = self.line_cache.get_mut();
+        if multiline {
+            cache.retain(|idx, _| *idx < line_idx);
+        } else {
+            cache.remove(&line_idx);
+        }
+    }
+    // Returns chunks of the text, which are lines joined with '\n'.
+    pub fn chunks(&self) -> impl Iterator<Item = &str> {
+        self.text.chunks()
     }
     pub fn get_line_length(&self, line_index: usize) -> usize {
-        self.lines.get(line_index).map_or(0, |line| line.len())
+        self.get_line(line_index).map_or(0, |line| line.len())
     }
     pub fn get_n_lines(&self) -> usize {
-        self.lines.len()
+        if self.has_lines {
+            self.text.len_lines()
+        } else {
+            0
+        }
     }
     pub fn get_filename(&self) -> Option<String> {
         self.filename.clone()
     }
-    fn init_if_empty(&mut self) {
-        if self.is_empty() {
-            self.lines.push(Line::default());
-        }
+    pub fn init_if_empty(&mut self) {
+        self.has_lines = true;
     }
     pub fn insert_char(&mut self, c: char, loc: TextLocation) {
-        self.init_if_empty();
-        if loc.line_idx >= self.lines.len() {
-            // TODO: insert new line at the end of buffer
-            return;
-        }
-        self.lines[loc.line_idx].insert_char(c, loc.grapheme_idx);
-        self.modified = true;
+        self.insert_text(loc, &c.to_string());
     }
     pub fn delete_grapheme(&mut self, loc: TextLocation) {
-        self.lines[loc.line_idx].delete_grapheme(loc.grapheme_idx);
-        self.modified = true;
+        self.delete_range(
+            loc,
+            TextLocation {
+                grapheme_idx: loc.grapheme_idx + 1,
+                line_idx: loc.line_idx,
+            },
+        );
     }
     pub fn join_adjacent_rows(&mut self, idx: usize) {
-        let next_line = self.lines.remove(idx + 1);
-        let current_line = &mut self.lines[idx];
-        current_line.push_line(&next_line);
-        self.modified = true;
+        if idx + 1 >= self.get_n_lines() {
+            return;
+        }
+        self.delete_range(
+            TextLocation {
+                grapheme_idx: self.get_line_length(idx),
+                line_idx: idx,
+            },
+            TextLocation {
+                grapheme_idx: 0,
+                line_idx: idx + 1,
+            },
+        );
     }
     pub fn begin_newline_above(&mut self, loc: TextLocation) {
         self.init_if_empty();
-        self.lines.insert(loc.line_idx, Line::default());
-        self.modified = true;
+        self.insert_text(
+            TextLocation {
+                grapheme_idx: 0,
+                line_idx: loc.line_idx,
+            },
+            "\n",
+        );
     }
     pub fn begin_newline_below(&mut self, loc: TextLocation) {
         self.init_if_empty();
-        self.lines.insert(loc.line_idx + 1, Line::default());
-        self.modified = true;
+        self.insert_text(
+            TextLocation {
+                grapheme_idx: self.get_line_length(loc.line_idx),
+                line_idx: loc.line_idx,
+            },
+            "\n",
+        );
     }
     pub fn insert_newline(&mut self, loc: TextLocation) {
+        self.insert_text(loc, "\n");
+    }
+    // Inserts `text` at `loc` and returns location just past the inserted text.
+    pub fn insert_text(&mut self, loc: TextLocation, text: &str) -> TextLocation {
         self.init_if_empty();
-        let remainder = self.lines[loc.line_idx].split_off(loc.grapheme_idx);
-        self.lines.insert(loc.line_idx + 1, remainder);
+        if loc.line_idx >= self.get_n_lines() || text.is_empty() {
+            // TODO: insert new line at the end of buffer
+            return loc;
+        }
+        let end = self.insert_text_unrecorded(loc, text);
+        let edit = Edit::Insert {
+            start: loc,
+            end,
+            text: String::from(text),
+        };
+        self.changes.push(edit.clone());
+        self.changedtick += 1;
+        self.history.record(edit);
         self.modified = true;
+        end
+    }
+    // Deletes text in range [start, end) and returns deleted text.
+    pub fn delete_range(&mut self, start: TextLocation, end: TextLocation) -> String {
+        if start.line_idx >= self.get_n_lines() {
+            return String::new();
+        }
+        let text = self.delete_range_unrecorded(start, end);
+        if !text.is_empty() {
+            let edit = Edit::Delete {
+                start,
+                end,
+                text: text.clone(),
+            };
+            self.changes.push(edit.clone());
+            self.changedtick += 1;
+            self.history.record(edit);
+            self.modified = true;
+        }
+        text
+    }
+    // Returns text in range [start, end) without modifying buffer.
+    pub fn get_text(&self, start: TextLocation, end: TextLocation) -> String {
+        if start.line_idx >= self.get_n_lines() {
+            return String::new();
+        }
+        let start = self.to_char_idx(start);
+        let end = self.to_char_idx(end);
+        if start >= end {
+            return String::new();
+        }
+        self.text.slice(start..end).to_string()
+    }
+    // Returns whole lines in range [first, last] joined with '\n'.
+    pub fn get_lines(&self, first: usize, last: usize) -> String {
+        self.get_text(
+            TextLocation {
+                grapheme_idx: 0,
+                line_idx: first,
+            },
+            TextLocation {
+                grapheme_idx: self.get_line_length(last),
+                line_idx: last,
+            },
+        )
+    }
+    // Deletes whole lines in range [first, last] and returns them joined with '\n'.
+    pub fn delete_lines(&mut self, first: usize, last: usize) -> String {
+        let n_lines = self.get_n_lines();
+        if first >= n_lines {
+            return String::new();
+        }
+        let last = usize::min(last, n_lines - 1);
+        let text = self.get_lines(first, last);
+        let (start, end) = if last + 1 < n_lines {
+            // remove lines together with newline at the end of last line
+            (
+                TextLocation {
+                    grapheme_idx: 0,
+                    line_idx: first,
+                },
+                TextLocation {
+                    grapheme_idx: 0,
+                    line_idx: last + 1,
+                },
+            )
+        } else if first > 0 {
+            // remove lines together with newline at the end of previous line
+            (
+                TextLocation {
+                    grapheme_idx: self.get_line_length(first - 1),
+                    line_idx: first - 1,
+                },
+                TextLocation {
+                    grapheme_idx: self.get_line_length(last),
+                    line_idx: last,
+                },
+            )
+        } else {
+            // all lines are removed, and a single empty line remains
+            (
+                TextLocation {
+                    grapheme_idx: 0,
+                    line_idx: 0,
+                },
+                TextLocation {
+                    grapheme_idx: self.get_line_length(last),
+                    line_idx: last,
+                },
+            )
+        };
+        self.delete_range(start, end);
+        text
+    }
+    // Deletes contents of lines in range [first, last] leaving a single
+    // empty line, and returns deleted lines joined with '\n'.
+    pub fn clear_lines(&mut self, first: usize, last: usize) -> String {
+        let text = self.get_lines(first, last);
+        self.delete_range(
+            TextLocation {
+                grapheme_idx: 0,
+                line_idx: first,
+            },
+            TextLocation {
+                grapheme_idx: self.get_line_length(last),
+                line_idx: last,
+            },
+        );
+        text
+    }
+    // Returns char index in the text of `loc`, which is clamped to the buffer.
+    fn to_char_idx(&self, loc: TextLocation) -> usize {
+        let line_idx = usize::min(loc.line_idx, self.text.len_lines() - 1);
+        let byte_idx = self
+            .get_line(line_idx)
+            .map_or(0, |line| line.to_byte_idx(loc.grapheme_idx));
+        self.text
+            .byte_to_char(self.text.line_to_byte(line_idx) + byte_idx)
+    }
+    fn insert_text_unrecorded(&mut self, loc: TextLocation, text: &str) -> TextLocation {
+        let line = self.get_line(loc.line_idx).unwrap_or_default();
+        let head = line.get_substr(0, loc.grapheme_idx);
+        let char_idx = self.to_char_idx(loc);
+        self.text.insert(char_idx, text);
+        self.invalidate_lines(loc.line_idx, text.contains('\n'));
+        let n_pieces = text.split('\n').count();
+        // graphemes are counted together with the text before the insertion
+        // on the same line, since they may be combined
+        let last_piece = text.rsplit('\n').next().unwrap_or_default();
+        let grapheme_idx = if n_pieces == 1 {
+            format!("{}{}", head, last_piece).graphemes(true).count()
+        } else {
+            last_piece.graphemes(true).count()
+        };
+        TextLocation {
+            grapheme_idx,
+            line_idx: loc.line_idx + n_pieces - 1,
+        }
+    }
+    fn delete_range_unrecorded(&mut self, start: TextLocation, end: TextLocation) -> String {
+        let start_idx = self.to_char_idx(start);
+        let end_idx = self.to_char_idx(end);
+        if start_idx >= end_idx {
+            return String::new();
+        }
+        let text = self.text.slice(start_idx..end_idx).to_string();
+        self.text.remove(start_idx..end_idx);
+        self.invalidate_lines(start.line_idx, text.contains('\n'));
+        text
+    }
+    fn apply_edit(&mut self, edit: &Edit) {
+        self.changes.push(edit.clone());
+        self.changedtick += 1;
+        match edit {
+            Edit::Insert { start, text, .. } => {
+                self.insert_text_unrecorded(*start, text);
+            }
+            Edit::Delete { start, end, .. } => {
+                self.delete_range_unrecorded(*start, *end);
+            }
+        }
+    }
+    // Returns edits made since the last call.
+    pub fn take_changes(&mut self) -> Vec<Edit> {
+        std::mem::take(&mut self.changes)
+    }
+    pub fn begin_undo_group(&mut self, cursor: TextLocation) {
+        self.history.begin_group(cursor);
+    }
+    pub fn end_undo_group(&mut self) {
+        self.history.end_group();
+    }
+    // Reverts last undo step and returns cursor location before the step.
+    pub fn undo(&mut self) -> Option<TextLocation> {
+        let step = self.history.pop_undo()?;
+        for edit in step.edits.iter().rev() {
+            self.apply_edit(&edit.inverse());
+        }
+        let cursor = step.cursor;
+        self.history.push_redo(step);
+        self.modified = !self.history.is_at_saved();
+        Some(cursor)
+    }
+    // Re-applies last undone step and returns location where the step starts.
+    pub fn redo(&mut self) -> Option<TextLocation> {
+        let step = self.history.pop_redo()?;
+        for edit in &step.edits {
+            self.apply_edit(edit);
+        }
+        let cursor = step.edits.first().map_or(step.cursor, Edit::start);
+        self.history.push_undo(step);
+        self.modified = !self.history.is_at_saved();
+        Some(cursor)
+    }
+    fn end_of_buffer(&self) -> TextLocation {
+        let last = self.get_n_lines().saturating_sub(1);
+        TextLocation {
+            grapheme_idx: self.get_line_length(last),
+            line_idx: last,
+        }
+    }
+    // Returns start location of the word next to `loc`.
+    // Returns end of buffer if there are no more words.
+    pub fn next_word_start(&self, loc: TextLocation, big_word: bool) -> TextLocation {
+        let Some(line) = self.get_line(loc.line_idx) else {
+            return loc;
+        };
+        if let Some((start, _)) = line
+            .word_spans(big_word)
+            .into_iter()
+            .find(|(start, _)| *start > loc.grapheme_idx)
+        {
+            return TextLocation {
+                grapheme_idx: start,
+                line_idx: loc.line_idx,
+            };
+        }
+        for line_idx in loc.line_idx + 1..self.get_n_lines() {
+            let line = self.get_line(line_idx).unwrap_or_default();
+            // an empty line is also regarded as a word
+            let first_word = line.word_spans(big_word).first().cloned();
+            if line.is_empty() || first_word.is_some() {
+                return TextLocation {
+                    grapheme_idx: first_word.map_or(0, |(start, _)| start),
+                    line_idx,
+                };
+            }
+        }
+        self.end_of_buffer()
     }
-    pub fn search(&self, pattern: &str) -> Vec<TextLocation> {
-        // return first match in the entire buffer
-        let mut result_list = vec![];
-        for (line_idx, line) in self.lines.iter().enumerate() {
-            let search_hits = line.search_all_occurence(pattern);
-            result_list.append(
-                &mut search_hits
-                    .iter()
-                    .map(|(str_idx, _)| TextLocation {
-                        line_idx,
-                        grapheme_idx: line.to_grapheme_idx(*str_idx),
-                    })
-                    .collect::<Vec<_>>(),
-            );
-        }
-        result_list
-    }
-    pub fn highlight(&self, highlighter: &mut HighlighterBundler) {
-        for line in self.lines.iter() {
-            highlighter.highlight_line(line);
+    // Returns start location of the word before `loc`.
+    // Returns start of buffer if there are no more words.
+    pub fn prev_word_start(&self, loc: TextLocation, big_word: bool) -> TextLocation {
+        let Some(line) = self.get_line(loc.line_idx) else {
+            return loc;
+        };
+        if let Some((start, _)) = line
+            .word_spans(big_word)
+            .into_iter()
+            .rev()
+            .find(|(start, _)| *start < loc.grapheme_idx)
+        {
+            return TextLocation {
+                grapheme_idx: start,
+                line_idx: loc.line_idx,
+            };
         }
+        for line_idx in (0..loc.line_idx).rev() {
+            let line = self.get_line(line_idx).unwrap_or_default();
+            let last_word = line.word_spans(big_word).last().cloned();
+            if line.is_empty() || last_word.is_some() {
+                return TextLocation {
+                    grapheme_idx: last_word.map_or(0, |(start, _)| start),
+                    line_idx,
+                };
+            }
+        }
+        TextLocation::default()
+    }
+    // Returns location of the last character of the word at or next to `loc`.
+    // Returns end of buffer if there are no more words.
+    pub fn next_word_end(&self, loc: TextLocation, big_word: bool) -> TextLocation {
+        let Some(line) = self.get_line(loc.line_idx) else {
+            return loc;
+        };
+        if let Some((_, end)) = line
+            .word_spans(big_word)
+            .into_iter()
+            .find(|(_, end)| end - 1 > loc.grapheme_idx)
+        {
+            return TextLocation {
+                grapheme_idx: end - 1,
+                line_idx: loc.line_idx,
+            };
+        }
+        for line_idx in loc.line_idx + 1..self.get_n_lines() {
+            let line = self.get_line(line_idx).unwrap_or_default();
+            if let Some((_, end)) = line.word_spans(big_word).first() {
+                return TextLocation {
+                    grapheme_idx: end - 1,
+                    line_idx,
+                };
+            }
+        }
+        self.end_of_buffer()
+    }
+    // Returns whether the line is empty, without building the line.
+    fn is_blank_line(&self, line_idx: usize) -> bool {
+        self.text.line(line_idx).chars().all(|c| c == '\n')
+    }
+    // Returns start of the empty line after the paragraph at `loc`.
+    pub fn next_paragraph_boundary(&self, loc: TextLocation) -> TextLocation {
+        let n_lines = self.get_n_lines();
+        let mut line_idx = loc.line_idx;
+        while line_idx < n_lines && self.is_blank_line(line_idx) {
+            line_idx += 1;
+        }
+        while line_idx < n_lines && !self.is_blank_line(line_idx) {
+            line_idx += 1;
+        }
+        if line_idx >= n_lines {
+            return self.end_of_buffer();
+        }
+        TextLocation {
+            grapheme_idx: 0,
+            line_idx,
+        }
+    }
+    // Returns start of the empty line before the paragraph at `loc`.
+    pub fn prev_paragraph_boundary(&self, loc: TextLocation) -> TextLocation {
+        let mut line_idx = usize::min(loc.line_idx, self.get_n_lines().saturating_sub(1));
+        while line_idx > 0 && self.is_blank_line(line_idx) {
+            line_idx -= 1;
+        }
+        while line_idx > 0 && !self.is_blank_line(line_idx) {
+            line_idx -= 1;
+        }
+        TextLocation {
+            grapheme_idx: 0,
+            line_idx,
+        }
+    }
+    // Returns location of the bracket matching with the first bracket
+    // at or after `loc` in the line.
+    pub fn matching_bracket(&self, loc: TextLocation) -> Option<TextLocation> {
+        const PAIRS: [(&str, &str); 3] = [("(", ")"), ("[", "]"), ("{", "}")];
+        let line = self.get_line(loc.line_idx)?;
+        let (grapheme_idx, open, close, forward) =
+            (loc.grapheme_idx..line.len()).find_map(|idx| {
+                let grapheme = line.get_nth_grapheme(idx)?.to_string();
+                PAIRS.iter().find_map(|(open, close)| {
+                    if grapheme == *open {
+                        Some((idx, *open, *close, true))
+                    } else if grapheme == *close {
+                        Some((idx, *close, *open, false))
+                    } else {
+                        None
+                    }
+                })
+            })?;
+        let mut depth = 0;
+        let mut current = TextLocation {
+            grapheme_idx,
+            line_idx: loc.line_idx,
+        };
+        loop {
+            let grapheme = self
+                .get_line(current.line_idx)
+                .and_then(|line| line.get_nth_grapheme(current.grapheme_idx))
+                .map(|g| g.to_string());
+            if grapheme.as_deref() == Some(open) {
+                depth += 1;
+            } else if grapheme.as_deref() == Some(close) {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(current);
+                }
+            }
+            current = if forward {
+                self.next_location(current)?
+            } else {
+                self.prev_location(current)?
+            };
+        }
+    }
+    // Returns next location of `loc` moving across lines.
+    fn next_location(&self, loc: TextLocation) -> Option<TextLocation> {
+        if loc.grapheme_idx + 1 < self.get_line_length(loc.line_idx) {
+            Some(TextLocation {
+                grapheme_idx: loc.grapheme_idx + 1,
+                line_idx: loc.line_idx,
+            })
+        } else if loc.line_idx + 1 < self.get_n_lines() {
+            Some(TextLocation {
+                grapheme_idx: 0,
+                line_idx: loc.line_idx + 1,
+            })
+        } else {
+            None
+        }
+    }
+    // Returns previous location of `loc` moving across lines.
+    fn prev_location(&self, loc: TextLocation) -> Option<TextLocation> {
+        if loc.grapheme_idx > 0 {
+            Some(TextLocation {
+                grapheme_idx: loc.grapheme_idx - 1,
+                line_idx: loc.line_idx,
+            })
+        } else if loc.line_idx > 0 {
+            Some(TextLocation {
+                grapheme_idx: self.get_line_length(loc.line_idx - 1).saturating_sub(1),
+                line_idx: loc.line_idx - 1,
+            })
+        } else {
+            None
+        }
+    }
+    // Finds the nearest match after or before `from`, wrapping around at the end of the buffer.
+    // Lines are searched one by one from `from`, so that a match near the cursor is found
+    // without searching the whole buffer.
+    pub fn find_next_match(
+        &self,
+        pattern: &SearchPattern,
+        from: TextLocation,
+        direction: SearchDirection,
+    ) -> Option<TextLocation> {
+        let n_lines = self.get_n_lines();
+        if n_lines == 0 {
+            return None;
+        }
+        // the line of `from` is searched again at last for matches before `from`
+        (0..=n_lines).find_map(|i| {
+            let line_idx = match direction {
+                SearchDirection::Forward => (from.line_idx + i) % n_lines,
+                SearchDirection::Backward => (from.line_idx + n_lines - i % n_lines) % n_lines,
+            };
+            let matches = self.find_in_line(pattern, line_idx);
+            let grapheme_idx = match (direction, i) {
+                (SearchDirection::Forward, 0) => {
+                    matches.into_iter().find(|idx| *idx > from.grapheme_idx)
+                }
+                (SearchDirection::Forward, _) => matches.into_iter().next(),
+                (SearchDirection::Backward, 0) => matches
+                    .into_iter()
+                    .rev()
+                    .find(|idx| *idx < from.grapheme_idx),
+                (SearchDirection::Backward, _) => matches.into_iter().last(),
+            }?;
+            Some(TextLocation {
+                grapheme_idx,
+                line_idx,
+            })
+        })
+    }
+    // Returns grapheme indices where matches start in the line.
+    fn find_in_line(&self, pattern: &SearchPattern, line_idx: usize) -> Vec<usize> {
+        let line = Cow::from(self.text.line(line_idx));
+        let line = line.strip_suffix('\n').unwrap_or(&line);
+        pattern
+            .find_all(line)
+            .iter()
+            .map(|(str_idx, _)| line[..*str_idx].graphemes(true).count())
+            .collect()
+    }
+    // Highlights lines from the first line of `highlighter` up to `end`.
+    pub fn highlight(&self, end: usize, highlighter: &mut HighlighterBundler) {
+        let start = highlighter.get_first_line_idx();
+        let end = usize::min(end, self.get_n_lines());
+        if start >= end {
+            return;
+        }
+        let mut cache = self.highlight_cache.borrow_mut();
+        let mut ctx = cache.get_context(start, highlighter, |idx| self.get_line_uncached(idx));
+        for line_idx in start..end {
+            highlighter.highlight_line(line_idx, &self.get_line_uncached(line_idx), &mut ctx);
+            cache.record(line_idx + 1, ctx);
+        }
+    }
+    // Returns the line without adding it to the cache,
+    // for lines which are read only once such as lines above the window.
+    fn get_line_uncached(&self, line_idx: usize) -> Rc<Line> {
+        let cached = self.line_cache.borrow().get(&line_idx).cloned();
+        cached.unwrap_or_else(|| Rc::new(self.build_line(line_idx).unwrap_or_default()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::options::EditorOptions;
+
+    fn loc(line_idx: usize, grapheme_idx: usize) -> TextLocation {
+        TextLocation {
+            grapheme_idx,
+            line_idx,
+        }
+    }
+
+    fn buffer_with(text: &str) -> Buffer {
+        let mut buffer = Buffer::default();
+        buffer.insert_text(loc(0, 0), text);
+        buffer
+    }
+
+    fn find(
+        buffer: &Buffer,
+        pattern: &str,
+        from: TextLocation,
+        direction: SearchDirection,
+    ) -> Option<TextLocation> {
+        let pattern = SearchPattern::new(pattern, &EditorOptions::default()).unwrap();
+        buffer.find_next_match(&pattern, from, direction)
+    }
+
+    #[test]
+    fn find_next_match_forward() {
+        let buffer = buffer_with("foo bar foo\nbar\nfoo");
+        let forward = SearchDirection::Forward;
+        assert_eq!(find(&buffer, "foo", loc(0, 0), forward), Some(loc(0, 8)));
+        assert_eq!(find(&buffer, "foo", loc(0, 8), forward), Some(loc(2, 0)));
+        assert_eq!(find(&buffer, "bar", loc(0, 4), forward), Some(loc(1, 0)));
+    }
+
+    #[test]
+    fn find_next_match_backward() {
+        let buffer = buffer_with("foo bar foo\nbar\nfoo");
+        let backward = SearchDirection::Backward;
+        assert_eq!(find(&buffer, "foo", loc(2, 0), backward), Some(loc(0, 8)));
+        assert_eq!(find(&buffer, "foo", loc(0, 8), backward), Some(loc(0, 0)));
+        assert_eq!(find(&buffer, "bar", loc(1, 2), backward), Some(loc(1, 0)));
+    }
+
+    #[test]
+    fn find_next_match_wraps_around() {
+        let buffer = buffer_with("foo bar\nbaz\nqux foo");
+        assert_eq!(
+            find(&buffer, "foo", loc(2, 4), SearchDirection::Forward),
+            Some(loc(0, 0))
+        );
+        assert_eq!(
+            find(&buffer, "foo", loc(0, 0), SearchDirection::Backward),
+            Some(loc(2, 4))
+        );
+        // the only match is found again from itself
+        assert_eq!(
+            find(&buffer, "baz", loc(1, 0), SearchDirection::Forward),
+            Some(loc(1, 0))
+        );
+        assert_eq!(
+            find(&buffer, "baz", loc(1, 0), SearchDirection::Backward),
+            Some(loc(1, 0))
+        );
+    }
+
+    #[test]
+    fn find_next_match_counts_graphemes() {
+        let buffer = buffer_with("日本語 foo");
+        assert_eq!(
+            find(&buffer, "foo", loc(0, 0), SearchDirection::Forward),
+            Some(loc(0, 4))
+        );
+    }
+
+    #[test]
+    fn find_next_match_without_match() {
+        let buffer = buffer_with("foo\nbar");
+        assert_eq!(
+            find(&buffer, "baz", loc(0, 0), SearchDirection::Forward),
+            None
+        );
+        assert_eq!(
+            find(
+                &Buffer::default(),
+                "baz",
+                loc(0, 0),
+                SearchDirection::Forward
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn revert_without_file() {
+        let mut buffer = buffer_with("foo\nbar");
+        assert!(buffer.modified);
+        buffer.revert().unwrap();
+        assert!(buffer.is_empty());
+        assert!(!buffer.modified);
+        assert!(buffer.undo().is_none());
+    }
+
+    // Returns lines through the line cache, which edits have to keep up to date.
+    fn lines(buffer: &Buffer) -> Vec<String> {
+        (0..buffer.get_n_lines())
+            .map(|idx| buffer.get_line(idx).unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn insert_into_empty_buffer() {
+        let mut buffer = Buffer::default();
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.get_n_lines(), 0);
+        assert_eq!(buffer.insert_text(loc(0, 0), "foo"), loc(0, 3));
+        assert!(!buffer.is_empty());
+        assert!(buffer.modified);
+        assert_eq!(lines(&buffer), ["foo"]);
+
+        let mut buffer = Buffer::default();
+        assert_eq!(buffer.insert_text(loc(0, 0), "\n"), loc(1, 0));
+        assert_eq!(lines(&buffer), ["", ""]);
+    }
+
+    #[test]
+    fn insert_multiple_lines() {
+        let mut buffer = buffer_with("foo bar\nbaz");
+        assert_eq!(lines(&buffer), ["foo bar", "baz"]);
+        assert_eq!(buffer.insert_text(loc(0, 3), "1\n22\n333"), loc(2, 3));
+        assert_eq!(lines(&buffer), ["foo1", "22", "333 bar", "baz"]);
+        assert_eq!(buffer.insert_text(loc(3, 0), "\n"), loc(4, 0));
+        assert_eq!(lines(&buffer), ["foo1", "22", "333 bar", "", "baz"]);
+    }
+
+    #[test]
+    fn insert_at_end() {
+        let mut buffer = buffer_with("foo\nbar");
+        assert_eq!(buffer.insert_text(loc(1, 3), "\nbaz"), loc(2, 3));
+        assert_eq!(lines(&buffer), ["foo", "bar", "baz"]);
+        assert_eq!(buffer.insert_text(loc(2, 3), "\n"), loc(3, 0));
+        assert_eq!(lines(&buffer), ["foo", "bar", "baz", ""]);
+        // location after the end of line is clamped to the end
+        assert_eq!(buffer.insert_text(loc(0, 99), "!"), loc(0, 4));
+        assert_eq!(lines(&buffer), ["foo!", "bar", "baz", ""]);
+        // lines after the buffer are not created
+        assert_eq!(buffer.insert_text(loc(4, 0), "qux"), loc(4, 0));
+        assert_eq!(buffer.get_n_lines(), 4);
+    }
+
+    #[test]
+    fn insert_combining_character() {
+        let mut buffer = buffer_with("e日本");
+        // combined with the preceding character into one grapheme
+        assert_eq!(buffer.insert_text(loc(0, 1), "\u{301}"), loc(0, 1));
+        assert_eq!(lines(&buffer), ["e\u{301}日本"]);
+        assert_eq!(buffer.get_line_length(0), 3);
+        assert_eq!(buffer.insert_text(loc(0, 2), "x"), loc(0, 3));
+        assert_eq!(lines(&buffer), ["e\u{301}日x本"]);
+    }
+
+    #[test]
+    fn delete_multiple_lines() {
+        let mut buffer = buffer_with("foo\nbar\nbaz");
+        assert_eq!(lines(&buffer), ["foo", "bar", "baz"]);
+        assert_eq!(buffer.delete_range(loc(0, 1), loc(2, 2)), "oo\nbar\nba");
+        assert_eq!(lines(&buffer), ["fz"]);
+
+        let mut buffer = buffer_with("foo\nbar\nbaz");
+        // joins lines
+        assert_eq!(buffer.delete_range(loc(0, 3), loc(1, 0)), "\n");
+        assert_eq!(lines(&buffer), ["foobar", "baz"]);
+        assert_eq!(buffer.delete_lines(0, 0), "foobar");
+        assert_eq!(lines(&buffer), ["baz"]);
+    }
+
+    #[test]
+    fn delete_at_end() {
+        let mut buffer = buffer_with("foo\nbar");
+        assert_eq!(buffer.delete_range(loc(1, 2), loc(1, 3)), "r");
+        assert_eq!(lines(&buffer), ["foo", "ba"]);
+        // nothing after the end of buffer
+        assert_eq!(buffer.delete_range(loc(1, 2), loc(1, 99)), "");
+        assert_eq!(buffer.delete_range(loc(2, 0), loc(2, 1)), "");
+        assert_eq!(lines(&buffer), ["foo", "ba"]);
+        assert_eq!(buffer.delete_lines(1, 1), "ba");
+        assert_eq!(lines(&buffer), ["foo"]);
+        // a single empty line remains
+        assert_eq!(buffer.delete_lines(0, 0), "foo");
+        assert_eq!(lines(&buffer), [""]);
+        assert!(!buffer.is_empty());
+    }
+
+    #[test]
+    fn delete_in_empty_buffer() {
+        let mut buffer = Buffer::default();
+        assert_eq!(buffer.delete_range(loc(0, 0), loc(0, 1)), "");
+        assert_eq!(buffer.delete_lines(0, 0), "");
+        assert!(buffer.is_empty());
+        assert!(!buffer.modified);
+        assert!(buffer.undo().is_none());
+    }
+
+    #[test]
+    fn edit_and_save_crlf_file() {
+        let dir = std::env::temp_dir().join(format!("hecto-crlf-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("dos.txt");
+        let filename = path.to_str().unwrap();
+        std::fs::write(&path, "foo\r\nbar\r\n").unwrap();
+
+        let mut buffer = Buffer::default();
+        buffer.load_file(filename).unwrap();
+        assert_eq!(buffer.options.fileformat, FileFormat::Dos);
+        assert_eq!(lines(&buffer), ["foo", "bar"]);
+        assert_eq!(buffer.insert_text(loc(1, 3), "1\n2"), loc(2, 1));
+        assert_eq!(buffer.delete_range(loc(0, 2), loc(0, 3)), "o");
+        assert_eq!(lines(&buffer), ["fo", "bar1", "2"]);
+        buffer.save_as_filename(filename, None).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"fo\r\nbar1\r\n2\r\n");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn carriage_return_is_not_line_break() {
+        let mut buffer = buffer_with("a\rb\r\nc");
+        assert_eq!(lines(&buffer), ["a\rb\r", "c"]);
+        assert_eq!(buffer.insert_text(loc(1, 1), "\r"), loc(1, 2));
+        assert_eq!(buffer.get_n_lines(), 2);
     }
 }
@@ -0,0 +1,46 @@
+use std::fmt;
+use std::io::ErrorKind;
+
+// Errors of reading and writing files of buffers.
+#[derive(Debug)]
+pub enum BufferError {
+    // buffer has no file to write to
+    NoFilename,
+    // buffer is opened read-only
+    ReadOnly,
+    PermissionDenied(String),
+    // file is not encoded in UTF-8
+    InvalidUtf8(String),
+    // file has been changed by other programs since it was read
+    ChangedOnDisk(String),
+    Io(String, std::io::Error),
+}
+
+impl BufferError {
+    pub fn from_io(filename: &str, err: std::io::Error) -> Self {
+        match err.kind() {
+            ErrorKind::PermissionDenied => Self::PermissionDenied(String::from(filename)),
+            ErrorKind::InvalidData => Self::InvalidUtf8(String::from(filename)),
+            _ => Self::Io(String::from(filename), err),
+        }
+    }
+}
+
+impl fmt::Display for BufferError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NoFilename => write!(formatter, "No file name"),
+            Self::ReadOnly => write!(formatter, "'readonly' option is set (add ! to override)"),
+            Self::PermissionDenied(filename) => {
+                write!(formatter, "\"{}\" Permission denied", filename)
+            }
+            Self::InvalidUtf8(filename) => write!(formatter, "\"{}\" Invalid UTF-8", filename),
+            Self::ChangedOnDisk(filename) => write!(
+                formatter,
+                "\"{}\" WARNING: The file has been changed since reading it (add ! to override)",
+                filename
+            ),
+            Self::Io(filename, err) => write!(formatter, "\"{}\" {}", filename, err),
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+// Metadata of a file used to detect changes made by other programs.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DiskState {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl DiskState {
+    // Returns `None` if the file does not exist.
+    pub fn read(path: &Path) -> Option<Self> {
+        let metadata = std::fs::metadata(path).ok()?;
+        Some(Self {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+        })
+    }
+}
+
+// Writes `contents` to a temporary file and renames it to `path`, so that
+// the file is not broken even if writing fails on the way.
+// Permissions of the existing file are kept, and the file is copied to
+// `path` + `backupext` beforehand if `backupext` is given.
+pub fn write_atomically(
+    path: &Path,
+    contents: &[u8],
+    backupext: Option<&str>,
+) -> Result<(), std::io::Error> {
+    // write through symbolic links instead of replacing them
+    let target = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let metadata = std::fs::metadata(&target).ok();
+    if let (Some(ext), Some(_)) = (backupext, &metadata) {
+        let mut backup = OsString::from(target.as_os_str());
+        backup.push(ext);
+        std::fs::copy(&target, backup)?;
+    }
+    let temp = temp_path(&target);
+    let result = write_new_file(&temp, contents)
+        .and_then(|()| match &metadata {
+            Some(metadata) => std::fs::set_permissions(&temp, metadata.permissions()),
+            None => Ok(()),
+        })
+        .and_then(|()| std::fs::rename(&temp, &target));
+    if result.is_err() {
+        // explicitly ignore errors, since the temporary file may not be created
+        let _ = std::fs::remove_file(&temp);
+    }
+    result
+}
+
+fn write_new_file(path: &Path, contents: &[u8]) -> Result<(), std::io::Error> {
+    let mut file = File::options().write(true).create_new(true).open(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+// Returns a hidden file name in the same directory as `path`,
+// since renaming works only within a file system.
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(".{}.tmp", std::process::id()));
+    path.with_file_name(name)
+}
@@ -15,7 +15,7 @@ impl GraphemeWidth {
             _ => panic!("Invalid grapheme width"),
         }
     }
-    pub fn to_usize(&self) -> usize {
+    pub fn to_usize(self) -> usize {
         match self {
             Self::Half => 1,
             Self::Full => 2,
@@ -23,27 +23,32 @@ impl GraphemeWidth {
     }
 }
 
+// Number of columns between tab stops.
+pub const TABSTOP: usize = 8;
+
 fn calc_tab_width(current_pos: usize) -> usize {
-    let tabstop = 8;
-    (current_pos / tabstop + 1) * tabstop - current_pos
+    (current_pos / TABSTOP + 1) * TABSTOP - current_pos
 }
 
-#[derive(Clone)]
-pub struct Grapheme {
-    string: String,
+// Display width of a grapheme, whose text is kept in the line.
+#[derive(Copy, Clone)]
+pub struct GraphemeInfo {
     width: GraphemeWidth,
+    is_tab: bool,
 }
 
-impl Grapheme {
+impl GraphemeInfo {
+    fn from_str(s: &str) -> Self {
+        Self {
+            width: GraphemeWidth::from_usize(s.width_cjk()),
+            is_tab: s.starts_with('\t'),
+        }
+    }
     pub fn is_tab(&self) -> bool {
-        self.string
-            .chars()
-            .next()
-            .expect("contents of grapheme should not be empty")
-            == '\t'
+        self.is_tab
     }
     pub fn get_width_at_current_pos(&self, current_pos: usize) -> usize {
-        if self.is_tab() {
+        if self.is_tab {
             calc_tab_width(current_pos)
         } else {
             self.width.to_usize()
@@ -51,22 +56,32 @@ impl Grapheme {
     }
 }
 
+#[derive(Clone)]
+pub struct Grapheme {
+    string: String,
+    info: GraphemeInfo,
+}
+
+impl Grapheme {
+    pub fn from_str(s: &str) -> Self {
+        Self {
+            string: String::from(s),
+            info: GraphemeInfo::from_str(s),
+        }
+    }
+    pub fn is_tab(&self) -> bool {
+        self.info.is_tab()
+    }
+}
+
 impl std::fmt::Display for Grapheme {
     fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(formatter, "{}", self.string)
     }
 }
 
-pub fn str_to_graphemes(s: &str) -> (Vec<Grapheme>, Vec<usize>) {
+pub fn str_to_graphemes(s: &str) -> (Vec<GraphemeInfo>, Vec<usize>) {
     s.grapheme_indices(true)
-        .map(|(grapheme_idx, s)| {
-            (
-                Grapheme {
-                    string: String::from(s),
-                    width: GraphemeWidth::from_usize(s.width_cjk()),
-                },
-                grapheme_idx,
-            )
-        })
+        .map(|(str_idx, s)| (GraphemeInfo::from_str(s), str_idx))
         .unzip()
 }
@@ -0,0 +1,302 @@
+use super::super::window::TextLocation;
+
+// Primitive edit operations applied to a buffer.
+// `text` may contain '\n', in which case the edit spans multiple lines.
+#[derive(Clone, Debug)]
+pub enum Edit {
+    Insert {
+        start: TextLocation,
+        end: TextLocation,
+        text: String,
+    },
+    Delete {
+        start: TextLocation,
+        end: TextLocation,
+        text: String,
+    },
+}
+
+impl Edit {
+    pub fn start(&self) -> TextLocation {
+        match self {
+            Self::Insert { start, .. } | Self::Delete { start, .. } => *start,
+        }
+    }
+    // Returns where `loc` moves to when the edit is applied.
+    pub fn adjust_location(&self, loc: TextLocation) -> TextLocation {
+        let key = |loc: TextLocation| (loc.line_idx, loc.grapheme_idx);
+        match self {
+            Self::Insert { start, end, .. } => {
+                if key(loc) < key(*start) {
+                    loc
+                } else if loc.line_idx == start.line_idx {
+                    TextLocation {
+                        grapheme_idx: end.grapheme_idx + loc.grapheme_idx - start.grapheme_idx,
+                        line_idx: end.line_idx,
+                    }
+                } else {
+                    TextLocation {
+                        grapheme_idx: loc.grapheme_idx,
+                        line_idx: loc.line_idx + end.line_idx - start.line_idx,
+                    }
+                }
+            }
+            Self::Delete { start, end, .. } => {
+                if key(loc) <= key(*start) {
+                    loc
+                } else if key(loc) < key(*end) {
+                    *start
+                } else if loc.line_idx == end.line_idx {
+                    TextLocation {
+                        grapheme_idx: start.grapheme_idx + loc.grapheme_idx - end.grapheme_idx,
+                        line_idx: start.line_idx,
+                    }
+                } else {
+                    TextLocation {
+                        grapheme_idx: loc.grapheme_idx,
+                        line_idx: loc.line_idx - (end.line_idx - start.line_idx),
+                    }
+                }
+            }
+        }
+    }
+    pub fn inverse(&self) -> Self {
+        match self.clone() {
+            Self::Insert { start, end, text } => Self::Delete { start, end, text },
+            Self::Delete { start, end, text } => Self::Insert { start, end, text },
+        }
+    }
+}
+
+// A group of edits which is undone/redone at once.
+pub struct UndoStep {
+    pub edits: Vec<Edit>,
+    // cursor location before the first edit in this step
+    pub cursor: TextLocation,
+    // unique in the history, to tell whether the buffer is back to the saved state
+    id: usize,
+}
+
+pub struct History {
+    undo_stack: Vec<UndoStep>,
+    redo_stack: Vec<UndoStep>,
+    // step being built while an undo group is open
+    pending: Option<UndoStep>,
+    group_depth: usize,
+    next_id: usize,
+    // id of the step on top of the undo stack when the buffer matched the file,
+    // where 0 means the empty stack. `None` if the state cannot be reached by undo or redo.
+    saved_step: Option<usize>,
+}
+
+// History of a buffer just read from the file.
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            undo_stack: vec![],
+            redo_stack: vec![],
+            pending: None,
+            group_depth: 0,
+            next_id: 1,
+            saved_step: Some(0),
+        }
+    }
+}
+
+impl History {
+    pub fn begin_group(&mut self, cursor: TextLocation) {
+        if self.group_depth == 0 {
+            self.pending = Some(UndoStep {
+                edits: vec![],
+                cursor,
+                id: self.new_id(),
+            });
+        }
+        self.group_depth += 1;
+    }
+    pub fn end_group(&mut self) {
+        self.group_depth = self.group_depth.saturating_sub(1);
+        if self.group_depth > 0 {
+            return;
+        }
+        if let Some(step) = self.pending.take() {
+            if !step.edits.is_empty() {
+                self.undo_stack.push(step);
+            }
+        }
+    }
+    pub fn record(&mut self, edit: Edit) {
+        self.redo_stack.clear();
+        if let Some(step) = self.pending.as_mut() {
+            step.edits.push(edit);
+        } else {
+            // Edits outside of any group are undone one by one.
+            let cursor = edit.start();
+            let id = self.new_id();
+            self.undo_stack.push(UndoStep {
+                edits: vec![edit],
+                cursor,
+                id,
+            });
+        }
+    }
+    fn new_id(&mut self) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+    fn top_id(&self) -> usize {
+        self.undo_stack.last().map_or(0, |step| step.id)
+    }
+    fn has_pending_edits(&self) -> bool {
+        self.pending
+            .as_ref()
+            .is_some_and(|step| !step.edits.is_empty())
+    }
+    // Remembers the current state as the one written to the file.
+    pub fn mark_saved(&mut self) {
+        // a step still open may get more edits, which are not in the file
+        self.saved_step = if self.has_pending_edits() {
+            None
+        } else {
+            Some(self.top_id())
+        };
+    }
+    // Forgets the saved state, such as when the contents are replaced without history.
+    pub fn clear_saved_mark(&mut self) {
+        self.saved_step = None;
+    }
+    // Returns whether undo and redo have brought the buffer back to the saved state.
+    pub fn is_at_saved(&self) -> bool {
+        !self.has_pending_edits() && self.saved_step == Some(self.top_id())
+    }
+    pub fn pop_undo(&mut self) -> Option<UndoStep> {
+        self.undo_stack.pop()
+    }
+    pub fn push_undo(&mut self, step: UndoStep) {
+        self.undo_stack.push(step);
+    }
+    pub fn pop_redo(&mut self) -> Option<UndoStep> {
+        self.redo_stack.pop()
+    }
+    pub fn push_redo(&mut self, step: UndoStep) {
+        self.redo_stack.push(step);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Buffer;
+    use super::*;
+
+    fn loc(line_idx: usize, grapheme_idx: usize) -> TextLocation {
+        TextLocation {
+            grapheme_idx,
+            line_idx,
+        }
+    }
+
+    fn text(buffer: &Buffer) -> String {
+        buffer.chunks().collect()
+    }
+
+    #[test]
+    fn insert_session_is_one_undo_step() {
+        let mut buffer = Buffer::default();
+        buffer.insert_text(loc(0, 0), "abc");
+        buffer.begin_undo_group(loc(0, 3));
+        buffer.insert_char('d', loc(0, 3));
+        buffer.insert_char('e', loc(0, 4));
+        buffer.insert_newline(loc(0, 5));
+        buffer.insert_char('f', loc(1, 0));
+        buffer.end_undo_group();
+        assert_eq!(text(&buffer), "abcde\nf");
+
+        assert_eq!(buffer.undo(), Some(loc(0, 3)));
+        assert_eq!(text(&buffer), "abc");
+        assert_eq!(buffer.undo(), Some(loc(0, 0)));
+        assert_eq!(text(&buffer), "");
+        assert_eq!(buffer.undo(), None);
+    }
+
+    #[test]
+    fn nested_groups_are_one_undo_step() {
+        let mut buffer = Buffer::default();
+        buffer.begin_undo_group(loc(0, 0));
+        buffer.insert_text(loc(0, 0), "ab");
+        buffer.begin_undo_group(loc(0, 2));
+        buffer.insert_text(loc(0, 2), "cd");
+        buffer.end_undo_group();
+        buffer.insert_text(loc(0, 4), "ef");
+        buffer.end_undo_group();
+
+        assert_eq!(buffer.undo(), Some(loc(0, 0)));
+        assert_eq!(text(&buffer), "");
+        assert_eq!(buffer.undo(), None);
+    }
+
+    #[test]
+    fn new_edit_clears_redo() {
+        let mut buffer = Buffer::default();
+        buffer.insert_text(loc(0, 0), "abc");
+        buffer.insert_text(loc(0, 3), "def");
+        buffer.undo();
+        buffer.insert_text(loc(0, 3), "xyz");
+
+        assert_eq!(buffer.redo(), None);
+        assert_eq!(text(&buffer), "abcxyz");
+        buffer.undo();
+        assert_eq!(text(&buffer), "abc");
+    }
+
+    #[test]
+    fn undo_and_redo_restore_text_and_cursor() {
+        let mut buffer = Buffer::default();
+        buffer.insert_text(loc(0, 0), "one\ntwo\nthree");
+        buffer.begin_undo_group(loc(1, 1));
+        let deleted = buffer.delete_range(loc(0, 2), loc(2, 1));
+        buffer.end_undo_group();
+        assert_eq!(deleted, "e\ntwo\nt");
+        assert_eq!(text(&buffer), "onhree");
+
+        assert_eq!(buffer.undo(), Some(loc(1, 1)));
+        assert_eq!(text(&buffer), "one\ntwo\nthree");
+        assert_eq!(buffer.redo(), Some(loc(0, 2)));
+        assert_eq!(text(&buffer), "onhree");
+        assert_eq!(buffer.redo(), None);
+    }
+
+    #[test]
+    fn undo_to_saved_state_clears_modified() {
+        let mut buffer = Buffer::default();
+        buffer.insert_text(loc(0, 0), "abc");
+        buffer.history.mark_saved();
+        buffer.modified = false;
+        buffer.insert_text(loc(0, 3), "def");
+        assert!(buffer.modified);
+
+        buffer.undo();
+        assert!(!buffer.modified);
+        buffer.undo();
+        assert!(buffer.modified);
+        buffer.redo();
+        assert!(!buffer.modified);
+        buffer.redo();
+        assert!(buffer.modified);
+    }
+
+    #[test]
+    fn saved_state_is_lost_with_discarded_redo() {
+        let mut buffer = Buffer::default();
+        buffer.insert_text(loc(0, 0), "abc");
+        buffer.history.mark_saved();
+        buffer.modified = false;
+        buffer.undo();
+        buffer.insert_text(loc(0, 0), "abc");
+
+        assert!(buffer.modified);
+        buffer.undo();
+        buffer.redo();
+        assert!(buffer.modified);
+    }
+}
@@ -1,128 +1,170 @@
+use std::cell::OnceCell;
+
 use unicode_segmentation::UnicodeSegmentation;
 
 use crate::editor::highlighter::LineHighlighter;
+use crate::editor::search_pattern::SearchPattern;
 
 use super::super::annotated_string::AnnotatedString;
-use super::grapheme::{str_to_graphemes, Grapheme};
+use super::grapheme::{str_to_graphemes, Grapheme, GraphemeInfo};
+
+struct Fragments {
+    graphemes: Vec<GraphemeInfo>,
+    to_str_idx: Vec<usize>, // grapheme index to corresponding string index
+}
 
 #[derive(Default)]
 pub struct Line {
-    graphemes: Vec<Grapheme>,
     raw_string: String,
-    to_str_idx: Vec<usize>, // grapheme index to corresponding string index
+    // built on first use, since most lines are only searched or highlighted
+    fragments: OnceCell<Fragments>,
 }
 
 impl Line {
+    fn fragments(&self) -> &Fragments {
+        self.fragments.get_or_init(|| {
+            let (graphemes, to_str_idx) = str_to_graphemes(&self.raw_string);
+            Fragments {
+                graphemes,
+                to_str_idx,
+            }
+        })
+    }
+    fn graphemes(&self) -> &[GraphemeInfo] {
+        &self.fragments().graphemes
+    }
     fn rebuild_fragments(&mut self) {
-        let (graphemes, to_str_idx) = str_to_graphemes(&self.raw_string);
-        self.graphemes = graphemes;
-        self.to_str_idx = to_str_idx;
+        self.fragments = OnceCell::new();
     }
     pub fn from_str(s: &str) -> Self {
-        let (graphemes, to_str_idx) = str_to_graphemes(s);
         Self {
-            graphemes,
             raw_string: String::from(s),
-            to_str_idx,
+            fragments: OnceCell::new(),
         }
     }
     pub fn get_raw_str(&self) -> &str {
         &self.raw_string
     }
     pub fn get_nth_grapheme(&self, index: usize) -> Option<Grapheme> {
-        self.graphemes.get(index).cloned()
+        if index >= self.len() {
+            return None;
+        }
+        Some(Grapheme::from_str(self.get_substr(index, index + 1)))
+    }
+    // Returns substring of graphemes in range [start, end).
+    pub fn get_substr(&self, start: usize, end: usize) -> &str {
+        let start = self.to_byte_idx(start);
+        let end = self.to_byte_idx(end);
+        if start >= end {
+            return "";
+        }
+        &self.raw_string[start..end]
+    }
+    // Returns grapheme range [start, end) which overlaps with
+    // terminal column range [left, right).
+    pub fn graphemes_in_columns(&self, left: usize, right: usize) -> (usize, usize) {
+        let mut start = self.len();
+        let mut end = self.len();
+        let mut current_pos = 0;
+        for (i, grapheme) in self.graphemes().iter().enumerate() {
+            let next_pos = current_pos + grapheme.get_width_at_current_pos(current_pos);
+            if next_pos > left && start == self.len() {
+                start = i;
+            }
+            if current_pos >= right {
+                end = i;
+                break;
+            }
+            current_pos = next_pos;
+        }
+        (start, usize::max(start, end))
     }
     pub fn calc_width_until_grapheme_index(&self, graphme_index: usize) -> usize {
         let mut current_pos = 0;
-        for grapheme in self.graphemes.iter().take(graphme_index) {
+        for grapheme in self.graphemes().iter().take(graphme_index) {
             current_pos += grapheme.get_width_at_current_pos(current_pos);
         }
         current_pos
     }
     pub fn len(&self) -> usize {
-        self.graphemes.len()
-    }
-    pub fn byte_len(&self) -> usize {
-        self.raw_string.len()
+        self.graphemes().len()
     }
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
-    pub fn insert_char(&mut self, c: char, idx: usize) {
-        if let Some(str_idx) = self.to_str_idx.get(idx) {
-            self.raw_string.insert(*str_idx, c);
-        } else {
-            self.raw_string.push(c);
-        }
+    // Inserts `s` before the grapheme at `idx`, or at the end if `idx` is out of bound.
+    pub fn insert_str(&mut self, s: &str, idx: usize) {
+        let str_idx = self.to_byte_idx(idx);
+        self.raw_string.insert_str(str_idx, s);
         self.rebuild_fragments();
     }
-    pub fn delete_grapheme(&mut self, idx: usize) {
-        if let Some(grapheme) = self.graphemes.get(idx) {
-            let start = self.to_str_idx[idx];
-            let end = start + grapheme.to_string().len();
+    // Deletes graphemes in range [start, end).
+    pub fn delete_range(&mut self, start: usize, end: usize) {
+        let start = self.to_byte_idx(start);
+        let end = self.to_byte_idx(end);
+        if start < end {
             self.raw_string.drain(start..end);
             self.rebuild_fragments();
         }
     }
-    pub fn push_line(&mut self, other: &Self) {
-        self.raw_string.push_str(&other.raw_string);
-        self.rebuild_fragments();
-    }
-    pub fn split_off(&mut self, at: usize) -> Self {
-        assert!(at <= self.graphemes.len());
-        let str_idx = self
+    pub fn to_byte_idx(&self, grapheme_idx: usize) -> usize {
+        self.fragments()
             .to_str_idx
-            .get(at)
-            .cloned()
-            .unwrap_or(self.raw_string.len()); // just past the end
-        let remainder = self.raw_string.split_off(str_idx);
-        self.rebuild_fragments();
-        Self::from_str(&remainder)
-    }
-    fn to_byte_idx(&self, grapheme_idx: usize) -> usize {
-        self.to_str_idx
             .get(grapheme_idx)
             .cloned()
             .unwrap_or(self.raw_string.len())
     }
     pub fn to_grapheme_idx(&self, str_idx: usize) -> usize {
-        for (grapheme_idx, cur_str_idx) in self.to_str_idx.iter().enumerate() {
+        for (grapheme_idx, cur_str_idx) in self.fragments().to_str_idx.iter().enumerate() {
             if *cur_str_idx >= str_idx {
                 return grapheme_idx;
             }
         }
-        panic!("Error: str index is out of bound");
+        assert!(
+            str_idx <= self.raw_string.len(),
+            "str index is out of bound"
+        );
+        self.len()
     }
-    pub fn search_all_occurence(&self, pattern: &str) -> Vec<(usize, usize)> {
+    pub fn search_all_occurence(&self, pattern: &SearchPattern) -> Vec<(usize, usize)> {
         // Returns: vector of (start, end)
         // start, end indices are in byte indices
-        let mut result = vec![];
-        if pattern.is_empty() {
-            return result;
-        }
-        let mut start_index = 0;
-        while let Some(start) = self.search(pattern, start_index) {
-            let end = start + pattern.len();
-            result.push((start, end));
-            start_index = self.to_grapheme_idx(start) + 1;
-            if start_index >= self.len() {
-                break;
+        pattern.find_all(&self.raw_string)
+    }
+
+    // Returns grapheme ranges [start, end) of words in the line.
+    // A word is a sequence of letters, digits and underscores, or a sequence
+    // of other non-blank characters. If `big_word` is true, a word is
+    // any sequence of non-blank characters.
+    pub fn word_spans(&self, big_word: bool) -> Vec<(usize, usize)> {
+        let mut spans: Vec<(usize, usize)> = vec![];
+        let mut prev_is_keyword = None;
+        for (idx, word) in self.split_word_bound_indices() {
+            if word.trim().is_empty() {
+                prev_is_keyword = None;
+                continue;
             }
+            let start = self.to_grapheme_idx(idx);
+            let end = self.to_grapheme_idx(idx + word.len());
+            let is_keyword = word.chars().any(|c| c.is_alphanumeric() || c == '_');
+            match spans.last_mut() {
+                // join adjacent words of the same class
+                Some(last) if prev_is_keyword.is_some_and(|k| big_word || k == is_keyword) => {
+                    last.1 = end;
+                }
+                _ => spans.push((start, end)),
+            }
+            prev_is_keyword = Some(is_keyword);
         }
-        result
+        spans
     }
-    // search pattern in a line after start_idx (in graphemes) and
-    // returns first index in bytes.
-    fn search(&self, pattern: &str, start_idx: usize) -> Option<usize> {
-        if self.is_empty() {
-            return None;
-        }
-        let byte_index = self.to_str_idx[start_idx];
-        self.raw_string[byte_index..]
-            .find(pattern)
-            .map(|str_idx| byte_index + str_idx)
+    // Returns grapheme index of first non-blank character.
+    pub fn first_non_blank(&self) -> usize {
+        self.raw_string
+            .graphemes(true)
+            .position(|g| !g.trim().is_empty())
+            .unwrap_or(0)
     }
-
     pub fn split_word_bound_indices(&self) -> unicode_segmentation::UWordBoundIndices<'_> {
         self.raw_string.split_word_bound_indices()
     }
@@ -150,14 +192,14 @@ impl<'a> LineView<'a> {
         let mut left_grapheme_idx = usize::MAX;
         let mut right_grapheme_idx = usize::MAX;
 
-        for (i, grapheme) in line.graphemes.iter().enumerate() {
+        for (i, grapheme) in line.graphemes().iter().enumerate() {
             let next_pos = current_pos + grapheme.get_width_at_current_pos(current_pos);
             // Current character is out of visible range
             if next_pos <= left || current_pos >= right {
                 current_pos = next_pos;
                 continue;
             }
-            let right_end_visible = left <= next_pos - 1 && next_pos - 1 < right;
+            let right_end_visible = left < next_pos && next_pos - 1 < right;
             let left_end_visible = left <= current_pos && current_pos < right;
             assert!(right_end_visible || left_end_visible);
             if !left_end_visible {
@@ -186,11 +228,35 @@ impl<'a> LineView<'a> {
         }
         let start = self.line.to_byte_idx(self.visible_range.0);
         let end = self.line.to_byte_idx(self.visible_range.1);
-        let visible_content = content.substr(start, end);
+        let visible_content = if self.line.get_raw_str().contains('\t') {
+            self.expand_tabs(&content)
+        } else {
+            content.substr(start, end)
+        };
         let mut result = AnnotatedString::default();
         result.push_annot_str(&AnnotatedString::from_str(&self.padding_left));
         result.push_annot_str(&visible_content);
         result.push_annot_str(&AnnotatedString::from_str(&self.padding_right));
         result
     }
+    // Returns visible part of `content` with tabs replaced by spaces,
+    // so that tab stops are relative to the left edge of the window.
+    fn expand_tabs(&self, content: &AnnotatedString) -> AnnotatedString {
+        let mut result = AnnotatedString::default();
+        let mut current_pos = 0;
+        for (i, grapheme) in self.line.graphemes().iter().enumerate() {
+            let width = grapheme.get_width_at_current_pos(current_pos);
+            current_pos += width;
+            if i < self.visible_range.0 || self.visible_range.1 <= i {
+                continue;
+            }
+            let piece = content.substr(self.line.to_byte_idx(i), self.line.to_byte_idx(i + 1));
+            if grapheme.is_tab() {
+                result.push_annot_str(&piece.replace_str(&" ".repeat(width)));
+            } else {
+                result.push_annot_str(&piece);
+            }
+        }
+        result
+    }
 }
@@ -0,0 +1,149 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::buffer::Buffer;
+use super::cmdline_commands::BufferSpecifier;
+use super::swap_file::SwapFile;
+use super::terminal::Position;
+use super::window::TextLocation;
+
+// A buffer opened in the editor.
+pub struct BufferEntry {
+    // number shown by `:ls` and given to `:b N`
+    pub number: usize,
+    pub buffer: Rc<RefCell<Buffer>>,
+    // cursor and scroll offset when the buffer was shown last time
+    pub cursor_location: TextLocation,
+    pub scroll_offset: Position,
+    // swap file written while the buffer is modified
+    pub swap_file: Option<SwapFile>,
+}
+
+// Buffers opened in the editor.
+// There is always at least one buffer, which is the current one.
+pub struct BufferList {
+    entries: Vec<BufferEntry>,
+    current: usize,
+    next_number: usize,
+}
+
+impl Default for BufferList {
+    fn default() -> Self {
+        let mut list = Self {
+            entries: vec![],
+            current: 0,
+            next_number: 1,
+        };
+        list.add(Buffer::default());
+        list
+    }
+}
+
+impl BufferList {
+    pub fn current(&self) -> &BufferEntry {
+        &self.entries[self.current]
+    }
+    pub fn current_mut(&mut self) -> &mut BufferEntry {
+        &mut self.entries[self.current]
+    }
+    pub fn current_index(&self) -> usize {
+        self.current
+    }
+    pub fn set_current(&mut self, idx: usize) {
+        self.current = idx;
+    }
+    pub fn get(&self, idx: usize) -> &BufferEntry {
+        &self.entries[idx]
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &BufferEntry> {
+        self.entries.iter()
+    }
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut BufferEntry> {
+        self.entries.iter_mut()
+    }
+    // Appends a buffer and returns its index.
+    pub fn add(&mut self, buffer: Buffer) -> usize {
+        self.entries.push(BufferEntry {
+            number: self.next_number,
+            buffer: Rc::new(RefCell::new(buffer)),
+            cursor_location: TextLocation::default(),
+            scroll_offset: Position::default(),
+            swap_file: None,
+        });
+        self.next_number += 1;
+        self.entries.len() - 1
+    }
+    // Removes a buffer. The last buffer is replaced with an empty one.
+    // The current buffer should be changed afterward if it is removed.
+    pub fn remove(&mut self, idx: usize) {
+        if let Some(swap_file) = self.entries.remove(idx).swap_file {
+            swap_file.remove();
+        }
+        if self.entries.is_empty() {
+            self.add(Buffer::default());
+        }
+        if idx < self.current || self.current >= self.entries.len() {
+            self.current = self.current.saturating_sub(1);
+        }
+    }
+    pub fn find_by_number(&self, number: usize) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.number == number)
+    }
+    // Finds the buffer by its number, or the only one whose name contains the given name.
+    pub fn find(&self, specifier: &BufferSpecifier) -> Result<usize, String> {
+        let name = match specifier {
+            BufferSpecifier::Number(number) => {
+                return self
+                    .find_by_number(*number)
+                    .ok_or(format!("Buffer {} does not exist", number))
+            }
+            BufferSpecifier::Name(name) => name,
+        };
+        if let Some(idx) = self.find_by_filename(name) {
+            return Ok(idx);
+        }
+        let mut matches = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| {
+                entry
+                    .buffer
+                    .borrow()
+                    .get_filename()
+                    .is_some_and(|filename| filename.contains(name.as_str()))
+            })
+            .map(|(idx, _)| idx);
+        match (matches.next(), matches.next()) {
+            (Some(idx), None) => Ok(idx),
+            (Some(_), Some(_)) => Err(format!("More than one match for {}", name)),
+            (None, _) => Err(format!("No matching buffer for {}", name)),
+        }
+    }
+    // Returns names of buffers having a file name.
+    pub fn names(&self) -> Vec<String> {
+        self.entries
+            .iter()
+            .filter_map(|entry| entry.buffer.borrow().get_filename())
+            .collect()
+    }
+    pub fn find_by_filename(&self, filename: &str) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|entry| entry.buffer.borrow().get_filename().as_deref() == Some(filename))
+    }
+    pub fn find_by_buffer(&self, buffer: &Rc<RefCell<Buffer>>) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|entry| Rc::ptr_eq(&entry.buffer, buffer))
+    }
+    // Returns index of the buffer `count` buffers after the current one,
+    // wrapping around at the end of the list.
+    pub fn next_index(&self, count: usize) -> usize {
+        (self.current + count) % self.entries.len()
+    }
+    pub fn prev_index(&self, count: usize) -> usize {
+        let len = self.entries.len();
+        (self.current + len - count % len) % len
+    }
+}
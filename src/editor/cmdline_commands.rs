@@ -1,15 +1,84 @@
+use super::editor_command::SplitDirection;
+
+// Full names of commands, which are completed with Tab.
+pub const COMMAND_NAMES: [&str; 18] = [
+    "bdelete",
+    "bnext",
+    "bprevious",
+    "buffer",
+    "buffers",
+    "close",
+    "colorscheme",
+    "edit",
+    "ls",
+    "noh",
+    "only",
+    "q",
+    "saveas",
+    "set",
+    "split",
+    "substitute",
+    "vsplit",
+    "w",
+];
+
+// Buffer given to `:b` and `:bd` by its number or a part of its name.
+pub enum BufferSpecifier {
+    Number(usize),
+    Name(String),
+}
+
 pub enum CmdlineCommands {
     Quit,
-    Write,
+    // `:w!` writes even if the file has been changed by other programs
+    Write {
+        force: bool,
+    },
     Saveas(String),
     StopHighlighting,
+    Set(Vec<String>),
+    Substitute(Option<LineRange>, Substitution),
+    // `:e[!] [file]`: without file, reload the current buffer
+    Edit {
+        filename: Option<String>,
+        force: bool,
+    },
+    BufferNext,
+    BufferPrev,
+    // `:b {N|name}`: switch to buffer with number N or the name
+    Buffer(BufferSpecifier),
+    ListBuffers,
+    // `:bd[!] [N|name]`: close the buffer or the current one
+    BufferDelete {
+        buffer: Option<BufferSpecifier>,
+        force: bool,
+    },
+    // `:sp [file]` and `:vs [file]`
+    Split(SplitDirection, Option<String>),
+    Close,
+    Only,
+    // `:colo [name]`: without name, show the current colorscheme
+    Colorscheme(Option<String>),
 }
 
 impl CmdlineCommands {
-    pub fn parse_cmdline(cmdline: &Vec<String>) -> Result<Self, String> {
-        match cmdline[0].as_str() {
+    pub fn parse_cmdline(cmdline: &str) -> Result<Self, String> {
+        let (range, rest) = LineRange::parse(cmdline.trim_start())?;
+        if let Some(args) = strip_substitute(rest) {
+            return Ok(Self::Substitute(range, Substitution::parse(args)?));
+        }
+        if range.is_some() {
+            return Err("No range allowed".to_string());
+        }
+        let cmdline: Vec<String> = rest.split_whitespace().map(String::from).collect();
+        let Some(name) = cmdline.first() else {
+            return Err("No command provided".to_string());
+        };
+        match name.as_str() {
             "q" => Ok(Self::Quit),
-            "w" => Ok(Self::Write),
+            "w" | "w!" => Ok(Self::Write {
+                force: name.ends_with('!'),
+            }),
             "saveas" => {
                 if cmdline.len() >= 2 {
                     Ok(Self::Saveas(cmdline[1].clone()))
@@ -19,7 +88,434 @@ impl CmdlineCommands {
             }
             // NO Highlight search
             "noh" => Ok(Self::StopHighlighting),
-            _ => Err(format!("No such command: {}", cmdline[0])),
+            "set" => {
+                if cmdline.len() >= 2 {
+                    Ok(Self::Set(cmdline[1..].to_vec()))
+                } else {
+                    Err("No option provided for `set` command.".to_string())
+                }
+            }
+            "e" | "edit" | "e!" | "edit!" => Ok(Self::Edit {
+                filename: cmdline.get(1).cloned(),
+                force: name.ends_with('!'),
+            }),
+            "bn" | "bnext" => Ok(Self::BufferNext),
+            "bp" | "bprevious" => Ok(Self::BufferPrev),
+            "b" | "buffer" => match cmdline.get(1) {
+                Some(arg) => Ok(Self::Buffer(parse_buffer_specifier(arg))),
+                None => Err("No buffer provided for `b` command.".to_string()),
+            },
+            "ls" | "buffers" => Ok(Self::ListBuffers),
+            "bd" | "bdelete" | "bd!" | "bdelete!" => Ok(Self::BufferDelete {
+                buffer: cmdline.get(1).map(|arg| parse_buffer_specifier(arg)),
+                force: name.ends_with('!'),
+            }),
+            "sp" | "split" => Ok(Self::Split(
+                SplitDirection::Horizontal,
+                cmdline.get(1).cloned(),
+            )),
+            "vs" | "vsplit" => Ok(Self::Split(
+                SplitDirection::Vertical,
+                cmdline.get(1).cloned(),
+            )),
+            "clo" | "close" => Ok(Self::Close),
+            "on" | "only" => Ok(Self::Only),
+            "colo" | "colorscheme" => Ok(Self::Colorscheme(cmdline.get(1).cloned())),
+            _ => match name.strip_prefix('b').map(parse_buffer_number) {
+                // `:bN` is the same as `:b N`
+                Some(Ok(number)) => Ok(Self::Buffer(BufferSpecifier::Number(number))),
+                _ => Err(format!("No such command: {}", name)),
+            },
+        }
+    }
+}
+
+fn parse_buffer_number(arg: &str) -> Result<usize, String> {
+    arg.parse()
+        .map_err(|_| format!("Invalid buffer number: {}", arg))
+}
+
+fn parse_buffer_specifier(arg: &str) -> BufferSpecifier {
+    match arg.parse() {
+        Ok(number) => BufferSpecifier::Number(number),
+        Err(_) => BufferSpecifier::Name(String::from(arg)),
+    }
+}
+
+// Returns arguments of `:s` or `:substitute` starting with the delimiter.
+fn strip_substitute(cmdline: &str) -> Option<&str> {
+    let args = cmdline
+        .strip_prefix("substitute")
+        .or_else(|| cmdline.strip_prefix('s'))?;
+    let delimiter = args.chars().next()?;
+    if delimiter.is_alphanumeric() || delimiter.is_whitespace() || delimiter == '\\' {
+        // other commands such as `:set`
+        return None;
+    }
+    Some(args)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineSpecifier {
+    // 1-based line number
+    Number(usize),
+    // `.`
+    Current,
+    // `$`
+    Last,
+    // `'<`
+    VisualStart,
+    // `'>`
+    VisualEnd,
+}
+
+// Line number such as `.+3` or `$-1`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LineAddress {
+    base: LineSpecifier,
+    offset: isize,
+}
+
+impl LineAddress {
+    // Parses an address at the beginning of `s`, and returns it with the rest.
+    fn parse(s: &str) -> Result<(Option<Self>, &str), String> {
+        let digits = s.chars().take_while(char::is_ascii_digit).count();
+        let (base, mut rest) = if digits > 0 {
+            let number = s[..digits]
+                .parse()
+                .map_err(|_| format!("Invalid range: {}", s))?;
+            (Some(LineSpecifier::Number(number)), &s[digits..])
+        } else if let Some(rest) = s.strip_prefix('.') {
+            (Some(LineSpecifier::Current), rest)
+        } else if let Some(rest) = s.strip_prefix('$') {
+            (Some(LineSpecifier::Last), rest)
+        } else if let Some(rest) = s.strip_prefix("'<") {
+            (Some(LineSpecifier::VisualStart), rest)
+        } else if let Some(rest) = s.strip_prefix("'>") {
+            (Some(LineSpecifier::VisualEnd), rest)
+        } else if s.starts_with('\'') {
+            return Err(format!("Invalid range: {}", s));
+        } else {
+            (None, s)
+        };
+        let mut offset = None;
+        while let Some(sign) = rest.chars().next().filter(|c| *c == '+' || *c == '-') {
+            rest = &rest[1..];
+            let digits = rest.chars().take_while(char::is_ascii_digit).count();
+            // `+` without number means `+1`
+            let value: isize = if digits > 0 {
+                rest[..digits]
+                    .parse()
+                    .map_err(|_| format!("Invalid range: {}", s))?
+            } else {
+                1
+            };
+            rest = &rest[digits..];
+            let value = if sign == '+' { value } else { -value };
+            offset = Some(offset.unwrap_or(0) + value);
         }
+        let address = match (base, offset) {
+            (None, None) => None,
+            // offset without base is relative to the current line
+            (base, offset) => Some(Self {
+                base: base.unwrap_or(LineSpecifier::Current),
+                offset: offset.unwrap_or(0),
+            }),
+        };
+        Ok((address, rest))
+    }
+    // Returns 0-based line index.
+    fn resolve(
+        &self,
+        current_line_idx: usize,
+        n_lines: usize,
+        visual_lines: Option<(usize, usize)>,
+    ) -> Result<usize, String> {
+        let base = match self.base {
+            // line 0 is treated as the first line
+            LineSpecifier::Number(number) => number.saturating_sub(1),
+            LineSpecifier::Current => current_line_idx,
+            LineSpecifier::Last => n_lines.saturating_sub(1),
+            LineSpecifier::VisualStart => visual_lines.ok_or("Mark not set")?.0,
+            LineSpecifier::VisualEnd => visual_lines.ok_or("Mark not set")?.1,
+        };
+        match base.checked_add_signed(self.offset) {
+            Some(line_idx) if line_idx < usize::max(n_lines, 1) => Ok(line_idx),
+            _ => Err("Invalid range".to_string()),
+        }
+    }
+}
+
+// Range of lines given before a command, such as `%` and `1,$`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineRange {
+    WholeFile,
+    Lines(LineAddress, LineAddress),
+}
+
+impl LineRange {
+    // Parses a range at the beginning of `cmdline`, and returns it with the rest.
+    fn parse(cmdline: &str) -> Result<(Option<Self>, &str), String> {
+        if let Some(rest) = cmdline.strip_prefix('%') {
+            return Ok((Some(Self::WholeFile), rest));
+        }
+        let (start, rest) = LineAddress::parse(cmdline)?;
+        let Some(start) = start else {
+            return Ok((None, rest));
+        };
+        match rest.strip_prefix(',') {
+            Some(rest) => {
+                let (end, rest) = LineAddress::parse(rest)?;
+                // `1,` means `1,.`
+                let end = end.unwrap_or(LineAddress {
+                    base: LineSpecifier::Current,
+                    offset: 0,
+                });
+                Ok((Some(Self::Lines(start, end)), rest))
+            }
+            None => Ok((Some(Self::Lines(start, start)), rest)),
+        }
+    }
+    // Returns the first and last line indices of the range.
+    // `visual_lines` are the lines of the last visual selection.
+    pub fn resolve(
+        &self,
+        current_line_idx: usize,
+        n_lines: usize,
+        visual_lines: Option<(usize, usize)>,
+    ) -> Result<(usize, usize), String> {
+        match self {
+            Self::WholeFile => Ok((0, n_lines.saturating_sub(1))),
+            Self::Lines(start, end) => {
+                let start = start.resolve(current_line_idx, n_lines, visual_lines)?;
+                let end = end.resolve(current_line_idx, n_lines, visual_lines)?;
+                // backwards range is swapped
+                Ok((usize::min(start, end), usize::max(start, end)))
+            }
+        }
+    }
+}
+
+// Arguments of `:s/{pattern}/{replacement}/[flags]`.
+pub struct Substitution {
+    pub pattern: String,
+    pub replacement: String,
+    // `g`: replace all matches in a line
+    pub global: bool,
+    // `c`: confirm each substitution
+    pub confirm: bool,
+    // `i` and `I`: override case sensitivity of the pattern
+    pub ignore_case: Option<bool>,
+}
+
+impl Substitution {
+    // Parses `/{pattern}/{replacement}/[flags]`, where `/` can be any delimiter.
+    fn parse(args: &str) -> Result<Self, String> {
+        let mut chars = args.chars();
+        let delimiter = chars.next().unwrap_or('/');
+        let mut fields = vec![];
+        let mut field = String::new();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    // escaped delimiter is a literal
+                    Some(c) if c == delimiter => field.push(c),
+                    Some(c) => {
+                        field.push('\\');
+                        field.push(c);
+                    }
+                    None => field.push('\\'),
+                },
+                c if c == delimiter && fields.len() < 2 => fields.push(std::mem::take(&mut field)),
+                c => field.push(c),
+            }
+        }
+        fields.push(field);
+        let mut fields = fields.into_iter();
+        let pattern = fields.next().unwrap_or_default();
+        let replacement = fields.next().unwrap_or_default();
+        let flags = fields.next().unwrap_or_default();
+        let mut substitution = Self {
+            pattern,
+            replacement,
+            global: false,
+            confirm: false,
+            ignore_case: None,
+        };
+        for flag in flags.trim_end().chars() {
+            match flag {
+                'g' => substitution.global = true,
+                'c' => substitution.confirm = true,
+                'i' => substitution.ignore_case = Some(true),
+                'I' => substitution.ignore_case = Some(false),
+                _ => return Err(format!("Trailing characters: {}", flags)),
+            }
+        }
+        Ok(substitution)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(base: LineSpecifier, offset: isize) -> LineAddress {
+        LineAddress { base, offset }
+    }
+
+    fn resolve(
+        range: &str,
+        current_line_idx: usize,
+        n_lines: usize,
+    ) -> Result<(usize, usize), String> {
+        let (range, _) = LineRange::parse(range)?;
+        range
+            .expect("range should be given")
+            .resolve(current_line_idx, n_lines, Some((3, 5)))
+    }
+
+    #[test]
+    fn parse_line_address() {
+        let parse = |s| LineAddress::parse(s).unwrap();
+        assert_eq!(
+            parse("12s"),
+            (Some(address(LineSpecifier::Number(12), 0)), "s")
+        );
+        assert_eq!(parse(".+3"), (Some(address(LineSpecifier::Current, 3)), ""));
+        assert_eq!(parse("$-1"), (Some(address(LineSpecifier::Last, -1)), ""));
+        assert_eq!(
+            parse("'<,'>"),
+            (Some(address(LineSpecifier::VisualStart, 0)), ",'>")
+        );
+        assert_eq!(parse("s/a/b/"), (None, "s/a/b/"));
+    }
+
+    #[test]
+    fn parse_line_address_offsets() {
+        let parse = |s| LineAddress::parse(s).unwrap();
+        // offset without base is relative to the current line
+        assert_eq!(parse("+3"), (Some(address(LineSpecifier::Current, 3)), ""));
+        assert_eq!(parse("-"), (Some(address(LineSpecifier::Current, -1)), ""));
+        // offsets are summed up
+        assert_eq!(
+            parse("5+2-1++"),
+            (Some(address(LineSpecifier::Number(5), 3)), "")
+        );
+        assert!(LineAddress::parse("'a").is_err());
+    }
+
+    #[test]
+    fn parse_line_range() {
+        let current = address(LineSpecifier::Current, 0);
+        let parse = |s| LineRange::parse(s).unwrap();
+        assert_eq!(parse("%s"), (Some(LineRange::WholeFile), "s"));
+        assert_eq!(
+            parse("1,$s"),
+            (
+                Some(LineRange::Lines(
+                    address(LineSpecifier::Number(1), 0),
+                    address(LineSpecifier::Last, 0)
+                )),
+                "s"
+            )
+        );
+        // `1,` means `1,.`
+        assert_eq!(
+            parse("1,s"),
+            (
+                Some(LineRange::Lines(
+                    address(LineSpecifier::Number(1), 0),
+                    current
+                )),
+                "s"
+            )
+        );
+        assert_eq!(parse(".s"), (Some(LineRange::Lines(current, current)), "s"));
+        assert_eq!(parse("s"), (None, "s"));
+    }
+
+    #[test]
+    fn resolve_line_range() {
+        assert_eq!(resolve("%", 2, 10), Ok((0, 9)));
+        assert_eq!(resolve("2,$", 0, 10), Ok((1, 9)));
+        assert_eq!(resolve("+3", 2, 10), Ok((5, 5)));
+        assert_eq!(resolve(".,.+2", 2, 10), Ok((2, 4)));
+        assert_eq!(resolve("'<,'>", 0, 10), Ok((3, 5)));
+        // line 0 is the first line
+        assert_eq!(resolve("0", 2, 10), Ok((0, 0)));
+    }
+
+    #[test]
+    fn resolve_backwards_range() {
+        assert_eq!(resolve("5,2", 0, 10), Ok((1, 4)));
+        assert_eq!(resolve(".,1", 3, 10), Ok((0, 3)));
+    }
+
+    #[test]
+    fn resolve_invalid_range() {
+        assert!(resolve("11", 0, 10).is_err());
+        assert!(resolve("$+1", 0, 10).is_err());
+        assert!(resolve("-3", 1, 10).is_err());
+        let (range, _) = LineRange::parse("'<").unwrap();
+        assert!(range.unwrap().resolve(0, 10, None).is_err());
+    }
+
+    #[test]
+    fn parse_substitution() {
+        let s = Substitution::parse("/foo/bar/").unwrap();
+        assert_eq!((s.pattern.as_str(), s.replacement.as_str()), ("foo", "bar"));
+        assert!(!s.global && !s.confirm && s.ignore_case.is_none());
+
+        // trailing delimiter may be omitted
+        let s = Substitution::parse("/foo/bar").unwrap();
+        assert_eq!((s.pattern.as_str(), s.replacement.as_str()), ("foo", "bar"));
+        let s = Substitution::parse("/foo").unwrap();
+        assert_eq!((s.pattern.as_str(), s.replacement.as_str()), ("foo", ""));
+    }
+
+    #[test]
+    fn parse_substitution_flags() {
+        let s = Substitution::parse("/a/b/gc").unwrap();
+        assert!(s.global && s.confirm);
+        assert_eq!(
+            Substitution::parse("/a/b/i").unwrap().ignore_case,
+            Some(true)
+        );
+        assert_eq!(
+            Substitution::parse("/a/b/I").unwrap().ignore_case,
+            Some(false)
+        );
+        assert!(Substitution::parse("/a/b/x").is_err());
+        // a delimiter after the flags is not a flag
+        assert!(Substitution::parse("/a/b/g/").is_err());
+    }
+
+    #[test]
+    fn parse_substitution_delimiters() {
+        let s = Substitution::parse("#a/b#c#g").unwrap();
+        assert_eq!((s.pattern.as_str(), s.replacement.as_str()), ("a/b", "c"));
+        assert!(s.global);
+        // escaped delimiter is a literal, while other escapes are kept
+        let s = Substitution::parse(r"/a\/b/\(c\)\//").unwrap();
+        assert_eq!(s.pattern, "a/b");
+        assert_eq!(s.replacement, r"\(c\)/");
+    }
+
+    #[test]
+    fn parse_cmdline_with_range() {
+        assert!(matches!(
+            CmdlineCommands::parse_cmdline("%s/a/b/g"),
+            Ok(CmdlineCommands::Substitute(Some(LineRange::WholeFile), _))
+        ));
+        assert!(matches!(
+            CmdlineCommands::parse_cmdline("substitute/a/b/"),
+            Ok(CmdlineCommands::Substitute(None, _))
+        ));
+        assert!(CmdlineCommands::parse_cmdline("1,2q").is_err());
+        // `:set` is not `:s` with delimiter `e`
+        assert!(matches!(
+            CmdlineCommands::parse_cmdline("set ic"),
+            Ok(CmdlineCommands::Set(_))
+        ));
     }
 }
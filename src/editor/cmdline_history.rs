@@ -0,0 +1,80 @@
+use std::path::PathBuf;
+
+use super::editor_command::CmdlineSubmode;
+use super::options;
+
+// Number of entries kept for each kind of history.
+const HISTORY_SIZE: usize = 100;
+
+// Commands and search patterns entered in the command line, oldest first.
+// They are written to a file on exit and read in the next session, as lines
+// starting with `:` or `/`.
+#[derive(Default)]
+pub struct CmdlineHistory {
+    commands: Vec<String>,
+    searches: Vec<String>,
+}
+
+impl CmdlineHistory {
+    // Reads the history of the last session. A missing or broken file gives an empty history.
+    pub fn load() -> Self {
+        let mut history = Self::default();
+        let Some(contents) = history_file().and_then(|path| std::fs::read_to_string(path).ok())
+        else {
+            return history;
+        };
+        for line in contents.lines() {
+            if let Some(entry) = line.strip_prefix(':') {
+                history.add(CmdlineSubmode::Cmdline, entry);
+            } else if let Some(entry) = line.strip_prefix('/') {
+                history.add(CmdlineSubmode::Search, entry);
+            }
+        }
+        history
+    }
+    pub fn save(&self) -> Result<(), std::io::Error> {
+        let Some(path) = history_file() else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut contents = String::new();
+        for (prefix, entries) in [(':', &self.commands), ('/', &self.searches)] {
+            for entry in entries {
+                contents.push(prefix);
+                contents.push_str(entry);
+                contents.push('\n');
+            }
+        }
+        std::fs::write(path, contents)
+    }
+    // Appends `entry`, removing the same older entry.
+    pub fn add(&mut self, submode: CmdlineSubmode, entry: &str) {
+        if entry.is_empty() {
+            return;
+        }
+        let entries = self.entries_mut(submode);
+        entries.retain(|e| e != entry);
+        entries.push(String::from(entry));
+        if entries.len() > HISTORY_SIZE {
+            entries.remove(0);
+        }
+    }
+    pub fn entries(&self, submode: CmdlineSubmode) -> &[String] {
+        match submode {
+            CmdlineSubmode::Cmdline => &self.commands,
+            CmdlineSubmode::Search => &self.searches,
+        }
+    }
+    fn entries_mut(&mut self, submode: CmdlineSubmode) -> &mut Vec<String> {
+        match submode {
+            CmdlineSubmode::Cmdline => &mut self.commands,
+            CmdlineSubmode::Search => &mut self.searches,
+        }
+    }
+}
+
+fn history_file() -> Option<PathBuf> {
+    Some(options::state_directory()?.join("history"))
+}
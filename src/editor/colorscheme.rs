@@ -0,0 +1,230 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use crossterm::style::Color;
+
+use super::annotated_string::{DrawingOptions, Style};
+use super::options;
+
+// Colorschemes shipped with the editor.
+const BUILTIN_COLORSCHEMES: [(&str, &str); 2] = [
+    ("default", include_str!("../../colors/default.colorscheme")),
+    ("light", include_str!("../../colors/light.colorscheme")),
+];
+
+// Drawing options of styles, which are read from a colorscheme file such as:
+//
+//     # comment
+//     keyword = fg=#9776d8 bold
+//     search_hit = fg=white bg=#685300
+//     comment = fg=244 italic underline
+//
+// Colors are `#rrggbb`, indices of the 256-color palette, names such as
+// `dark_grey`, or `default` for the terminal's own color.
+// Styles not listed are drawn in the terminal's own colors.
+pub struct Colorscheme {
+    name: String,
+    styles: HashMap<Style, DrawingOptions>,
+}
+
+impl Colorscheme {
+    // Returns an error message with the line number if the colorscheme is invalid.
+    pub fn parse(name: &str, source: &str) -> Result<Self, String> {
+        let mut styles = HashMap::new();
+        for (idx, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (style, options) =
+                parse_entry(line).map_err(|err| format!("line {}: {}", idx + 1, err))?;
+            styles.insert(style, options);
+        }
+        Ok(Self {
+            name: String::from(name),
+            styles,
+        })
+    }
+    // Reads `{name}.colorscheme` in the user's directory, or a built-in colorscheme.
+    pub fn load(name: &str) -> Result<Self, String> {
+        if let Some(dir) = options::config_directory() {
+            let path = dir.join("colors").join(format!("{}.colorscheme", name));
+            if path.is_file() {
+                let source = std::fs::read_to_string(&path)
+                    .map_err(|err| format!("{}: {}", path.display(), err))?;
+                return Self::parse(name, &source)
+                    .map_err(|err| format!("{}: {}", path.display(), err));
+            }
+        }
+        let (_, source) = BUILTIN_COLORSCHEMES
+            .iter()
+            .find(|(builtin, _)| *builtin == name)
+            .ok_or_else(|| format!("Cannot find colorscheme: {}", name))?;
+        Self::parse(name, source)
+    }
+    fn get(&self, style: Style) -> DrawingOptions {
+        self.styles.get(&style).copied().unwrap_or_default()
+    }
+}
+
+// Parses `{style} = {attributes}`.
+fn parse_entry(line: &str) -> Result<(Style, DrawingOptions), String> {
+    let Some((name, value)) = line.split_once('=') else {
+        return Err(format!("Expected `style = attributes`: {}", line));
+    };
+    let name = name.trim();
+    let style = Style::from_name(name).ok_or_else(|| format!("Unknown style: {}", name))?;
+    let mut options = DrawingOptions::default();
+    for word in value.split_whitespace() {
+        if let Some(color) = word.strip_prefix("fg=") {
+            options.foreground_color = parse_color(color)?;
+        } else if let Some(color) = word.strip_prefix("bg=") {
+            options.background_color = parse_color(color)?;
+        } else {
+            match word {
+                "bold" => options.bold = true,
+                "italic" => options.italic = true,
+                "underline" => options.underline = true,
+                _ => return Err(format!("Unknown attribute: {}", word)),
+            }
+        }
+    }
+    Ok((style, options))
+}
+
+fn parse_color(s: &str) -> Result<Color, String> {
+    let invalid = || format!("Invalid color: {}", s);
+    if s == "default" {
+        return Ok(Color::Reset);
+    }
+    if let Some(hex) = s.strip_prefix('#') {
+        if hex.len() != 6 || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let component = |idx: usize| u8::from_str_radix(&hex[idx..idx + 2], 16);
+        return match (component(0), component(2), component(4)) {
+            (Ok(r), Ok(g), Ok(b)) => Ok(Color::Rgb { r, g, b }),
+            _ => Err(invalid()),
+        };
+    }
+    if let Ok(value) = s.parse() {
+        return Ok(Color::AnsiValue(value));
+    }
+    Color::try_from(s).map_err(|()| invalid())
+}
+
+thread_local! {
+    static CURRENT: RefCell<Colorscheme> = RefCell::new(
+        Colorscheme::parse("default", BUILTIN_COLORSCHEMES[0].1)
+            .expect("Built-in colorscheme is invalid!"),
+    );
+}
+
+// Switches to the colorscheme, and keeps the current one if it cannot be loaded.
+pub fn set_current(name: &str) -> Result<(), String> {
+    let colorscheme = Colorscheme::load(name)?;
+    CURRENT.with_borrow_mut(|current| *current = colorscheme);
+    Ok(())
+}
+
+pub fn current_name() -> String {
+    CURRENT.with_borrow(|current| current.name.clone())
+}
+
+pub fn drawing_options(style: Style) -> DrawingOptions {
+    CURRENT.with_borrow(|current| current.get(style))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_colors() {
+        assert_eq!(parse_color("default"), Ok(Color::Reset));
+        assert_eq!(
+            parse_color("#9776d8"),
+            Ok(Color::Rgb {
+                r: 0x97,
+                g: 0x76,
+                b: 0xd8
+            })
+        );
+        assert_eq!(parse_color("244"), Ok(Color::AnsiValue(244)));
+        assert_eq!(parse_color("dark_grey"), Ok(Color::DarkGrey));
+        for invalid in ["#12345", "#1234567", "#gg0000", "#ééé", "256", "purple"] {
+            assert_eq!(
+                parse_color(invalid),
+                Err(format!("Invalid color: {}", invalid))
+            );
+        }
+    }
+
+    #[test]
+    fn parse_entries() {
+        assert_eq!(
+            parse_entry("keyword = fg=#9776d8 bg=default bold italic underline"),
+            Ok((
+                Style::Keywords,
+                DrawingOptions {
+                    foreground_color: Color::Rgb {
+                        r: 0x97,
+                        g: 0x76,
+                        b: 0xd8
+                    },
+                    background_color: Color::Reset,
+                    bold: true,
+                    italic: true,
+                    underline: true,
+                }
+            ))
+        );
+        assert_eq!(
+            parse_entry("comment=fg=244"),
+            Ok((
+                Style::Comment,
+                DrawingOptions {
+                    foreground_color: Color::AnsiValue(244),
+                    ..DrawingOptions::default()
+                }
+            ))
+        );
+        assert_eq!(
+            parse_entry("keyword ="),
+            Ok((Style::Keywords, DrawingOptions::default()))
+        );
+        assert!(parse_entry("keyword fg=red").is_err());
+        assert_eq!(
+            parse_entry("keywords = bold"),
+            Err(String::from("Unknown style: keywords"))
+        );
+        assert_eq!(
+            parse_entry("keyword = blink"),
+            Err(String::from("Unknown attribute: blink"))
+        );
+        assert_eq!(
+            parse_entry("keyword = fg=#12"),
+            Err(String::from("Invalid color: #12"))
+        );
+    }
+
+    #[test]
+    fn parse_colorscheme() {
+        let source = "# comment\n\n  keyword = bold\ncomment = fg=red\n";
+        let colorscheme = Colorscheme::parse("test", source).unwrap();
+        assert!(colorscheme.get(Style::Keywords).bold);
+        assert_eq!(colorscheme.get(Style::Comment).foreground_color, Color::Red);
+        assert_eq!(colorscheme.get(Style::String), DrawingOptions::default());
+        assert_eq!(
+            Colorscheme::parse("test", "keyword = bold\ncomment = fg=?").err(),
+            Some(String::from("line 2: Invalid color: ?"))
+        );
+    }
+
+    #[test]
+    fn builtin_colorschemes_are_valid() {
+        for (name, source) in BUILTIN_COLORSCHEMES {
+            assert!(Colorscheme::parse(name, source).is_ok(), "{}", name);
+        }
+    }
+}
@@ -1,49 +1,258 @@
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use super::annotated_string::{AnnotatedString, Annotation, Style};
 use super::buffer::Line;
-use super::editor_command::CmdlineSubmode;
+use super::cmdline_history::CmdlineHistory;
+use super::completion;
+use super::editor_command::{CmdlineSubmode, Direction};
 use super::terminal::Position;
 use super::Terminal;
 
+// Position in the history while browsing it with Up and Down.
+struct HistoryBrowsing {
+    // text typed before browsing, which shown entries start with
+    prefix: String,
+    // index of the shown entry, or the number of entries while showing the typed text
+    idx: usize,
+}
+
+// Candidates cycled with Tab and Shift-Tab.
+struct Completion {
+    // grapheme index where the completed word starts
+    start: usize,
+    // word typed before completion, which is shown again after the last candidate
+    original: String,
+    candidates: Vec<String>,
+    // index of the shown candidate, or `None` while showing the typed word
+    selected: Option<usize>,
+}
+
+#[derive(Default)]
 pub struct CommandBar {
     prompt: String,
     cmdline: Line,
+    // grapheme index in the command line where text is inserted
+    cursor: usize,
+    // kind of the command line being typed, or `None` while showing a message
+    submode: Option<CmdlineSubmode>,
+    history: CmdlineHistory,
+    browsing: Option<HistoryBrowsing>,
+    completion: Option<Completion>,
+    // the wildmenu has been drawn over the status line
+    wildmenu_drawn: bool,
+    // lines of a long message drawn above the command line
+    message_lines: Vec<String>,
     needs_redraw: bool,
     pos_y: usize,
+    width: usize,
 }
 
 impl CommandBar {
-    pub fn new(pos_y: usize) -> Self {
-        Self {
-            prompt: String::new(),
-            cmdline: Line::from_str(""),
-            needs_redraw: true,
-            pos_y,
-        }
+    pub fn set_area(&mut self, pos_y: usize, width: usize) {
+        self.pos_y = pos_y;
+        self.width = width;
+        self.needs_redraw = true;
     }
     pub fn render(&mut self) -> Result<(), std::io::Error> {
         if !self.needs_redraw {
             return Ok(());
         }
+        let top = self.pos_y.saturating_sub(self.message_lines.len());
+        for (i, line) in self.message_lines.iter().enumerate() {
+            Terminal::move_cursor_to(Position {
+                row: top + i,
+                col: 0,
+            })?;
+            Terminal::clear_line()?;
+            Terminal::print(truncate_to_width(line, self.width))?;
+        }
         let message = format!("{}{}", self.prompt, self.cmdline);
         Terminal::move_cursor_to(Position {
             row: self.pos_y,
             col: 0,
         })?;
         Terminal::clear_line()?;
-        Terminal::print(&message)?;
+        Terminal::print(truncate_to_width(&message, self.width))?;
+        if let Some(completion) = self.completion.as_ref().filter(|c| c.candidates.len() > 1) {
+            Terminal::move_cursor_to(Position {
+                row: self.pos_y.saturating_sub(1),
+                col: 0,
+            })?;
+            Terminal::clear_line()?;
+            Terminal::print_annotated_str(&wildmenu(completion, self.width))?;
+            self.wildmenu_drawn = true;
+        }
         self.needs_redraw = false;
         Ok(())
     }
 
+    // Returns where the terminal cursor is shown while typing the command line.
+    pub fn get_cursor_position(&self) -> Position {
+        let col = self.prompt.width() + self.cmdline.calc_width_until_grapheme_index(self.cursor);
+        Position {
+            row: self.pos_y,
+            col: usize::min(col, self.width.saturating_sub(1)),
+        }
+    }
     pub fn insert_char(&mut self, c: char) {
-        self.needs_redraw = true;
-        self.cmdline.insert_char(c, self.cmdline.len());
+        self.insert_str(&c.to_string());
+    }
+    pub fn insert_str(&mut self, s: &str) {
+        let orig_len = self.cmdline.len();
+        self.cmdline.insert_str(s, self.cursor);
+        // a combining character joins the grapheme before the cursor
+        self.cursor += self.cmdline.len().saturating_sub(orig_len);
+        self.edited();
     }
-
     pub fn handle_backspace(&mut self) {
-        if self.cmdline.len() > 0 {
-            self.needs_redraw = true;
-            self.cmdline.delete_grapheme(self.cmdline.len() - 1);
+        if self.cursor > 0 {
+            self.delete_before_cursor(self.cursor - 1);
+        }
+    }
+    pub fn handle_delete(&mut self) {
+        if self.cursor < self.cmdline.len() {
+            self.cmdline.delete_range(self.cursor, self.cursor + 1);
+            self.edited();
+        }
+    }
+    // Deletes the word before the cursor with blanks after it.
+    pub fn delete_word_before_cursor(&mut self) {
+        let start = self
+            .cmdline
+            .word_spans(false)
+            .into_iter()
+            .map(|(start, _)| start)
+            .rev()
+            .find(|start| *start < self.cursor)
+            .unwrap_or(0);
+        self.delete_before_cursor(start);
+    }
+    // Deletes graphemes from `start` to the cursor.
+    pub fn delete_before_cursor(&mut self, start: usize) {
+        self.cmdline.delete_range(start, self.cursor);
+        self.cursor = start;
+        self.edited();
+    }
+    // Moves the cursor by `Left`, `Right`, `LineStart` and `LineEnd`.
+    pub fn move_cursor(&mut self, direction: Direction) {
+        self.completion = None;
+        self.cursor = match direction {
+            Direction::Left => self.cursor.saturating_sub(1),
+            Direction::Right => usize::min(self.cursor + 1, self.cmdline.len()),
+            Direction::LineStart => 0,
+            Direction::LineEnd => self.cmdline.len(),
+            _ => self.cursor,
+        };
+        self.needs_redraw = true;
+    }
+    fn edited(&mut self) {
+        self.browsing = None;
+        self.completion = None;
+        self.needs_redraw = true;
+    }
+    // Replaces the word being completed with the next or previous candidate.
+    // A word with the only candidate is completed at once.
+    pub fn complete(&mut self, forward: bool, buffer_names: &[String]) {
+        if self.completion.is_none() {
+            if !matches!(self.submode, Some(CmdlineSubmode::Cmdline)) {
+                return;
+            }
+            let text = self.cmdline.get_substr(0, self.cursor);
+            let Some((start, candidates)) = completion::complete(text, buffer_names) else {
+                return;
+            };
+            if candidates.is_empty() {
+                return;
+            }
+            self.completion = Some(Completion {
+                start: Line::from_str(&text[..start]).len(),
+                original: String::from(&text[start..]),
+                candidates,
+                selected: None,
+            });
+        }
+        let Some(completion) = self.completion.as_mut() else {
+            return;
+        };
+        let len = completion.candidates.len();
+        completion.selected = match (completion.selected, forward) {
+            (None, true) => Some(0),
+            (None, false) => Some(len - 1),
+            (Some(idx), true) => (idx + 1 < len).then_some(idx + 1),
+            (Some(idx), false) => idx.checked_sub(1),
+        };
+        let text = match completion.selected {
+            Some(idx) => completion.candidates[idx].clone(),
+            None => completion.original.clone(),
+        };
+        let start = completion.start;
+        if len == 1 {
+            self.completion = None;
         }
+        self.cmdline.delete_range(start, self.cursor);
+        let orig_len = self.cmdline.len();
+        self.cmdline.insert_str(&text, start);
+        self.cursor = start + self.cmdline.len().saturating_sub(orig_len);
+        self.browsing = None;
+        self.needs_redraw = true;
+    }
+    // Returns whether the wildmenu has been removed, and the status line under it
+    // should be drawn again.
+    pub fn take_closed_wildmenu(&mut self) -> bool {
+        let is_shown = self
+            .completion
+            .as_ref()
+            .is_some_and(|c| c.candidates.len() > 1);
+        let closed = self.wildmenu_drawn && !is_shown;
+        if closed {
+            self.wildmenu_drawn = false;
+        }
+        closed
+    }
+    pub fn set_history(&mut self, history: CmdlineHistory) {
+        self.history = history;
+    }
+    pub fn get_history(&self) -> &CmdlineHistory {
+        &self.history
+    }
+    // Adds the command line being typed to the history.
+    pub fn record_history(&mut self) {
+        if let Some(submode) = self.submode {
+            self.history.add(submode, self.cmdline.get_raw_str());
+        }
+    }
+    // Shows an older or newer entry starting with the text typed before browsing.
+    // Going past the newest entry shows the typed text again.
+    pub fn browse_history(&mut self, older: bool) {
+        let Some(submode) = self.submode else {
+            return;
+        };
+        let entries = self.history.entries(submode);
+        let browsing = self.browsing.get_or_insert_with(|| HistoryBrowsing {
+            prefix: self.cmdline.to_string(),
+            idx: entries.len(),
+        });
+        let matches = |idx: &usize| entries[*idx].starts_with(&browsing.prefix);
+        let found = if older {
+            (0..browsing.idx).rev().find(matches)
+        } else {
+            (browsing.idx + 1..entries.len()).find(matches)
+        };
+        let text = match found {
+            Some(idx) => {
+                browsing.idx = idx;
+                entries[idx].as_str()
+            }
+            None if older => return,
+            None => {
+                browsing.idx = entries.len();
+                browsing.prefix.as_str()
+            }
+        };
+        self.cmdline = Line::from_str(text);
+        self.cursor = self.cmdline.len();
+        self.completion = None;
+        self.needs_redraw = true;
     }
     pub fn set_cmdline_prompt(&mut self, submode: CmdlineSubmode) {
         let prompt_str = match submode {
@@ -52,25 +261,101 @@ impl CommandBar {
         };
         self.needs_redraw = true;
         self.prompt = String::from(prompt_str);
+        self.submode = Some(submode);
     }
     pub fn clear_cmdline(&mut self) {
         self.needs_redraw = true;
         self.prompt = String::new();
         self.cmdline = Line::from_str("");
+        self.cursor = 0;
+        self.submode = None;
+        self.browsing = None;
+        self.completion = None;
     }
     pub fn get_raw_cmdline(&self) -> String {
         self.cmdline.to_string()
     }
-    pub fn get_current_cmdline(&self) -> Vec<String> {
-        self.cmdline
-            .to_string()
-            .split_whitespace()
-            .map(String::from)
-            .collect()
+    pub fn set_message(&mut self, msg: &str) {
+        self.clear_cmdline();
+        self.cmdline = Line::from_str(msg);
     }
     pub fn set_error_message(&mut self, msg: &str) {
-        self.needs_redraw = true;
-        self.prompt = String::new();
-        self.cmdline = Line::from_str(msg);
+        self.set_message(msg);
+    }
+    // Shows a message over the window, which is kept until the next key.
+    pub fn set_multiline_message(&mut self, lines: Vec<String>) {
+        self.set_message("Press ENTER or type command to continue");
+        self.message_lines = lines;
+    }
+    // Removes the message over the window, and returns whether there was one.
+    pub fn clear_multiline_message(&mut self) -> bool {
+        if self.message_lines.is_empty() {
+            return false;
+        }
+        self.message_lines.clear();
+        self.clear_cmdline();
+        true
+    }
+}
+
+// Cuts `s` not to wrap around at the right edge of the terminal.
+fn truncate_to_width(s: &str, width: usize) -> &str {
+    let mut total = 0;
+    for (idx, c) in s.char_indices() {
+        total += c.width().unwrap_or(0);
+        if total > width {
+            return &s[..idx];
+        }
+    }
+    s
+}
+
+// Lists candidates in a line, scrolling it to show the selected one.
+// `<` and `>` are shown if some candidates are hidden.
+fn wildmenu(completion: &Completion, width: usize) -> AnnotatedString {
+    // file names are shown without directories
+    let labels: Vec<&str> = completion
+        .candidates
+        .iter()
+        .map(|candidate| {
+            let name_start = candidate
+                .trim_end_matches('/')
+                .rfind('/')
+                .map_or(0, |idx| idx + 1);
+            &candidate[name_start..]
+        })
+        .collect();
+    let span_width = |first: usize, last: usize| {
+        labels[first..=last]
+            .iter()
+            .map(|label| label.width() + 2)
+            .sum::<usize>()
+    };
+    let is_scrolled = span_width(0, labels.len() - 1) > width;
+    let available = if is_scrolled {
+        width.saturating_sub(4)
+    } else {
+        width
+    };
+    let mut first = 0;
+    if let Some(selected) = completion.selected {
+        while first < selected && span_width(first, selected) > available {
+            first += 1;
+        }
+    }
+    let mut menu = AnnotatedString::from_str(if first > 0 { "< " } else { "" });
+    let mut last = first;
+    while last < labels.len() && span_width(first, last) <= available {
+        let mut item = AnnotatedString::from_str(labels[last]);
+        if completion.selected == Some(last) {
+            item.add_annotation(Annotation::new(Style::WildMenu, 0, labels[last].len()));
+        }
+        menu.push_annot_str(&item);
+        menu.push_annot_str(&AnnotatedString::from_str("  "));
+        last += 1;
+    }
+    if last < labels.len() {
+        menu.push_annot_str(&AnnotatedString::from_str(">"));
     }
+    menu
 }
@@ -0,0 +1,78 @@
+use super::cmdline_commands::COMMAND_NAMES;
+use super::options::{BOOLEAN_OPTION_NAMES, VALUE_OPTION_NAMES};
+
+// Returns candidates for the last word of `cmdline` with byte index where the word starts.
+// Command names are completed in the first word, and arguments depend on the command.
+pub fn complete(cmdline: &str, buffer_names: &[String]) -> Option<(usize, Vec<String>)> {
+    let word = cmdline
+        .rsplit(char::is_whitespace)
+        .next()
+        .unwrap_or_default();
+    let word_start = cmdline.len() - word.len();
+    let Some(name) = cmdline[..word_start].split_whitespace().next() else {
+        let candidates = COMMAND_NAMES
+            .iter()
+            .filter(|name| name.starts_with(word))
+            .map(|name| String::from(*name))
+            .collect();
+        return Some((word_start, candidates));
+    };
+    let candidates = match name.trim_end_matches('!') {
+        "e" | "edit" | "saveas" | "sp" | "split" | "vs" | "vsplit" => complete_path(word),
+        "set" => complete_option(word),
+        "b" | "buffer" | "bd" | "bdelete" => buffer_names
+            .iter()
+            .filter(|name| name.contains(word))
+            .cloned()
+            .collect(),
+        _ => return None,
+    };
+    Some((word_start, candidates))
+}
+
+// Lists files and directories starting with `word`, and appends `/` to directories.
+fn complete_path(word: &str) -> Vec<String> {
+    let (dir, prefix) = match word.rfind('/') {
+        Some(idx) => word.split_at(idx + 1),
+        None => ("", word),
+    };
+    let Ok(entries) = std::fs::read_dir(if dir.is_empty() { "." } else { dir }) else {
+        return vec![];
+    };
+    let mut candidates: Vec<String> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let name = entry.file_name().into_string().ok()?;
+            // hidden files are listed only if the word starts with `.`
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let suffix = if entry.path().is_dir() { "/" } else { "" };
+            Some(format!("{}{}{}", dir, name, suffix))
+        })
+        .collect();
+    candidates.sort();
+    candidates
+}
+
+// Lists options starting with `word`, including `no{option}` and `inv{option}`.
+fn complete_option(word: &str) -> Vec<String> {
+    let mut candidates: Vec<String> = BOOLEAN_OPTION_NAMES
+        .iter()
+        .chain(VALUE_OPTION_NAMES.iter())
+        .filter(|name| name.starts_with(word))
+        .map(|name| String::from(*name))
+        .collect();
+    candidates.sort();
+    for prefix in ["no", "inv"] {
+        if let Some(rest) = word.strip_prefix(prefix) {
+            candidates.extend(
+                BOOLEAN_OPTION_NAMES
+                    .iter()
+                    .filter(|name| name.starts_with(rest))
+                    .map(|name| format!("{}{}", prefix, name)),
+            );
+        }
+    }
+    candidates
+}
@@ -1,5 +1,85 @@
-use crossterm::event::Event::{self, Key};
-use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+
+use super::registers::Registers;
+
+// Key decoded from a terminal event, which mode handlers interpret.
+// Printable characters are text whether typed with Shift or AltGr,
+// and only Ctrl and Alt make chords.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Key {
+    // text such as `a`, `A`, `é` and `€`
+    Char(char),
+    // `CTRL-{c}` with lowercase letter, e.g. `Ctrl('w')` for CTRL-W
+    Ctrl(char),
+    // `ALT-{c}`, which is also sent by Meta
+    Alt(char),
+    Enter,
+    Esc,
+    Tab,
+    // Shift-Tab
+    BackTab,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+}
+
+impl Key {
+    // Returns `None` for events other than key presses and for chords not supported.
+    pub fn from_event(event: &Event) -> Option<Self> {
+        let Event::Key(KeyEvent {
+            code,
+            modifiers,
+            kind: KeyEventKind::Press | KeyEventKind::Repeat,
+            ..
+        }) = event
+        else {
+            return None;
+        };
+        let ctrl = modifiers.contains(KeyModifiers::CONTROL);
+        let alt = modifiers.contains(KeyModifiers::ALT);
+        if let KeyCode::Char(c) = *code {
+            return Some(match (ctrl, alt) {
+                // AltGr is reported as Ctrl+Alt on some platforms
+                (true, true) if !c.is_ascii_alphabetic() => Self::Char(c),
+                (true, true) => return None,
+                (true, false) => Self::Ctrl(c.to_ascii_lowercase()),
+                (false, true) => Self::Alt(c),
+                // some terminals report Shift with the unshifted letter
+                (false, false) if modifiers.contains(KeyModifiers::SHIFT) => {
+                    Self::Char(c.to_ascii_uppercase())
+                }
+                (false, false) => Self::Char(c),
+            });
+        }
+        if ctrl || alt {
+            return None;
+        }
+        match code {
+            KeyCode::Enter => Some(Self::Enter),
+            KeyCode::Esc => Some(Self::Esc),
+            KeyCode::Tab => Some(Self::Tab),
+            KeyCode::BackTab => Some(Self::BackTab),
+            KeyCode::Backspace => Some(Self::Backspace),
+            KeyCode::Delete => Some(Self::Delete),
+            KeyCode::Left => Some(Self::Left),
+            KeyCode::Right => Some(Self::Right),
+            KeyCode::Up => Some(Self::Up),
+            KeyCode::Down => Some(Self::Down),
+            KeyCode::Home => Some(Self::Home),
+            KeyCode::End => Some(Self::End),
+            KeyCode::PageUp => Some(Self::PageUp),
+            KeyCode::PageDown => Some(Self::PageDown),
+            _ => None,
+        }
+    }
+}
 
 #[derive(Clone, Copy)]
 pub enum CmdlineSubmode {
@@ -7,69 +87,392 @@ pub enum CmdlineSubmode {
     Search,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum VisualSubmode {
+    Characterwise,
+    Linewise,
+    Blockwise,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SplitDirection {
+    // windows stacked vertically, made by `:split`
+    Horizontal,
+    // windows side by side, made by `:vsplit`
+    Vertical,
+}
+
+#[allow(clippy::enum_variant_names)]
+#[derive(Default)]
 pub enum EditorMode {
+    #[default]
     NormalMode,
     InsertMode,
     CmdlineMode(CmdlineSubmode),
+    VisualMode(VisualSubmode),
+    // confirming each substitution of `:s///c`
+    ConfirmMode,
+    // choosing what to do with an existing swap file
+    RecoveryMode,
 }
 
-impl Default for EditorMode {
-    fn default() -> Self {
-        Self::NormalMode
-    }
-}
-
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum Direction {
     Left,
     Right,
     Up,
     Down,
+    // `w` and `W`
+    WordForward,
+    BigWordForward,
+    // `b` and `B`
+    WordBackward,
+    BigWordBackward,
+    // `e` and `E`
+    WordEnd,
+    BigWordEnd,
+    // `0`, `^` and `$`
+    LineStart,
+    FirstNonBlank,
+    LineEnd,
+    // `gg` and `G` with 1-indexed line number
+    GotoLine(usize),
+    // `{` and `}`
+    ParagraphBackward,
+    ParagraphForward,
+    // `%`
+    MatchingBracket,
+}
+
+impl Direction {
+    // Motions which consist of two keys, such as `gg`, are handled by key parsers.
+    fn from_key(key: Key) -> Option<Self> {
+        match key {
+            Key::Char('h') => Some(Self::Left),
+            Key::Char('j') => Some(Self::Down),
+            Key::Char('k') => Some(Self::Up),
+            Key::Char('l') => Some(Self::Right),
+            Key::Char('w') => Some(Self::WordForward),
+            Key::Char('W') => Some(Self::BigWordForward),
+            Key::Char('b') => Some(Self::WordBackward),
+            Key::Char('B') => Some(Self::BigWordBackward),
+            Key::Char('e') => Some(Self::WordEnd),
+            Key::Char('E') => Some(Self::BigWordEnd),
+            Key::Char('0') => Some(Self::LineStart),
+            Key::Char('^') => Some(Self::FirstNonBlank),
+            Key::Char('$') => Some(Self::LineEnd),
+            Key::Char('G') => Some(Self::GotoLine(usize::MAX)),
+            Key::Char('{') => Some(Self::ParagraphBackward),
+            Key::Char('}') => Some(Self::ParagraphForward),
+            Key::Char('%') => Some(Self::MatchingBracket),
+            _ => None,
+        }
+    }
+    // Returns motion for keys following `g`.
+    fn from_key_after_g(key: Key) -> Option<Self> {
+        match key {
+            Key::Char('g') => Some(Self::GotoLine(1)),
+            _ => None,
+        }
+    }
+    // Applies count to the motion. `G` and `gg` take count as line number,
+    // and other motions are repeated by the count.
+    fn with_count(self, count: Option<usize>) -> (Self, usize) {
+        match (self, count) {
+            (Self::GotoLine(_), Some(line_number)) => (Self::GotoLine(line_number), 1),
+            (_, count) => (self, count.unwrap_or(1)),
+        }
+    }
+    // Operators applied with linewise motions affect whole lines.
+    pub fn is_linewise(&self) -> bool {
+        matches!(self, Self::Up | Self::Down | Self::GotoLine(_))
+    }
+    // Operators applied with inclusive motions include
+    // the character at the end of motion.
+    pub fn is_inclusive(&self) -> bool {
+        matches!(
+            self,
+            Self::LineEnd | Self::WordEnd | Self::BigWordEnd | Self::MatchingBracket
+        )
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+impl Operator {
+    fn from_key(key: Key) -> Option<Self> {
+        match key {
+            Key::Char('d') => Some(Self::Delete),
+            Key::Char('c') => Some(Self::Change),
+            Key::Char('y') => Some(Self::Yank),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum OperatorTarget {
+    // text from the cursor to the end of motion
+    Motion(Direction),
+    // whole lines from the cursor line, e.g. `dd`, `cc` and `yy`
+    Lines,
 }
 
 pub enum NormalModeCommand {
-    CursorMove(Direction),
+    CursorMove(Direction, usize),
+    Operate {
+        operator: Operator,
+        target: OperatorTarget,
+        count: usize,
+        register: Option<char>,
+    },
+    // `p` puts text after the cursor, and `P` puts before the cursor.
+    Put {
+        after: bool,
+        count: usize,
+        register: Option<char>,
+    },
     EnterInsertMode,
     EnterInsertModeAppend,
     EnterInsertModeBeginNewLineAbove,
     EnterInsertModeBeginNewLineBelow,
     EnterCmdlineMode(CmdlineSubmode),
+    EnterVisualMode(VisualSubmode),
     SearchNext,
     SearchPrev,
+    Undo,
+    Redo,
+    // `CTRL-W` followed by a key, with count typed before `CTRL-W`
+    Window(WindowCommand, usize),
     Nop,
 }
 
 impl NormalModeCommand {
-    pub fn from_key_event(event: &Event) -> Self {
-        if let Key(KeyEvent {
-            code,
-            modifiers,
-            kind: KeyEventKind::Press,
-            ..
-        }) = event
-        {
-            let command = if *modifiers == KeyModifiers::NONE || *modifiers == KeyModifiers::SHIFT {
-                match code {
-                    KeyCode::Char('h') => Self::CursorMove(Direction::Left),
-                    KeyCode::Char('j') => Self::CursorMove(Direction::Down),
-                    KeyCode::Char('k') => Self::CursorMove(Direction::Up),
-                    KeyCode::Char('l') => Self::CursorMove(Direction::Right),
-                    KeyCode::Char('i') => Self::EnterInsertMode,
-                    KeyCode::Char('a') => Self::EnterInsertModeAppend,
-                    KeyCode::Char('O') => Self::EnterInsertModeBeginNewLineAbove,
-                    KeyCode::Char('o') => Self::EnterInsertModeBeginNewLineBelow,
-                    KeyCode::Char(':') => Self::EnterCmdlineMode(CmdlineSubmode::Cmdline),
-                    KeyCode::Char('/') => Self::EnterCmdlineMode(CmdlineSubmode::Search),
-                    KeyCode::Char('n') => Self::SearchNext,
-                    KeyCode::Char('N') => Self::SearchPrev,
-                    _ => Self::Nop,
+    pub fn from_key(key: Key) -> Self {
+        if let Some(direction) = Direction::from_key(key) {
+            return Self::CursorMove(direction, 1);
+        }
+        match key {
+            Key::Char('i') => Self::EnterInsertMode,
+            Key::Char('a') => Self::EnterInsertModeAppend,
+            Key::Char('O') => Self::EnterInsertModeBeginNewLineAbove,
+            Key::Char('o') => Self::EnterInsertModeBeginNewLineBelow,
+            Key::Char(':') => Self::EnterCmdlineMode(CmdlineSubmode::Cmdline),
+            Key::Char('/') => Self::EnterCmdlineMode(CmdlineSubmode::Search),
+            Key::Char('v') => Self::EnterVisualMode(VisualSubmode::Characterwise),
+            Key::Char('V') => Self::EnterVisualMode(VisualSubmode::Linewise),
+            Key::Char('n') => Self::SearchNext,
+            Key::Char('N') => Self::SearchPrev,
+            Key::Char('u') => Self::Undo,
+            Key::Ctrl('r') => Self::Redo,
+            Key::Ctrl('v') => Self::EnterVisualMode(VisualSubmode::Blockwise),
+            _ => Self::Nop,
+        }
+    }
+}
+
+// Commands typed after `CTRL-W`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum WindowCommand {
+    // `s` and `v`
+    Split(SplitDirection),
+    // `h`, `j`, `k` and `l`
+    Focus(Direction),
+    // `w` and `W`
+    FocusNext,
+    FocusPrev,
+    // `c` and `q`
+    Close,
+    Quit,
+    // `o`
+    Only,
+    // `+` and `-` change height, `>` and `<` change width
+    Grow(SplitDirection),
+    Shrink(SplitDirection),
+    // `=`
+    Equalize,
+}
+
+impl WindowCommand {
+    // Keys may be typed with or without CTRL, e.g. `CTRL-W CTRL-J` is the same as `CTRL-W j`.
+    fn from_key(key: Key) -> Option<Self> {
+        match key {
+            Key::Ctrl(c) => match c {
+                's' => Some(Self::Split(SplitDirection::Horizontal)),
+                'v' => Some(Self::Split(SplitDirection::Vertical)),
+                'h' => Some(Self::Focus(Direction::Left)),
+                'j' => Some(Self::Focus(Direction::Down)),
+                'k' => Some(Self::Focus(Direction::Up)),
+                'l' => Some(Self::Focus(Direction::Right)),
+                'w' => Some(Self::FocusNext),
+                'c' => Some(Self::Close),
+                'q' => Some(Self::Quit),
+                'o' => Some(Self::Only),
+                _ => None,
+            },
+            Key::Char(c) => match c {
+                's' | 'S' => Some(Self::Split(SplitDirection::Horizontal)),
+                'v' => Some(Self::Split(SplitDirection::Vertical)),
+                'h' => Some(Self::Focus(Direction::Left)),
+                'j' => Some(Self::Focus(Direction::Down)),
+                'k' => Some(Self::Focus(Direction::Up)),
+                'l' => Some(Self::Focus(Direction::Right)),
+                'w' => Some(Self::FocusNext),
+                'W' => Some(Self::FocusPrev),
+                'c' => Some(Self::Close),
+                'q' => Some(Self::Quit),
+                'o' => Some(Self::Only),
+                '+' => Some(Self::Grow(SplitDirection::Horizontal)),
+                '-' => Some(Self::Shrink(SplitDirection::Horizontal)),
+                '>' => Some(Self::Grow(SplitDirection::Vertical)),
+                '<' => Some(Self::Shrink(SplitDirection::Vertical)),
+                '=' => Some(Self::Equalize),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+// Accumulates keys of composite normal mode commands,
+// such as `3j`, `gg`, `d3w`, `cc`, `2y$`, `"ap` and `CTRL-W j`.
+#[derive(Default)]
+pub struct NormalModeKeyParser {
+    count: Option<usize>,
+    // register name given by `"{name}`
+    register: Option<char>,
+    awaiting_register_name: bool,
+    // `g` is typed, waiting for the second key
+    pending_g: bool,
+    // operator waiting for a motion, with count typed before it
+    pending_operator: Option<(Operator, Option<usize>)>,
+    // `CTRL-W` is typed, waiting for the window command
+    pending_window: bool,
+}
+
+impl NormalModeKeyParser {
+    // Returns `NormalModeCommand::Nop` while the command is incomplete.
+    pub fn parse(&mut self, key: Key) -> NormalModeCommand {
+        if self.pending_window {
+            let count = self.count.unwrap_or(1);
+            self.reset();
+            return match WindowCommand::from_key(key) {
+                Some(command) => NormalModeCommand::Window(command, count),
+                None => NormalModeCommand::Nop,
+            };
+        }
+        if key == Key::Ctrl('w') && self.pending_operator.is_none() && !self.pending_g {
+            self.pending_window = true;
+            return NormalModeCommand::Nop;
+        }
+        if matches!(key, Key::Ctrl(_) | Key::Alt(_)) {
+            self.reset();
+            return NormalModeCommand::from_key(key);
+        }
+        if self.awaiting_register_name {
+            self.awaiting_register_name = false;
+            match key {
+                Key::Char(c) if Registers::is_valid_name(c) => {
+                    self.register = Some(c);
                 }
+                _ => self.reset(),
+            }
+            return NormalModeCommand::Nop;
+        }
+        if let Key::Char(c) = key {
+            // `0` is a motion unless it follows other digits.
+            let is_count = !self.pending_g && (c != '0' || self.count.is_some());
+            if let Some(digit) = c.to_digit(10).filter(|_| is_count) {
+                let count = self.count.unwrap_or(0);
+                self.count = Some(count.saturating_mul(10).saturating_add(digit as usize));
+                return NormalModeCommand::Nop;
+            }
+        }
+        let direction = if self.pending_g {
+            self.pending_g = false;
+            Direction::from_key_after_g(key)
+        } else if key == Key::Char('g') {
+            self.pending_g = true;
+            return NormalModeCommand::Nop;
+        } else {
+            Direction::from_key(key)
+        };
+        if let Some((operator, operator_count)) = self.pending_operator {
+            // Counts before and after operator are multiplied, e.g. `2d3w` deletes 6 words.
+            let count = match (operator_count, self.count) {
+                (None, None) => None,
+                (a, b) => Some(a.unwrap_or(1).saturating_mul(b.unwrap_or(1))),
+            };
+            let register = self.register;
+            self.reset();
+            let (target, count) = if let Some(direction) = direction {
+                let (direction, count) = direction.with_count(count);
+                (OperatorTarget::Motion(direction), count)
+            } else if Operator::from_key(key) == Some(operator) {
+                (OperatorTarget::Lines, count.unwrap_or(1))
             } else {
-                Self::Nop
+                // cancel operator (e.g. by Esc)
+                return NormalModeCommand::Nop;
             };
-            command
-        } else {
-            Self::Nop
+            return NormalModeCommand::Operate {
+                operator,
+                target,
+                count,
+                register,
+            };
+        }
+        if let Some(operator) = Operator::from_key(key) {
+            self.pending_operator = Some((operator, self.count.take()));
+            return NormalModeCommand::Nop;
+        }
+        if key == Key::Char('"') {
+            self.awaiting_register_name = true;
+            return NormalModeCommand::Nop;
+        }
+        let (count, register) = (self.count, self.register);
+        self.reset();
+        if let Some(direction) = direction {
+            let (direction, count) = direction.with_count(count);
+            return NormalModeCommand::CursorMove(direction, count);
         }
+        let count = count.unwrap_or(1);
+        let (operator, target) = match key {
+            Key::Char('p') | Key::Char('P') => {
+                return NormalModeCommand::Put {
+                    after: key == Key::Char('p'),
+                    count,
+                    register,
+                };
+            }
+            Key::Char('x') => (Operator::Delete, OperatorTarget::Motion(Direction::Right)),
+            Key::Char('D') => (Operator::Delete, OperatorTarget::Motion(Direction::LineEnd)),
+            Key::Char('C') => (Operator::Change, OperatorTarget::Motion(Direction::LineEnd)),
+            Key::Char('Y') => (Operator::Yank, OperatorTarget::Lines),
+            _ => return NormalModeCommand::from_key(key),
+        };
+        NormalModeCommand::Operate {
+            operator,
+            target,
+            count,
+            register,
+        }
+    }
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+    // Returns true while keys of a command are being typed.
+    pub fn is_pending(&self) -> bool {
+        self.count.is_some()
+            || self.register.is_some()
+            || self.awaiting_register_name
+            || self.pending_g
+            || self.pending_operator.is_some()
+            || self.pending_window
     }
 }
 
@@ -77,34 +480,51 @@ pub enum InsertModeCommand {
     LeaveInsertMode,
     Insert(char),
     Backspace,
+    // Delete key: delete the character under the cursor,
+    // or join the next line at the end of line
+    Delete,
     InsertNewLine,
+    // arrow keys and Home
+    CursorMove(Direction),
+    // End moves past the last character
+    MoveToLineEnd,
+    // PageDown and PageUp
+    ScrollPage { forward: bool },
+    // `CTRL-W`: delete the word before the cursor
+    DeleteWord,
+    // `CTRL-U`: delete text before the cursor in the line
+    DeleteLine,
+    // `CTRL-T` and `CTRL-D`: add or remove a level of indentation
+    Indent,
+    Dedent,
+    // `CTRL-O`: execute one command in normal mode, and return to insert mode
+    ExecuteNormalModeCommand,
     Nop,
 }
 
 impl InsertModeCommand {
-    pub fn from_key_event(event: &Event) -> Self {
-        if let Key(KeyEvent {
-            code,
-            modifiers,
-            kind: KeyEventKind::Press,
-            ..
-        }) = event
-        {
-            let command = if *modifiers == KeyModifiers::NONE {
-                match code {
-                    KeyCode::Char(c) => Self::Insert(*c),
-                    KeyCode::Tab => Self::Insert('\t'),
-                    KeyCode::Backspace => Self::Backspace,
-                    KeyCode::Enter => Self::InsertNewLine,
-                    KeyCode::Esc => Self::LeaveInsertMode,
-                    _ => Self::Nop,
-                }
-            } else {
-                Self::Nop
-            };
-            command
-        } else {
-            Self::Nop
+    pub fn from_key(key: Key) -> Self {
+        match key {
+            Key::Char(c) => Self::Insert(c),
+            Key::Tab => Self::Insert('\t'),
+            Key::Backspace => Self::Backspace,
+            Key::Delete => Self::Delete,
+            Key::Enter => Self::InsertNewLine,
+            Key::Esc => Self::LeaveInsertMode,
+            Key::Left => Self::CursorMove(Direction::Left),
+            Key::Right => Self::CursorMove(Direction::Right),
+            Key::Up => Self::CursorMove(Direction::Up),
+            Key::Down => Self::CursorMove(Direction::Down),
+            Key::Home => Self::CursorMove(Direction::LineStart),
+            Key::End => Self::MoveToLineEnd,
+            Key::PageDown => Self::ScrollPage { forward: true },
+            Key::PageUp => Self::ScrollPage { forward: false },
+            Key::Ctrl('w') => Self::DeleteWord,
+            Key::Ctrl('u') => Self::DeleteLine,
+            Key::Ctrl('t') => Self::Indent,
+            Key::Ctrl('d') => Self::Dedent,
+            Key::Ctrl('o') => Self::ExecuteNormalModeCommand,
+            _ => Self::Nop,
         }
     }
 }
@@ -114,32 +534,464 @@ pub enum CmdlineModeCommand {
     Execute,
     Insert(char),
     Backspace,
+    Delete,
+    // Left, Right, Home and End
+    CursorMove(Direction),
+    // `CTRL-W`: delete the word before the cursor
+    DeleteWord,
+    // `CTRL-U`: delete all text before the cursor
+    DeleteLine,
+    // Up and Down: recall older or newer history entries
+    BrowseHistory { older: bool },
+    // Tab and Shift-Tab: show the next or previous completion
+    Complete { forward: bool },
+    // `CTRL-G` and `CTRL-T`: move to the next or previous match while typing a search pattern
+    StepMatch { forward: bool },
+    // `CTRL-R {register}`: insert the contents of the register
+    InsertRegister(char),
+    // `CTRL-R CTRL-W`: insert the word under the cursor in the window
+    InsertWordUnderCursor,
     Nop,
 }
 
 impl CmdlineModeCommand {
-    pub fn from_key_event(event: &Event) -> Self {
-        if let Key(KeyEvent {
-            code,
-            modifiers,
-            kind: KeyEventKind::Press,
-            ..
-        }) = event
-        {
-            let command = if *modifiers == KeyModifiers::NONE {
-                match code {
-                    KeyCode::Esc => Self::LeaveCmdlineMode,
-                    KeyCode::Enter => Self::Execute,
-                    KeyCode::Char(c) => Self::Insert(*c),
-                    KeyCode::Backspace => Self::Backspace,
-                    _ => Self::Nop,
+    pub fn from_key(key: Key) -> Self {
+        match key {
+            Key::Esc => Self::LeaveCmdlineMode,
+            Key::Enter => Self::Execute,
+            Key::Char(c) => Self::Insert(c),
+            Key::Backspace => Self::Backspace,
+            Key::Delete => Self::Delete,
+            Key::Left => Self::CursorMove(Direction::Left),
+            Key::Right => Self::CursorMove(Direction::Right),
+            Key::Home => Self::CursorMove(Direction::LineStart),
+            Key::End => Self::CursorMove(Direction::LineEnd),
+            Key::Ctrl('w') => Self::DeleteWord,
+            Key::Ctrl('u') => Self::DeleteLine,
+            Key::Up => Self::BrowseHistory { older: true },
+            Key::Down => Self::BrowseHistory { older: false },
+            Key::Tab => Self::Complete { forward: true },
+            Key::BackTab => Self::Complete { forward: false },
+            Key::Ctrl('g') => Self::StepMatch { forward: true },
+            Key::Ctrl('t') => Self::StepMatch { forward: false },
+            _ => Self::Nop,
+        }
+    }
+}
+
+// Accumulates `CTRL-R` and the register name in cmdline mode.
+#[derive(Default)]
+pub struct CmdlineModeKeyParser {
+    awaiting_register_name: bool,
+}
+
+impl CmdlineModeKeyParser {
+    // Returns `CmdlineModeCommand::Nop` while the command is incomplete.
+    pub fn parse(&mut self, key: Key) -> CmdlineModeCommand {
+        if self.awaiting_register_name {
+            self.awaiting_register_name = false;
+            return match key {
+                Key::Char(c) if Registers::is_valid_name(c) => {
+                    CmdlineModeCommand::InsertRegister(c)
                 }
-            } else {
-                Self::Nop
+                Key::Ctrl('w') => CmdlineModeCommand::InsertWordUnderCursor,
+                _ => CmdlineModeCommand::Nop,
             };
-            command
-        } else {
-            Self::Nop
         }
+        if key == Key::Ctrl('r') {
+            self.awaiting_register_name = true;
+            return CmdlineModeCommand::Nop;
+        }
+        CmdlineModeCommand::from_key(key)
+    }
+}
+
+pub enum VisualModeCommand {
+    CursorMove(Direction, usize),
+    // `v`, `V` and Ctrl-V: switch to the given submode,
+    // or leave visual mode if already in it.
+    SwitchSubmode(VisualSubmode),
+    SwapAnchor,
+    Delete,
+    Yank,
+    Change,
+    // `:` starts a command on the selected lines
+    EnterCmdlineMode,
+    LeaveVisualMode,
+    Nop,
+}
+
+impl VisualModeCommand {
+    pub fn from_key(key: Key) -> Self {
+        if let Some(direction) = Direction::from_key(key) {
+            return Self::CursorMove(direction, 1);
+        }
+        match key {
+            Key::Char('v') => Self::SwitchSubmode(VisualSubmode::Characterwise),
+            Key::Char('V') => Self::SwitchSubmode(VisualSubmode::Linewise),
+            Key::Ctrl('v') => Self::SwitchSubmode(VisualSubmode::Blockwise),
+            Key::Char('o') => Self::SwapAnchor,
+            Key::Char('d') | Key::Char('x') => Self::Delete,
+            Key::Char('y') => Self::Yank,
+            Key::Char('c') | Key::Char('s') => Self::Change,
+            Key::Char(':') => Self::EnterCmdlineMode,
+            Key::Esc => Self::LeaveVisualMode,
+            _ => Self::Nop,
+        }
+    }
+}
+
+// Accumulates counts and `g` prefix of motions in visual mode.
+#[derive(Default)]
+pub struct VisualModeKeyParser {
+    count: Option<usize>,
+    pending_g: bool,
+}
+
+impl VisualModeKeyParser {
+    // Returns `VisualModeCommand::Nop` while the command is incomplete.
+    pub fn parse(&mut self, key: Key) -> VisualModeCommand {
+        if let Key::Char(c) = key {
+            let is_count = !self.pending_g && (c != '0' || self.count.is_some());
+            if let Some(digit) = c.to_digit(10).filter(|_| is_count) {
+                let count = self.count.unwrap_or(0);
+                self.count = Some(count.saturating_mul(10).saturating_add(digit as usize));
+                return VisualModeCommand::Nop;
+            }
+            if self.pending_g {
+                let count = self.count.take();
+                self.pending_g = false;
+                return match Direction::from_key_after_g(key) {
+                    Some(direction) => {
+                        let (direction, count) = direction.with_count(count);
+                        VisualModeCommand::CursorMove(direction, count)
+                    }
+                    None => VisualModeCommand::Nop,
+                };
+            }
+            if c == 'g' {
+                self.pending_g = true;
+                return VisualModeCommand::Nop;
+            }
+        }
+        let count = self.count.take();
+        self.pending_g = false;
+        match VisualModeCommand::from_key(key) {
+            VisualModeCommand::CursorMove(direction, _) => {
+                let (direction, count) = direction.with_count(count);
+                VisualModeCommand::CursorMove(direction, count)
+            }
+            command => command,
+        }
+    }
+}
+
+// Answers to the prompt of `:s///c`.
+pub enum ConfirmModeCommand {
+    // `y`: replace this match
+    Yes,
+    // `n`: skip this match
+    No,
+    // `a`: replace this and all remaining matches
+    All,
+    // `q` and Esc: stop substituting
+    Quit,
+    // `l`: replace this match and stop
+    Last,
+    Nop,
+}
+
+impl ConfirmModeCommand {
+    pub fn from_key(key: Key) -> Self {
+        match key {
+            Key::Char('y') => Self::Yes,
+            Key::Char('n') => Self::No,
+            Key::Char('a') => Self::All,
+            Key::Char('q') | Key::Esc => Self::Quit,
+            Key::Char('l') => Self::Last,
+            _ => Self::Nop,
+        }
+    }
+}
+
+pub enum RecoveryModeCommand {
+    // `r`: restore contents from the swap file
+    Recover,
+    // `o` and Esc: open the file read-only, leaving the swap file
+    ReadOnly,
+    // `d`: delete the swap file
+    Delete,
+    Nop,
+}
+
+impl RecoveryModeCommand {
+    pub fn from_key(key: Key) -> Self {
+        match key {
+            Key::Char('r' | 'R') => Self::Recover,
+            Key::Char('o' | 'O') | Key::Esc => Self::ReadOnly,
+            Key::Char('d' | 'D') => Self::Delete,
+            _ => Self::Nop,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(keys: &str) -> Vec<Key> {
+        keys.chars().map(Key::Char).collect()
+    }
+
+    // Feeds `keys` and returns the command completed by the last key.
+    fn parse(parser: &mut NormalModeKeyParser, keys: &[Key]) -> NormalModeCommand {
+        let (last, init) = keys.split_last().expect("keys should not be empty");
+        for key in init {
+            assert!(matches!(parser.parse(*key), NormalModeCommand::Nop));
+        }
+        parser.parse(*last)
+    }
+
+    fn parse_chars(keys: &str) -> NormalModeCommand {
+        parse(&mut NormalModeKeyParser::default(), &chars(keys))
+    }
+
+    #[test]
+    fn counts_are_multiplied() {
+        assert!(matches!(
+            parse_chars("2d3w"),
+            NormalModeCommand::Operate {
+                operator: Operator::Delete,
+                target: OperatorTarget::Motion(Direction::WordForward),
+                count: 6,
+                register: None,
+            }
+        ));
+        assert!(matches!(
+            parse_chars("d3w"),
+            NormalModeCommand::Operate { count: 3, .. }
+        ));
+        assert!(matches!(
+            parse_chars("3dw"),
+            NormalModeCommand::Operate { count: 3, .. }
+        ));
+        assert!(matches!(
+            parse_chars("dw"),
+            NormalModeCommand::Operate { count: 1, .. }
+        ));
+        // counts of `G` are line numbers
+        assert!(matches!(
+            parse_chars("2y3G"),
+            NormalModeCommand::Operate {
+                operator: Operator::Yank,
+                target: OperatorTarget::Motion(Direction::GotoLine(6)),
+                count: 1,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn counts_of_motions() {
+        assert!(matches!(
+            parse_chars("10j"),
+            NormalModeCommand::CursorMove(Direction::Down, 10)
+        ));
+        assert!(matches!(
+            parse_chars("0"),
+            NormalModeCommand::CursorMove(Direction::LineStart, 1)
+        ));
+        assert!(matches!(
+            parse_chars("5gg"),
+            NormalModeCommand::CursorMove(Direction::GotoLine(5), 1)
+        ));
+        assert!(matches!(
+            parse_chars("gg"),
+            NormalModeCommand::CursorMove(Direction::GotoLine(1), 1)
+        ));
+    }
+
+    #[test]
+    fn doubled_operators_affect_lines() {
+        assert!(matches!(
+            parse_chars("dd"),
+            NormalModeCommand::Operate {
+                operator: Operator::Delete,
+                target: OperatorTarget::Lines,
+                count: 1,
+                ..
+            }
+        ));
+        assert!(matches!(
+            parse_chars("3yy"),
+            NormalModeCommand::Operate {
+                operator: Operator::Yank,
+                target: OperatorTarget::Lines,
+                count: 3,
+                ..
+            }
+        ));
+        assert!(matches!(
+            parse_chars("2c3c"),
+            NormalModeCommand::Operate {
+                operator: Operator::Change,
+                target: OperatorTarget::Lines,
+                count: 6,
+                ..
+            }
+        ));
+        // different operators cancel each other
+        let mut parser = NormalModeKeyParser::default();
+        assert!(matches!(
+            parse(&mut parser, &chars("dy")),
+            NormalModeCommand::Nop
+        ));
+        assert!(!parser.is_pending());
+    }
+
+    #[test]
+    fn register_prefixes() {
+        assert!(matches!(
+            parse_chars("\"ayy"),
+            NormalModeCommand::Operate {
+                operator: Operator::Yank,
+                target: OperatorTarget::Lines,
+                count: 1,
+                register: Some('a'),
+            }
+        ));
+        assert!(matches!(
+            parse_chars("2\"Ad3w"),
+            NormalModeCommand::Operate {
+                count: 6,
+                register: Some('A'),
+                ..
+            }
+        ));
+        assert!(matches!(
+            parse_chars("\"-3P"),
+            NormalModeCommand::Put {
+                after: false,
+                count: 3,
+                register: Some('-'),
+            }
+        ));
+        // invalid register name is ignored with the prefix
+        let mut parser = NormalModeKeyParser::default();
+        assert!(matches!(
+            parse(&mut parser, &chars("\"!")),
+            NormalModeCommand::Nop
+        ));
+        assert!(!parser.is_pending());
+        assert!(matches!(
+            parser.parse(Key::Char('p')),
+            NormalModeCommand::Put { register: None, .. }
+        ));
+    }
+
+    #[test]
+    fn esc_cancels_pending_keys() {
+        for keys in ["3", "d", "2d3", "\"", "\"a", "g", "\"ad"] {
+            let mut parser = NormalModeKeyParser::default();
+            let mut keys = chars(keys);
+            keys.push(Key::Esc);
+            assert!(matches!(parse(&mut parser, &keys), NormalModeCommand::Nop));
+            assert!(!parser.is_pending());
+            assert!(matches!(
+                parser.parse(Key::Char('w')),
+                NormalModeCommand::CursorMove(Direction::WordForward, 1)
+            ));
+        }
+    }
+
+    fn decode(code: KeyCode, modifiers: KeyModifiers) -> Option<Key> {
+        Key::from_event(&Event::Key(KeyEvent::new(code, modifiers)))
+    }
+
+    #[test]
+    fn shift_makes_uppercase_letters() {
+        assert_eq!(
+            decode(KeyCode::Char('a'), KeyModifiers::NONE),
+            Some(Key::Char('a'))
+        );
+        assert_eq!(
+            decode(KeyCode::Char('A'), KeyModifiers::SHIFT),
+            Some(Key::Char('A'))
+        );
+        // reported with the unshifted letter by some terminals
+        assert_eq!(
+            decode(KeyCode::Char('a'), KeyModifiers::SHIFT),
+            Some(Key::Char('A'))
+        );
+        assert_eq!(
+            decode(KeyCode::Char('!'), KeyModifiers::SHIFT),
+            Some(Key::Char('!'))
+        );
+        assert_eq!(
+            decode(KeyCode::Char('é'), KeyModifiers::SHIFT),
+            Some(Key::Char('é'))
+        );
+        assert_eq!(
+            decode(KeyCode::BackTab, KeyModifiers::SHIFT),
+            Some(Key::BackTab)
+        );
+    }
+
+    #[test]
+    fn ctrl_and_alt_chords() {
+        assert_eq!(
+            decode(KeyCode::Char('w'), KeyModifiers::CONTROL),
+            Some(Key::Ctrl('w'))
+        );
+        assert_eq!(
+            decode(
+                KeyCode::Char('W'),
+                KeyModifiers::CONTROL | KeyModifiers::SHIFT
+            ),
+            Some(Key::Ctrl('w'))
+        );
+        assert_eq!(
+            decode(KeyCode::Char('['), KeyModifiers::CONTROL),
+            Some(Key::Ctrl('['))
+        );
+        assert_eq!(
+            decode(KeyCode::Char('x'), KeyModifiers::ALT),
+            Some(Key::Alt('x'))
+        );
+        assert_eq!(
+            decode(KeyCode::Char('X'), KeyModifiers::ALT),
+            Some(Key::Alt('X'))
+        );
+        // chords with other keys are not supported
+        assert_eq!(decode(KeyCode::Left, KeyModifiers::CONTROL), None);
+        assert_eq!(decode(KeyCode::Enter, KeyModifiers::ALT), None);
+    }
+
+    #[test]
+    fn ctrl_alt_non_letters_are_text() {
+        // AltGr on some platforms
+        let altgr = KeyModifiers::CONTROL | KeyModifiers::ALT;
+        assert_eq!(decode(KeyCode::Char('€'), altgr), Some(Key::Char('€')));
+        assert_eq!(decode(KeyCode::Char('@'), altgr), Some(Key::Char('@')));
+        assert_eq!(decode(KeyCode::Char('{'), altgr), Some(Key::Char('{')));
+        assert_eq!(decode(KeyCode::Char('a'), altgr), None);
+        assert_eq!(decode(KeyCode::Char('Z'), altgr), None);
+    }
+
+    #[test]
+    fn other_events() {
+        assert_eq!(decode(KeyCode::Enter, KeyModifiers::NONE), Some(Key::Enter));
+        assert_eq!(
+            decode(KeyCode::PageDown, KeyModifiers::NONE),
+            Some(Key::PageDown)
+        );
+        assert_eq!(decode(KeyCode::F(1), KeyModifiers::NONE), None);
+        let release = KeyEvent::new_with_kind(
+            KeyCode::Char('a'),
+            KeyModifiers::NONE,
+            KeyEventKind::Release,
+        );
+        assert_eq!(Key::from_event(&Event::Key(release)), None);
+        assert_eq!(Key::from_event(&Event::Resize(80, 24)), None);
     }
 }
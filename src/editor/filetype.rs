@@ -1,27 +1,25 @@
 use std::path::Path;
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+use super::syntax;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
 pub enum FileType {
     Rust,
+    // language defined by a syntax definition of the name
+    Syntax(&'static str),
+    #[default]
     Text,
 }
 
 impl FileType {
     pub fn from_filename(filename: &str) -> Self {
         let ext = Path::new(filename).extension();
-        match ext {
-            Some(osstr) => osstr.to_str().map_or(Self::Text, |s| match s {
-                "rs" => Self::Rust,
-                _ => Self::Text,
-            }),
-            None => Self::Text,
+        if ext.is_some_and(|ext| ext == "rs") {
+            return Self::Rust;
         }
-    }
-}
-
-impl Default for FileType {
-    fn default() -> Self {
-        Self::Text
+        syntax::find_by_filename(filename).map_or(Self::Text, |definition| {
+            Self::Syntax(definition.name.as_str())
+        })
     }
 }
 
@@ -29,6 +27,7 @@ impl std::fmt::Display for FileType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Rust => write!(f, "rust"),
+            Self::Syntax(name) => write!(f, "{}", name),
             Self::Text => write!(f, "text"),
         }
     }
@@ -1,69 +1,149 @@
 mod rust;
 mod search_highlight;
+mod selection_highlight;
+mod syntax_highlight;
+
+use std::rc::Rc;
 
 use super::{annotated_string::Annotation, buffer::Line, RenderContext};
 use crate::editor::filetype::FileType;
+use crate::editor::syntax;
 use search_highlight::SearchHighlighter;
+use selection_highlight::SelectionHighlighter;
+use syntax_highlight::SyntaxHighlighter;
 
-struct HighlightContext {
+// State of syntax highlighting carried over to the next line.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct HighlightContext {
     in_multiline_comment: bool,
     in_string_literal: bool,
+    // delimiter which opened the comment or string, as an index in the syntax definition
+    delimiter_idx: usize,
+    // nesting level of the block comment in languages allowing nested comments
+    comment_depth: usize,
+    // number of `#` of the raw string literal, such as 1 for `r#"..."#`
+    raw_string_hashes: Option<usize>,
 }
 
 trait Highlighter {
-    fn highlight_line(&mut self, line: &Line, ctx: &mut HighlightContext);
-    fn get_annotations(&self, line_idx: usize) -> Vec<Annotation>;
+    // Returns annotations of the line, updating `ctx` for the next line.
+    fn highlight_line(
+        &self,
+        line_idx: usize,
+        line: &Line,
+        ctx: &mut HighlightContext,
+    ) -> Vec<Annotation>;
 }
 
 fn create_syntax_highlighter(file_type: FileType) -> Option<Box<dyn Highlighter>> {
     match file_type {
-        FileType::Rust => Some(Box::new(rust::RustSyntaxHighlighter::new())),
+        FileType::Rust => Some(Box::new(rust::RustSyntaxHighlighter)),
+        FileType::Syntax(name) => syntax::find_by_name(name)
+            .map(|definition| Box::new(SyntaxHighlighter::new(definition)) as Box<dyn Highlighter>),
         FileType::Text => None,
     }
 }
 
+// Syntax highlighting states at the start of lines, kept across redraws
+// so that lines above the window are not highlighted again.
+#[derive(Default)]
+pub struct HighlightCache {
+    file_type: FileType,
+    // `states[i]` is the state at the start of line `i`
+    states: Vec<HighlightContext>,
+}
+
+impl HighlightCache {
+    // Drops states which may be changed by an edit of the line.
+    pub fn invalidate(&mut self, line_idx: usize) {
+        self.states.truncate(line_idx + 1);
+    }
+    pub fn clear(&mut self) {
+        self.states.clear();
+    }
+    // Returns the state at the start of line `line_idx`, highlighting
+    // lines from the last cached state. `get_line` returns lines of the buffer.
+    pub fn get_context(
+        &mut self,
+        line_idx: usize,
+        highlighter: &HighlighterBundler,
+        get_line: impl Fn(usize) -> Rc<Line>,
+    ) -> HighlightContext {
+        if self.file_type != highlighter.file_type {
+            self.file_type = highlighter.file_type;
+            self.states.clear();
+        }
+        let Some(syntax_highlighter) = highlighter.syntax_highlighter.as_ref() else {
+            return HighlightContext::default();
+        };
+        if self.states.is_empty() {
+            self.states.push(HighlightContext::default());
+        }
+        while self.states.len() <= line_idx {
+            let idx = self.states.len() - 1;
+            let mut ctx = self.states[idx];
+            syntax_highlighter.highlight_line(idx, &get_line(idx), &mut ctx);
+            self.states.push(ctx);
+        }
+        self.states[line_idx]
+    }
+    // Records the state at the start of line `line_idx` if it is next to cached ones.
+    pub fn record(&mut self, line_idx: usize, ctx: HighlightContext) {
+        if self.states.len() == line_idx {
+            self.states.push(ctx);
+        }
+    }
+}
+
 pub struct HighlighterBundler<'a> {
-    // line index to annotations of line.
+    file_type: FileType,
     syntax_highlighter: Option<Box<dyn Highlighter>>,
     search_highlighter: SearchHighlighter<'a>,
-    highlight_context: HighlightContext,
+    selection_highlighter: SelectionHighlighter<'a>,
+    // annotations of highlighted lines from `first_line_idx`
+    highlights: Vec<Vec<Annotation>>,
+    first_line_idx: usize,
 }
 
 impl<'a> HighlighterBundler<'a> {
-    pub fn new(context: &'a RenderContext) -> Self {
+    // Lines from `first_line_idx` are highlighted.
+    pub fn new(context: &'a RenderContext, first_line_idx: usize) -> Self {
         Self {
+            file_type: context.file_type,
             syntax_highlighter: create_syntax_highlighter(context.file_type),
             search_highlighter: SearchHighlighter::new(context),
-            highlight_context: HighlightContext {
-                in_multiline_comment: false,
-                in_string_literal: false,
-            },
+            selection_highlighter: SelectionHighlighter::new(context),
+            highlights: vec![],
+            first_line_idx,
         }
     }
 
-    pub fn highlight_line(&mut self, line: &Line) {
-        if self.syntax_highlighter.is_some() {
-            self.syntax_highlighter
-                .as_mut()
-                .unwrap()
-                .highlight_line(line, &mut self.highlight_context);
+    pub fn get_first_line_idx(&self) -> usize {
+        self.first_line_idx
+    }
+
+    // Lines must be highlighted in order from `get_first_line_idx()`,
+    // starting with the state given by `HighlightCache::get_context()`.
+    pub fn highlight_line(&mut self, line_idx: usize, line: &Line, ctx: &mut HighlightContext) {
+        let mut annotations = self.search_highlighter.highlight_line(line_idx, line, ctx);
+        if let Some(syntax_highlighter) = self.syntax_highlighter.as_ref() {
+            annotations.append(&mut syntax_highlighter.highlight_line(line_idx, line, ctx));
         }
-        self.search_highlighter
-            .highlight_line(line, &mut self.highlight_context);
+        // Later annotations take precedence, so selection is drawn on top.
+        annotations.append(
+            &mut self
+                .selection_highlighter
+                .highlight_line(line_idx, line, ctx),
+        );
+        self.highlights.push(annotations);
     }
 
     pub fn get_annotations(&self, line_idx: usize) -> Vec<Annotation> {
-        let mut annotations = self.search_highlighter.get_annotations(line_idx);
-        if self.syntax_highlighter.is_some() {
-            annotations.append(
-                &mut self
-                    .syntax_highlighter
-                    .as_ref()
-                    .unwrap()
-                    .get_annotations(line_idx),
-            );
-        }
-        annotations
+        line_idx
+            .checked_sub(self.first_line_idx)
+            .and_then(|idx| self.highlights.get(idx))
+            .cloned()
+            .unwrap_or_default()
     }
 }
 
@@ -2,8 +2,11 @@ use super::{HighlightContext, Highlighter};
 use crate::editor::annotated_string::{Annotation, Style};
 use crate::editor::buffer::Line;
 
-const KEYWORDS: [&str; 12] = [
-    "fn", "mod", "use", "pub", "if", "else", "for", "in", "struct", "impl", "let", "match",
+const KEYWORDS: [&str; 38] = [
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+    "return", "self", "Self", "static", "struct", "super", "trait", "type", "union", "unsafe",
+    "use", "where", "while", "yield",
 ];
 
 const TYPE_NAMES: [&str; 21] = [
@@ -21,94 +24,488 @@ const VARIANT_NAMES: [&str; 6] = [
     "Ok", "Err", // Result
 ];
 
-pub struct RustSyntaxHighlighter {
-    highlights: Vec<Vec<Annotation>>,
-}
-
-impl RustSyntaxHighlighter {
-    pub fn new() -> Self {
-        Self {
-            highlights: Vec::new(),
-        }
-    }
-}
+pub struct RustSyntaxHighlighter;
 
-fn is_number(word: &str) -> bool {
-    word.chars().all(|char| char.is_ascii_digit())
+fn is_ident_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
 }
 
-fn is_keyword(word: &str) -> bool {
-    KEYWORDS.contains(&word)
+fn is_ident_continue(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
 }
 
-fn is_type_name(word: &str) -> bool {
-    TYPE_NAMES.contains(&word)
+// Returns byte length of the longest prefix of `s` whose characters satisfy `pred`.
+fn span(s: &str, pred: impl Fn(char) -> bool) -> usize {
+    s.find(|c| !pred(c)).unwrap_or(s.len())
 }
 
-fn is_variant_name(word: &str) -> bool {
-    VARIANT_NAMES.contains(&word)
+// Splits a line into tokens, annotating them.
+// Tokens continued to the next line are recorded in `HighlightContext`.
+struct Lexer<'a> {
+    text: &'a str,
+    pos: usize,
+    annotations: Vec<Annotation>,
 }
 
-fn is_single_line_comment_start(word: &str) -> bool {
-    word.starts_with("//")
+impl<'a> Lexer<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+    fn annotate(&mut self, style: Style, start: usize, end: usize) {
+        self.annotations.push(Annotation::new(style, start, end));
+        self.pos = end;
+    }
+    fn next_token(&mut self, ctx: &mut HighlightContext) {
+        let start = self.pos;
+        let rest = self.rest();
+        if rest.starts_with("//") {
+            self.annotate(Style::Comment, start, self.text.len());
+        } else if rest.starts_with("/*") {
+            ctx.in_multiline_comment = true;
+            ctx.comment_depth = 1;
+            self.pos += 2;
+            self.block_comment(start, ctx);
+        } else if let Some(len) = raw_string_prefix_len(rest) {
+            // number of `#` between `r` and `"`
+            let hashes = rest[..len].bytes().filter(|b| *b == b'#').count();
+            ctx.in_string_literal = true;
+            ctx.raw_string_hashes = Some(hashes);
+            self.pos += len;
+            self.string(start, ctx);
+        } else if rest.starts_with('"') || rest.starts_with("b\"") || rest.starts_with("c\"") {
+            ctx.in_string_literal = true;
+            ctx.raw_string_hashes = None;
+            self.pos += rest.find('"').unwrap_or_default() + 1;
+            self.string(start, ctx);
+        } else if rest.starts_with('\'') || rest.starts_with("b'") {
+            self.quote(start);
+        } else if rest.starts_with("#[") || rest.starts_with("#![") {
+            self.attribute(start);
+        } else if rest.starts_with(|c: char| c.is_ascii_digit()) {
+            let len = number_len(rest);
+            self.annotate(Style::Digit, start, start + len);
+        } else if rest.starts_with(is_ident_start) {
+            self.ident(start);
+        } else {
+            self.pos += rest.chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    // Annotates a block comment from `start`, scanning from the current position.
+    // Block comments may be nested.
+    fn block_comment(&mut self, start: usize, ctx: &mut HighlightContext) {
+        while ctx.comment_depth > 0 {
+            let rest = self.rest();
+            let open = rest.find("/*");
+            let close = rest.find("*/");
+            match (open, close) {
+                (Some(open), Some(close)) if open < close => {
+                    ctx.comment_depth += 1;
+                    self.pos += open + 2;
+                }
+                (_, Some(close)) => {
+                    ctx.comment_depth -= 1;
+                    self.pos += close + 2;
+                }
+                (Some(open), None) => {
+                    ctx.comment_depth += 1;
+                    self.pos += open + 2;
+                }
+                (None, None) => {
+                    self.annotate(Style::Comment, start, self.text.len());
+                    return;
+                }
+            }
+        }
+        ctx.in_multiline_comment = false;
+        self.annotate(Style::Comment, start, self.pos);
+    }
+    // Annotates a string literal from `start`, scanning from the current position.
+    fn string(&mut self, start: usize, ctx: &mut HighlightContext) {
+        let rest = self.rest();
+        let end = match ctx.raw_string_hashes {
+            Some(hashes) => {
+                let close = format!("\"{}", "#".repeat(hashes));
+                rest.find(&close).map(|idx| idx + close.len())
+            }
+            None => {
+                let mut chars = rest.char_indices();
+                let mut end = None;
+                while let Some((idx, c)) = chars.next() {
+                    match c {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '"' => {
+                            end = Some(idx + 1);
+                            break;
+                        }
+                        _ => {}
+                    }
+                }
+                end
+            }
+        };
+        match end {
+            Some(end) => {
+                ctx.in_string_literal = false;
+                self.annotate(Style::String, start, self.pos + end);
+            }
+            // string literals may span lines
+            None => self.annotate(Style::String, start, self.text.len()),
+        }
+    }
+    // Annotates a character literal such as `'a'` or `b'\n'`, or a lifetime such as `'a`.
+    fn quote(&mut self, start: usize) {
+        let quote = self.pos + self.rest().find('\'').unwrap_or_default();
+        let body = &self.text[quote + 1..];
+        if let Some(escaped) = body.strip_prefix('\\') {
+            // `'\''`, `'\n'`, `'\u{1F600}'` and so on
+            let len = escaped.chars().next().map_or(0, char::len_utf8);
+            let len = len
+                + escaped[len..]
+                    .find('\'')
+                    .map_or(escaped.len() - len, |idx| idx + 1);
+            self.annotate(Style::Char, start, quote + 2 + len);
+            return;
+        }
+        let mut chars = body.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), Some('\'')) => {
+                self.annotate(Style::Char, start, quote + 2 + c.len_utf8());
+            }
+            (Some(c), _) if is_ident_start(c) && quote == start => {
+                let len = span(body, is_ident_continue);
+                self.annotate(Style::Lifetime, start, quote + 1 + len);
+            }
+            _ => self.pos = quote + 1,
+        }
+    }
+    // Annotates an attribute such as `#[derive(Debug)]` up to the matching bracket.
+    fn attribute(&mut self, start: usize) {
+        let mut depth = 0;
+        let mut end = self.text.len();
+        let mut in_string = false;
+        let mut chars = self.rest().char_indices();
+        while let Some((idx, c)) = chars.next() {
+            match c {
+                '\\' if in_string => {
+                    chars.next();
+                }
+                '"' => in_string = !in_string,
+                '[' if !in_string => depth += 1,
+                ']' if !in_string => {
+                    depth -= 1;
+                    if depth == 0 {
+                        end = self.pos + idx + 1;
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.annotate(Style::Attribute, start, end);
+    }
+    fn ident(&mut self, start: usize) {
+        let rest = self.rest();
+        // raw identifier such as `r#type`
+        let prefix = if rest.starts_with("r#") && rest[2..].starts_with(is_ident_start) {
+            2
+        } else {
+            0
+        };
+        let len = prefix + span(&rest[prefix..], is_ident_continue);
+        let word = &rest[prefix..len];
+        let after = &rest[len..];
+        if after.starts_with('!') && !after.starts_with("!=") {
+            self.annotate(Style::Macro, start, start + len + 1);
+            return;
+        }
+        let style = if prefix > 0 {
+            None
+        } else if KEYWORDS.contains(&word) {
+            Some(Style::Keywords)
+        } else if TYPE_NAMES.contains(&word) {
+            Some(Style::TypeName)
+        } else if VARIANT_NAMES.contains(&word) {
+            Some(Style::VarinatName)
+        } else {
+            None
+        };
+        match style {
+            Some(style) => self.annotate(style, start, start + len),
+            None => self.pos += len,
+        }
+    }
 }
 
-fn is_multi_line_comment_start(word: &str) -> bool {
-    word.starts_with("/*")
+// Returns byte length of the prefix of a raw string literal,
+// such as `r#"` or `br"`, if `s` starts with it.
+fn raw_string_prefix_len(s: &str) -> Option<usize> {
+    let prefix = if s.starts_with("br") || s.starts_with("cr") {
+        2
+    } else if s.starts_with('r') {
+        1
+    } else {
+        return None;
+    };
+    let hashes = span(&s[prefix..], |c| c == '#');
+    s[prefix + hashes..]
+        .starts_with('"')
+        .then_some(prefix + hashes + 1)
 }
 
-fn is_multi_line_comment_end(word: &str) -> bool {
-    word.starts_with("*/")
+// Returns byte length of the number literal at the start of `s`,
+// such as `0xFF_u8`, `1_000` or `1.5e3f64`.
+fn number_len(s: &str) -> usize {
+    let digits = |radix: u32| move |c: char| c.is_digit(radix) || c == '_';
+    let radix = match s.get(..2) {
+        Some("0x") => 16,
+        Some("0o") => 8,
+        Some("0b") => 2,
+        _ => 10,
+    };
+    let mut len = if radix == 10 {
+        span(s, digits(10))
+    } else {
+        2 + span(&s[2..], digits(radix))
+    };
+    if radix == 10 {
+        // `1.5` is a float, while `1..2` is a range and `1.max(2)` is a method call
+        let rest = &s[len..];
+        if rest.starts_with('.') && rest[1..].starts_with(|c: char| c.is_ascii_digit()) {
+            len += 1 + span(&rest[1..], digits(10));
+        }
+        let rest = &s[len..];
+        if rest.starts_with(['e', 'E']) {
+            let sign = usize::from(rest[1..].starts_with(['+', '-']));
+            if rest[1 + sign..].starts_with(|c: char| c.is_ascii_digit()) {
+                len += 1 + sign + span(&rest[1 + sign..], digits(10));
+            }
+        }
+    }
+    // type suffix such as `u8` or `f64`
+    len + span(&s[len..], is_ident_continue)
 }
 
 impl Highlighter for RustSyntaxHighlighter {
-    fn highlight_line(&mut self, line: &Line, ctx: &mut HighlightContext) {
-        let mut annotations = vec![];
-        let mut in_sigle_line_comment = false;
-        for (idx, word) in line.split_word_bound_indices() {
-            let remainder = &line.get_raw_str()[idx..];
-            let annotation = if ctx.in_string_literal {
-                if remainder.chars().next().unwrap() == '"' {
-                    ctx.in_string_literal = false;
-                    Some(Annotation::new(Style::String, idx, idx + 1))
-                } else {
-                    Some(Annotation::new(Style::String, idx, idx + word.len()))
-                }
-            } else if ctx.in_multiline_comment {
-                if is_multi_line_comment_end(remainder) {
-                    ctx.in_multiline_comment = false;
-                    Some(Annotation::new(Style::Comment, idx, idx + 2))
-                } else {
-                    Some(Annotation::new(Style::Comment, idx, idx + word.len()))
-                }
-            } else if is_multi_line_comment_start(remainder) {
-                ctx.in_multiline_comment = true;
-                Some(Annotation::new(Style::Comment, idx, idx + 2))
-            } else if remainder.chars().next().unwrap() == '"' {
-                ctx.in_string_literal = true;
-                Some(Annotation::new(Style::String, idx, idx + 2))
-            } else if is_single_line_comment_start(remainder) {
-                in_sigle_line_comment = true;
-                Some(Annotation::new(Style::Comment, idx, idx + line.byte_len()))
-            } else if is_number(word) {
-                Some(Annotation::new(Style::Digit, idx, idx + word.len()))
-            } else if is_keyword(word) {
-                Some(Annotation::new(Style::Keywords, idx, idx + word.len()))
-            } else if is_type_name(word) {
-                Some(Annotation::new(Style::TypeName, idx, idx + word.len()))
-            } else if is_variant_name(word) {
-                Some(Annotation::new(Style::VarinatName, idx, idx + word.len()))
-            } else {
-                None
-            };
-            let _ = annotation.map_or((), |annot| annotations.push(annot));
-            if in_sigle_line_comment {
-                break;
-            }
+    fn highlight_line(
+        &self,
+        _line_idx: usize,
+        line: &Line,
+        ctx: &mut HighlightContext,
+    ) -> Vec<Annotation> {
+        let mut lexer = Lexer {
+            text: line.get_raw_str(),
+            pos: 0,
+            annotations: vec![],
+        };
+        // comment or string continued from the previous line
+        if ctx.in_multiline_comment {
+            lexer.block_comment(0, ctx);
+        } else if ctx.in_string_literal {
+            lexer.string(0, ctx);
+        }
+        while lexer.pos < lexer.text.len() {
+            lexer.next_token(ctx);
         }
-        self.highlights.push(annotations);
+        lexer.annotations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Highlights lines in order, carrying the context over.
+    fn highlight_lines(lines: &[&str]) -> Vec<Vec<Annotation>> {
+        let mut ctx = HighlightContext::default();
+        lines
+            .iter()
+            .enumerate()
+            .map(|(idx, line)| {
+                RustSyntaxHighlighter.highlight_line(idx, &Line::from_str(line), &mut ctx)
+            })
+            .collect()
+    }
+
+    fn highlight(line: &str) -> Vec<Annotation> {
+        highlight_lines(&[line]).remove(0)
+    }
+
+    // Builds annotations of `tokens`, which appear in `line` in this order.
+    fn tokens(line: &str, tokens: &[(Style, &str)]) -> Vec<Annotation> {
+        let mut pos = 0;
+        tokens
+            .iter()
+            .map(|(style, token)| {
+                let start = pos + line[pos..].find(token).expect("token should be in line");
+                pos = start + token.len();
+                Annotation::new(*style, start, pos)
+            })
+            .collect()
     }
-    fn get_annotations(&self, line_idx: usize) -> Vec<Annotation> {
-        self.highlights[line_idx].clone()
+
+    #[test]
+    fn char_and_lifetime() {
+        let line = "fn f<'a>(x: &'a str) -> char { 'a' }";
+        assert_eq!(
+            highlight(line),
+            tokens(
+                line,
+                &[
+                    (Style::Keywords, "fn"),
+                    (Style::Lifetime, "'a"),
+                    (Style::Lifetime, "'a"),
+                    (Style::TypeName, "str"),
+                    (Style::TypeName, "char"),
+                    (Style::Char, "'a'"),
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn escaped_chars() {
+        let line = r"'\'' '\n' b'\\' '\u{1F600}' 'é'";
+        assert_eq!(
+            highlight(line),
+            tokens(
+                line,
+                &[
+                    (Style::Char, r"'\''"),
+                    (Style::Char, r"'\n'"),
+                    (Style::Char, r"b'\\'"),
+                    (Style::Char, r"'\u{1F600}'"),
+                    (Style::Char, "'é'"),
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn string_escapes() {
+        let line = r#"let s = "a \"quoted\" \\" + b"x";"#;
+        assert_eq!(
+            highlight(line),
+            tokens(
+                line,
+                &[
+                    (Style::Keywords, "let"),
+                    (Style::String, r#""a \"quoted\" \\""#),
+                    (Style::String, r#"b"x""#),
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn raw_strings() {
+        let line = r####"r"a\" r#"b "quoted" c"# br##"d"#e"##"####;
+        assert_eq!(
+            highlight(line),
+            tokens(
+                line,
+                &[
+                    (Style::String, r#"r"a\""#),
+                    (Style::String, r##"r#"b "quoted" c"#"##),
+                    (Style::String, r###"br##"d"#e"##"###),
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn raw_string_across_lines() {
+        let lines = [r##"let s = r#"a"##, r#""b""#, r##"c"# + 1;"##];
+        let result = highlight_lines(&lines);
+        assert_eq!(
+            result[0],
+            tokens(
+                lines[0],
+                &[(Style::Keywords, "let"), (Style::String, r##"r#"a"##)]
+            )
+        );
+        assert_eq!(result[1], tokens(lines[1], &[(Style::String, r#""b""#)]));
+        assert_eq!(
+            result[2],
+            tokens(
+                lines[2],
+                &[(Style::String, r##"c"#"##), (Style::Digit, "1")]
+            )
+        );
+    }
+
+    #[test]
+    fn nested_block_comments() {
+        let line = "a /* b /* c */ d */ e";
+        assert_eq!(
+            highlight(line),
+            tokens(line, &[(Style::Comment, "/* b /* c */ d */")])
+        );
+        let lines = ["/* a /* b", "c */ d */ fn"];
+        let result = highlight_lines(&lines);
+        assert_eq!(result[0], tokens(lines[0], &[(Style::Comment, lines[0])]));
+        assert_eq!(
+            result[1],
+            tokens(
+                lines[1],
+                &[(Style::Comment, "c */ d */"), (Style::Keywords, "fn")]
+            )
+        );
+    }
+
+    #[test]
+    fn number_suffixes() {
+        let line = "0xFF_u8 1_000 1.5e3f64 2E-3 0b1010i32 0o77 7usize";
+        assert_eq!(
+            highlight(line),
+            tokens(
+                line,
+                &[
+                    (Style::Digit, "0xFF_u8"),
+                    (Style::Digit, "1_000"),
+                    (Style::Digit, "1.5e3f64"),
+                    (Style::Digit, "2E-3"),
+                    (Style::Digit, "0b1010i32"),
+                    (Style::Digit, "0o77"),
+                    (Style::Digit, "7usize"),
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn numbers_before_dots() {
+        // ranges and method calls are not floats
+        let line = "1..2 1.max(2) t.0";
+        assert_eq!(
+            highlight(line),
+            tokens(
+                line,
+                &[
+                    (Style::Digit, "1"),
+                    (Style::Digit, "2"),
+                    (Style::Digit, "1"),
+                    (Style::Digit, "2"),
+                    (Style::Digit, "0"),
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn macros_attributes_and_raw_identifiers() {
+        let line = r#"#[cfg(feature = "]")] println!("{}", r#type != x);"#;
+        assert_eq!(
+            highlight(line),
+            tokens(
+                line,
+                &[
+                    (Style::Attribute, r#"#[cfg(feature = "]")]"#),
+                    (Style::Macro, "println!"),
+                    (Style::String, r#""{}""#),
+                ]
+            )
+        );
     }
 }
@@ -4,36 +4,44 @@ use crate::editor::buffer::Line;
 use crate::editor::RenderContext;
 
 pub struct SearchHighlighter<'a> {
-    highlights: Vec<Vec<Annotation>>,
     render_context: &'a RenderContext,
 }
 
 impl<'a> SearchHighlighter<'a> {
     pub fn new(render_context: &'a RenderContext) -> Self {
-        Self {
-            highlights: Vec::new(),
-            render_context,
-        }
+        Self { render_context }
     }
-    fn highlight_search(&self, line: &Line, annotations: &mut Vec<Annotation>) {
+    fn highlight_search(&self, line_idx: usize, line: &Line, annotations: &mut Vec<Annotation>) {
         // search result annotations
         let search_hits = match self.render_context.get_search_highlight_pattern() {
             Some(s) => line.search_all_occurence(s),
             None => vec![],
         };
         for (match_start, match_end) in search_hits {
+            if match_start == match_end {
+                // nothing to highlight for empty match such as `^`
+                continue;
+            }
             annotations.push(Annotation::new(Style::SearchHit, match_start, match_end));
         }
+        // match being confirmed is emphasized over other matches
+        if let Some((match_line_idx, start, end)) = self.render_context.current_match {
+            if match_line_idx == line_idx {
+                annotations.push(Annotation::new(Style::CurrentMatch, start, end));
+            }
+        }
     }
 }
 
 impl<'a> Highlighter for SearchHighlighter<'a> {
-    fn highlight_line(&mut self, line: &Line, _ctx: &mut HighlightContext) {
+    fn highlight_line(
+        &self,
+        line_idx: usize,
+        line: &Line,
+        _ctx: &mut HighlightContext,
+    ) -> Vec<Annotation> {
         let mut annotations = vec![];
-        self.highlight_search(line, &mut annotations);
-        self.highlights.push(annotations);
-    }
-    fn get_annotations(&self, line_idx: usize) -> Vec<Annotation> {
-        self.highlights[line_idx].clone()
+        self.highlight_search(line_idx, line, &mut annotations);
+        annotations
     }
 }
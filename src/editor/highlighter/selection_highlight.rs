@@ -0,0 +1,39 @@
+use super::{HighlightContext, Highlighter};
+use crate::editor::annotated_string::{Annotation, Style};
+use crate::editor::buffer::Line;
+use crate::editor::RenderContext;
+
+pub struct SelectionHighlighter<'a> {
+    render_context: &'a RenderContext,
+}
+
+impl<'a> SelectionHighlighter<'a> {
+    pub fn new(render_context: &'a RenderContext) -> Self {
+        Self { render_context }
+    }
+}
+
+impl<'a> Highlighter for SelectionHighlighter<'a> {
+    fn highlight_line(
+        &self,
+        line_idx: usize,
+        line: &Line,
+        _ctx: &mut HighlightContext,
+    ) -> Vec<Annotation> {
+        let mut annotations = vec![];
+        let range = self
+            .render_context
+            .selection
+            .and_then(|selection| selection.grapheme_range(line_idx, line));
+        if let Some((start, end)) = range {
+            if start < end {
+                annotations.push(Annotation::new(
+                    Style::Selection,
+                    line.to_byte_idx(start),
+                    line.to_byte_idx(end),
+                ));
+            }
+        }
+        annotations
+    }
+}
@@ -0,0 +1,418 @@
+use super::{HighlightContext, Highlighter};
+use crate::editor::annotated_string::{Annotation, Style};
+use crate::editor::buffer::Line;
+use crate::editor::syntax::{NumberRule, SyntaxDefinition};
+
+// Highlighter for languages given by syntax definitions.
+pub struct SyntaxHighlighter {
+    definition: &'static SyntaxDefinition,
+}
+
+impl SyntaxHighlighter {
+    pub fn new(definition: &'static SyntaxDefinition) -> Self {
+        Self { definition }
+    }
+    fn is_word_char(&self, c: char) -> bool {
+        c.is_alphanumeric() || c == '_' || self.definition.word_chars.contains(&c)
+    }
+    fn word_style(&self, word: &str) -> Option<Style> {
+        let contains = |words: &Vec<String>| words.iter().any(|w| w == word);
+        if contains(&self.definition.keywords) {
+            Some(Style::Keywords)
+        } else if contains(&self.definition.types) {
+            Some(Style::TypeName)
+        } else if contains(&self.definition.constants) {
+            Some(Style::VarinatName)
+        } else {
+            None
+        }
+    }
+    // Annotates a block comment from `start` and returns the end of it.
+    // The closing delimiter is searched from `from`.
+    fn close_comment(
+        &self,
+        text: &str,
+        start: usize,
+        from: usize,
+        ctx: &mut HighlightContext,
+        annotations: &mut Vec<Annotation>,
+    ) -> usize {
+        let close = self
+            .definition
+            .block_comments
+            .get(ctx.delimiter_idx)
+            .map(|(_, close)| close.as_str());
+        let end = match close.and_then(|close| Some(from + text[from..].find(close)? + close.len()))
+        {
+            Some(end) => {
+                ctx.in_multiline_comment = false;
+                end
+            }
+            None if close.is_none() => {
+                // definition has been changed
+                ctx.in_multiline_comment = false;
+                text.len()
+            }
+            None => text.len(),
+        };
+        annotations.push(Annotation::new(Style::Comment, start, end));
+        end
+    }
+    // Annotates a string literal from `start` and returns the end of it.
+    // The closing delimiter is searched from `from`.
+    fn close_string(
+        &self,
+        text: &str,
+        start: usize,
+        from: usize,
+        ctx: &mut HighlightContext,
+        annotations: &mut Vec<Annotation>,
+    ) -> usize {
+        let mut end = text.len();
+        ctx.in_string_literal = false;
+        if let Some(rule) = self.definition.strings.get(ctx.delimiter_idx) {
+            let mut chars = text[from..].char_indices();
+            let mut closed = false;
+            while let Some((idx, c)) = chars.next() {
+                if text[from + idx..].starts_with(rule.close.as_str()) {
+                    end = from + idx + rule.close.len();
+                    closed = true;
+                    break;
+                }
+                if c == '\\' && !rule.raw {
+                    chars.next();
+                }
+            }
+            ctx.in_string_literal = !closed && rule.multiline;
+        }
+        annotations.push(Annotation::new(Style::String, start, end));
+        end
+    }
+}
+
+// Returns byte length of the longest prefix of `s` whose characters satisfy `pred`.
+fn span(s: &str, pred: impl Fn(char) -> bool) -> usize {
+    s.find(|c| !pred(c)).unwrap_or(s.len())
+}
+
+// Returns byte length of the number literal at the start of `s`.
+fn number_len(s: &str, rule: &NumberRule) -> usize {
+    let digits = |radix: u32| move |c: char| c.is_digit(radix) || Some(c) == rule.separator;
+    let mut chars = s.chars();
+    let prefix = (chars.next(), chars.next().map(|c| c.to_ascii_lowercase()));
+    let radix = match prefix {
+        (Some('0'), Some('x')) if rule.hex => Some(16),
+        (Some('0'), Some('b')) if rule.binary => Some(2),
+        (Some('0'), Some('o')) if rule.octal => Some(8),
+        _ => None,
+    };
+    let mut len = match radix {
+        Some(radix) => 2 + span(&s[2..], digits(radix)),
+        None => span(s, digits(10)),
+    };
+    if radix.is_none() && rule.float {
+        let rest = &s[len..];
+        if rest.starts_with('.') && rest[1..].starts_with(|c: char| c.is_ascii_digit()) {
+            len += 1 + span(&rest[1..], digits(10));
+        }
+        let rest = &s[len..];
+        if rest.starts_with(['e', 'E']) {
+            let sign = usize::from(rest[1..].starts_with(['+', '-']));
+            if rest[1 + sign..].starts_with(|c: char| c.is_ascii_digit()) {
+                len += 1 + sign + span(&rest[1 + sign..], digits(10));
+            }
+        }
+    }
+    // suffix such as `u` in `10u` or `f` in `1.5f`
+    len + span(&s[len..], |c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+impl Highlighter for SyntaxHighlighter {
+    fn highlight_line(
+        &self,
+        _line_idx: usize,
+        line: &Line,
+        ctx: &mut HighlightContext,
+    ) -> Vec<Annotation> {
+        let text = line.get_raw_str();
+        let mut annotations = vec![];
+        // comment or string continued from the previous line
+        let mut idx = if ctx.in_multiline_comment {
+            self.close_comment(text, 0, 0, ctx, &mut annotations)
+        } else if ctx.in_string_literal {
+            self.close_string(text, 0, 0, ctx, &mut annotations)
+        } else {
+            let trimmed = text.trim_start();
+            if let Some(rule) = self
+                .definition
+                .line_rules
+                .iter()
+                .find(|rule| trimmed.starts_with(rule.prefix.as_str()))
+            {
+                annotations.push(Annotation::new(rule.style, 0, text.len()));
+                return annotations;
+            }
+            0
+        };
+        while idx < text.len() {
+            let rest = &text[idx..];
+            if self
+                .definition
+                .line_comments
+                .iter()
+                .any(|comment| rest.starts_with(comment.as_str()))
+            {
+                annotations.push(Annotation::new(Style::Comment, idx, text.len()));
+                break;
+            }
+            // the longest delimiter is taken, such as `"""` rather than `"`
+            let block_comment = (self.definition.block_comments.iter().enumerate())
+                .filter(|(_, (open, _))| rest.starts_with(open.as_str()))
+                .max_by_key(|(_, (open, _))| open.len());
+            if let Some((delimiter_idx, (open, _))) = block_comment {
+                ctx.in_multiline_comment = true;
+                ctx.delimiter_idx = delimiter_idx;
+                idx = self.close_comment(text, idx, idx + open.len(), ctx, &mut annotations);
+                continue;
+            }
+            let string = (self.definition.strings.iter().enumerate())
+                .filter(|(_, rule)| rest.starts_with(rule.open.as_str()))
+                .max_by_key(|(_, rule)| rule.open.len());
+            if let Some((delimiter_idx, rule)) = string {
+                ctx.in_string_literal = true;
+                ctx.delimiter_idx = delimiter_idx;
+                idx = self.close_string(text, idx, idx + rule.open.len(), ctx, &mut annotations);
+                continue;
+            }
+            let c = rest.chars().next().unwrap_or_default();
+            if let Some(rule) = self.definition.numbers.as_ref() {
+                if c.is_ascii_digit() {
+                    let len = number_len(rest, rule);
+                    annotations.push(Annotation::new(Style::Digit, idx, idx + len));
+                    idx += len;
+                    continue;
+                }
+            }
+            if self.is_word_char(c) {
+                let len = span(rest, |c| self.is_word_char(c));
+                if let Some(style) = self.word_style(&rest[..len]) {
+                    annotations.push(Annotation::new(style, idx, idx + len));
+                }
+                idx += len;
+                continue;
+            }
+            idx += c.len_utf8();
+        }
+        annotations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEFINITION: &str = "\
+name = test
+keywords = if return
+types = int
+constants = NULL
+word_chars = $
+line_comment = //
+block_comment = /* */
+block_comment = /** **/
+string = \" \"
+string = ` ` raw
+string = \"\"\" \"\"\" multiline
+numbers = hex float
+number_separator = _
+line_start = # keyword
+";
+
+    // Highlights lines in order, carrying the context over.
+    fn highlight_lines(lines: &[&str]) -> Vec<Vec<Annotation>> {
+        let definition = Box::leak(Box::new(SyntaxDefinition::parse(DEFINITION).unwrap()));
+        let highlighter = SyntaxHighlighter::new(definition);
+        let mut ctx = HighlightContext::default();
+        lines
+            .iter()
+            .enumerate()
+            .map(|(idx, line)| highlighter.highlight_line(idx, &Line::from_str(line), &mut ctx))
+            .collect()
+    }
+
+    fn highlight(line: &str) -> Vec<Annotation> {
+        highlight_lines(&[line]).remove(0)
+    }
+
+    // Builds annotations of `tokens`, which appear in `line` in this order.
+    fn tokens(line: &str, tokens: &[(Style, &str)]) -> Vec<Annotation> {
+        let mut pos = 0;
+        tokens
+            .iter()
+            .map(|(style, token)| {
+                let start = pos + line[pos..].find(token).expect("token should be in line");
+                pos = start + token.len();
+                Annotation::new(*style, start, pos)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn words() {
+        let line = "if int NULL return $if ifx return_";
+        assert_eq!(
+            highlight(line),
+            tokens(
+                line,
+                &[
+                    (Style::Keywords, "if"),
+                    (Style::TypeName, "int"),
+                    (Style::VarinatName, "NULL"),
+                    (Style::Keywords, "return"),
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn comments() {
+        let line = r#"if // return "a""#;
+        assert_eq!(
+            highlight(line),
+            tokens(
+                line,
+                &[
+                    (Style::Keywords, "if"),
+                    (Style::Comment, r#"// return "a""#)
+                ]
+            )
+        );
+        let line = "/* if */ int /** a */ b **/";
+        assert_eq!(
+            highlight(line),
+            tokens(
+                line,
+                &[
+                    (Style::Comment, "/* if */"),
+                    (Style::TypeName, "int"),
+                    (Style::Comment, "/** a */ b **/"),
+                ]
+            )
+        );
+        let lines = ["int /* a", "if", "b */ if"];
+        let result = highlight_lines(&lines);
+        assert_eq!(
+            result[0],
+            tokens(
+                lines[0],
+                &[(Style::TypeName, "int"), (Style::Comment, "/* a")]
+            )
+        );
+        assert_eq!(result[1], tokens(lines[1], &[(Style::Comment, "if")]));
+        assert_eq!(
+            result[2],
+            tokens(
+                lines[2],
+                &[(Style::Comment, "b */"), (Style::Keywords, "if")]
+            )
+        );
+    }
+
+    #[test]
+    fn strings() {
+        let line = r#""a \" if" if `raw \` if"#;
+        assert_eq!(
+            highlight(line),
+            tokens(
+                line,
+                &[
+                    (Style::String, r#""a \" if""#),
+                    (Style::Keywords, "if"),
+                    (Style::String, r"`raw \`"),
+                    (Style::Keywords, "if"),
+                ]
+            )
+        );
+        // strings which are not multiline end at the end of line
+        let lines = [r#"int "a"#, "if"];
+        let result = highlight_lines(&lines);
+        assert_eq!(
+            result[0],
+            tokens(
+                lines[0],
+                &[(Style::TypeName, "int"), (Style::String, "\"a")]
+            )
+        );
+        assert_eq!(result[1], tokens(lines[1], &[(Style::Keywords, "if")]));
+        let lines = [r#"x = """a"#, "if", r#"b""" if"#];
+        let result = highlight_lines(&lines);
+        assert_eq!(result[0], tokens(lines[0], &[(Style::String, r#""""a"#)]));
+        assert_eq!(result[1], tokens(lines[1], &[(Style::String, "if")]));
+        assert_eq!(
+            result[2],
+            tokens(
+                lines[2],
+                &[(Style::String, r#"b""""#), (Style::Keywords, "if")]
+            )
+        );
+    }
+
+    #[test]
+    fn line_rules() {
+        let line = "  #include <stdio.h> // if";
+        assert_eq!(highlight(line), tokens(line, &[(Style::Keywords, line)]));
+        // not in a multiline comment
+        let lines = ["/*", "#if */"];
+        assert_eq!(
+            highlight_lines(&lines)[1],
+            tokens(lines[1], &[(Style::Comment, "#if */")])
+        );
+    }
+
+    #[test]
+    fn numbers() {
+        let line = "x1 1_000 0xFF 1.5e3 if";
+        assert_eq!(
+            highlight(line),
+            tokens(
+                line,
+                &[
+                    (Style::Digit, "1_000"),
+                    (Style::Digit, "0xFF"),
+                    (Style::Digit, "1.5e3"),
+                    (Style::Keywords, "if"),
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn number_lengths() {
+        let all = NumberRule {
+            hex: true,
+            binary: true,
+            octal: true,
+            float: true,
+            separator: Some('_'),
+        };
+        let decimal = NumberRule::default();
+        assert_eq!(number_len("123 ", &decimal), 3);
+        assert_eq!(number_len("1_000", &all), 5);
+        assert_eq!(number_len("1'000", &all), 1);
+        assert_eq!(number_len("0xFF_FF;", &all), 7);
+        assert_eq!(number_len("0b1010)", &all), 6);
+        assert_eq!(number_len("0o17,", &all), 4);
+        assert_eq!(number_len("0X1f", &all), 4);
+        // floats
+        assert_eq!(number_len("1.5", &all), 3);
+        assert_eq!(number_len("1.5", &decimal), 1);
+        assert_eq!(number_len("1.5e-3,", &all), 6);
+        assert_eq!(number_len("2E+10 ", &all), 5);
+        assert_eq!(number_len("1..2", &all), 1);
+        assert_eq!(number_len("1.max", &all), 1);
+        // suffixes
+        assert_eq!(number_len("10u;", &decimal), 3);
+        assert_eq!(number_len("1.5f)", &all), 4);
+        assert_eq!(number_len("0xFFul", &all), 6);
+    }
+}
@@ -0,0 +1,706 @@
+use super::editor_command::{Direction, SplitDirection};
+use super::terminal::{Position, Size};
+use super::window::Window;
+
+// Minimum height and width of a window including its status line and separator.
+const MIN_WINDOW_SIZE: usize = 2;
+
+enum LayoutNode {
+    Leaf {
+        id: usize,
+        window: Box<Window>,
+    },
+    // `sizes` are heights of children for horizontal split,
+    // and widths for vertical split.
+    Split {
+        direction: SplitDirection,
+        children: Vec<LayoutNode>,
+        sizes: Vec<usize>,
+    },
+}
+
+impl LayoutNode {
+    fn contains(&self, target: usize) -> bool {
+        match self {
+            Self::Leaf { id, .. } => *id == target,
+            Self::Split { children, .. } => children.iter().any(|child| child.contains(target)),
+        }
+    }
+    fn find(&self, target: usize) -> Option<&Window> {
+        match self {
+            Self::Leaf { id, window } => (*id == target).then_some(window.as_ref()),
+            Self::Split { children, .. } => children.iter().find_map(|child| child.find(target)),
+        }
+    }
+    fn find_mut(&mut self, target: usize) -> Option<&mut Window> {
+        match self {
+            Self::Leaf { id, window } => (*id == target).then_some(window.as_mut()),
+            Self::Split { children, .. } => {
+                children.iter_mut().find_map(|child| child.find_mut(target))
+            }
+        }
+    }
+    fn collect<'a>(&'a self, windows: &mut Vec<(usize, &'a Window)>) {
+        match self {
+            Self::Leaf { id, window } => windows.push((*id, window)),
+            Self::Split { children, .. } => {
+                for child in children {
+                    child.collect(windows);
+                }
+            }
+        }
+    }
+    fn collect_mut<'a>(&'a mut self, windows: &mut Vec<(usize, &'a mut Window)>) {
+        match self {
+            Self::Leaf { id, window } => windows.push((*id, window)),
+            Self::Split { children, .. } => {
+                for child in children {
+                    child.collect_mut(windows);
+                }
+            }
+        }
+    }
+    // Returns the smallest size in `direction` fitting all windows in this node.
+    fn min_extent(&self, direction: SplitDirection) -> usize {
+        match self {
+            Self::Leaf { .. } => MIN_WINDOW_SIZE,
+            Self::Split {
+                direction: split_direction,
+                children,
+                ..
+            } => {
+                let mins = children.iter().map(|child| child.min_extent(direction));
+                if *split_direction == direction {
+                    mins.sum()
+                } else {
+                    mins.max().unwrap_or(MIN_WINDOW_SIZE)
+                }
+            }
+        }
+    }
+    // Splits the window `target` and places `new_leaf` before it.
+    // `extent` is the size of this node in `direction`.
+    fn split(
+        &mut self,
+        target: usize,
+        direction: SplitDirection,
+        new_leaf: Self,
+        extent: usize,
+    ) -> Result<(), Self> {
+        match self {
+            Self::Leaf { id, .. } if *id == target => {
+                if extent < MIN_WINDOW_SIZE * 2 {
+                    return Err(new_leaf);
+                }
+                let old_leaf = std::mem::replace(self, Self::empty(direction));
+                let new_size = extent / 2;
+                *self = Self::Split {
+                    direction,
+                    children: vec![new_leaf, old_leaf],
+                    sizes: vec![new_size, extent - new_size],
+                };
+                Ok(())
+            }
+            Self::Leaf { .. } => Err(new_leaf),
+            Self::Split {
+                direction: split_direction,
+                children,
+                sizes,
+            } => {
+                let Some(idx) = children.iter().position(|child| child.contains(target)) else {
+                    return Err(new_leaf);
+                };
+                let is_leaf = matches!(children[idx], Self::Leaf { .. });
+                if is_leaf && *split_direction == direction {
+                    // add a sibling instead of nesting splits of the same direction
+                    if sizes[idx] < MIN_WINDOW_SIZE * 2 {
+                        return Err(new_leaf);
+                    }
+                    let new_size = sizes[idx] / 2;
+                    sizes[idx] -= new_size;
+                    children.insert(idx, new_leaf);
+                    sizes.insert(idx, new_size);
+                    return Ok(());
+                }
+                let child_extent = if *split_direction == direction {
+                    sizes[idx]
+                } else {
+                    extent
+                };
+                children[idx].split(target, direction, new_leaf, child_extent)
+            }
+        }
+    }
+    // Removes the window `target` and returns it.
+    // Space of the removed window is given to a neighbor.
+    fn remove(&mut self, target: usize) -> Option<Box<Window>> {
+        let Self::Split {
+            children, sizes, ..
+        } = self
+        else {
+            return None;
+        };
+        let idx = children.iter().position(|child| child.contains(target))?;
+        if !matches!(children[idx], Self::Leaf { .. }) {
+            let removed = children[idx].remove(target);
+            // collapse split which has only one child
+            if let Self::Split { children: c, .. } = &mut children[idx] {
+                if c.len() == 1 {
+                    children[idx] = c.remove(0);
+                }
+            }
+            return removed;
+        }
+        let Self::Leaf { window, .. } = children.remove(idx) else {
+            return None;
+        };
+        let size = sizes.remove(idx);
+        let neighbor = idx.saturating_sub(1);
+        if let Some(neighbor_size) = sizes.get_mut(neighbor) {
+            *neighbor_size += size;
+        }
+        Some(window)
+    }
+    // Changes size of the window `target` in `direction` by `delta`.
+    // The nearest split in `direction` containing the window is resized.
+    fn resize(&mut self, target: usize, direction: SplitDirection, delta: isize) -> bool {
+        let Self::Split {
+            direction: split_direction,
+            children,
+            sizes,
+        } = self
+        else {
+            return false;
+        };
+        let Some(idx) = children.iter().position(|child| child.contains(target)) else {
+            return false;
+        };
+        if children[idx].resize(target, direction, delta) {
+            return true;
+        }
+        if *split_direction != direction || children.len() < 2 {
+            return false;
+        }
+        if delta > 0 {
+            // take space from following windows first, then preceding ones
+            let mut remaining = delta.unsigned_abs();
+            let others = (idx + 1..sizes.len()).chain((0..idx).rev());
+            for other in others.collect::<Vec<_>>() {
+                let available = sizes[other].saturating_sub(children[other].min_extent(direction));
+                let taken = usize::min(available, remaining);
+                sizes[other] -= taken;
+                sizes[idx] += taken;
+                remaining -= taken;
+            }
+        } else {
+            let available = sizes[idx].saturating_sub(children[idx].min_extent(direction));
+            let given = usize::min(available, delta.unsigned_abs());
+            let neighbor = if idx + 1 < sizes.len() {
+                idx + 1
+            } else {
+                idx - 1
+            };
+            sizes[idx] -= given;
+            sizes[neighbor] += given;
+        }
+        true
+    }
+    fn equalize(&mut self) {
+        if let Self::Split {
+            children, sizes, ..
+        } = self
+        {
+            let extent: usize = sizes.iter().sum();
+            let n = sizes.len();
+            for (i, size) in sizes.iter_mut().enumerate() {
+                // remainder is given to the last window
+                *size = extent / n + if i + 1 == n { extent % n } else { 0 };
+            }
+            for child in children {
+                child.equalize();
+            }
+        }
+    }
+    // Places windows in the area.
+    fn arrange(
+        &mut self,
+        origin: Position,
+        size: Size,
+        screen_width: usize,
+    ) -> Result<(), std::io::Error> {
+        match self {
+            Self::Leaf { window, .. } => {
+                let has_separator = origin.col + size.width < screen_width;
+                window.set_area(origin, size, has_separator)
+            }
+            Self::Split {
+                direction,
+                children,
+                sizes,
+            } => {
+                let extent = match direction {
+                    SplitDirection::Horizontal => size.height,
+                    SplitDirection::Vertical => size.width,
+                };
+                let mins: Vec<usize> = children
+                    .iter()
+                    .map(|child| child.min_extent(*direction))
+                    .collect();
+                fit_sizes(sizes, &mins, extent);
+                let mut offset = 0;
+                for (child, child_size) in children.iter_mut().zip(sizes.iter()) {
+                    let (child_origin, child_area) = match direction {
+                        SplitDirection::Horizontal => (
+                            Position {
+                                col: origin.col,
+                                row: origin.row + offset,
+                            },
+                            Size {
+                                height: *child_size,
+                                width: size.width,
+                            },
+                        ),
+                        SplitDirection::Vertical => (
+                            Position {
+                                col: origin.col + offset,
+                                row: origin.row,
+                            },
+                            Size {
+                                height: size.height,
+                                width: *child_size,
+                            },
+                        ),
+                    };
+                    child.arrange(child_origin, child_area, screen_width)?;
+                    offset += child_size;
+                }
+                Ok(())
+            }
+        }
+    }
+    fn empty(direction: SplitDirection) -> Self {
+        Self::Split {
+            direction,
+            children: vec![],
+            sizes: vec![],
+        }
+    }
+}
+
+// Scales `sizes` so that they sum up to `extent`, keeping each size at least
+// the one in `mins`. Space beyond the minimum sizes is shared in proportion to
+// the current sizes. If `extent` is too small, it is shared in proportion to `mins`.
+fn fit_sizes(sizes: &mut [usize], mins: &[usize], extent: usize) {
+    let total: usize = sizes.iter().sum();
+    let fits = sizes.iter().zip(mins).all(|(size, min)| size >= min);
+    if (total == extent && fits) || sizes.is_empty() {
+        return;
+    }
+    let min_total: usize = mins.iter().sum();
+    let (bases, weights): (Vec<usize>, Vec<usize>) = if extent < min_total {
+        (vec![0; sizes.len()], mins.to_vec())
+    } else {
+        let extras = sizes
+            .iter()
+            .zip(mins)
+            .map(|(size, min)| size.saturating_sub(*min));
+        (mins.to_vec(), extras.collect())
+    };
+    let spare = extent - bases.iter().sum::<usize>();
+    let weight_total: usize = weights.iter().sum();
+    let mut used = 0;
+    let n = sizes.len();
+    for (i, size) in sizes.iter_mut().enumerate() {
+        // remainder is given to the last window
+        *size = if i + 1 == n {
+            extent - used
+        } else {
+            bases[i] + weights[i] * spare / usize::max(weight_total, 1)
+        };
+        used += *size;
+    }
+}
+
+// Tree of windows tiling the area above the command bar.
+pub struct Layout {
+    root: LayoutNode,
+    current: usize,
+    next_id: usize,
+    origin: Position,
+    size: Size,
+}
+
+impl Layout {
+    // Windows are placed by `set_area()`.
+    pub fn new(window: Window) -> Self {
+        Self {
+            root: LayoutNode::Leaf {
+                id: 0,
+                window: Box::new(window),
+            },
+            current: 0,
+            next_id: 1,
+            origin: Position::default(),
+            size: Size::default(),
+        }
+    }
+    pub fn current(&self) -> &Window {
+        self.root
+            .find(self.current)
+            .expect("current window should be in the layout")
+    }
+    pub fn current_mut(&mut self) -> &mut Window {
+        self.root
+            .find_mut(self.current)
+            .expect("current window should be in the layout")
+    }
+    // Returns windows in order from top-left to bottom-right, with whether
+    // each window is the current one.
+    pub fn windows(&self) -> Vec<(bool, &Window)> {
+        let mut windows = vec![];
+        self.root.collect(&mut windows);
+        windows
+            .into_iter()
+            .map(|(id, window)| (id == self.current, window))
+            .collect()
+    }
+    pub fn windows_mut(&mut self) -> Vec<(bool, &mut Window)> {
+        let mut windows = vec![];
+        self.root.collect_mut(&mut windows);
+        windows
+            .into_iter()
+            .map(|(id, window)| (id == self.current, window))
+            .collect()
+    }
+    pub fn len(&self) -> usize {
+        self.ids().len()
+    }
+    fn ids(&self) -> Vec<usize> {
+        let mut windows = vec![];
+        self.root.collect(&mut windows);
+        windows.into_iter().map(|(id, _)| id).collect()
+    }
+    pub fn set_needs_redraw(&mut self) {
+        for (_, window) in self.windows_mut() {
+            window.set_needs_redraw();
+        }
+    }
+    pub fn set_area(&mut self, origin: Position, size: Size) -> Result<(), std::io::Error> {
+        self.origin = origin;
+        self.size = size;
+        self.arrange()
+    }
+    fn arrange(&mut self) -> Result<(), std::io::Error> {
+        let screen_width = self.origin.col + self.size.width;
+        self.root.arrange(self.origin, self.size, screen_width)
+    }
+    // Splits the current window and focuses the new window placed
+    // above or left of it.
+    pub fn split(&mut self, direction: SplitDirection, window: Window) -> Result<(), String> {
+        let id = self.next_id;
+        let new_leaf = LayoutNode::Leaf {
+            id,
+            window: Box::new(window),
+        };
+        let extent = match direction {
+            SplitDirection::Horizontal => self.size.height,
+            SplitDirection::Vertical => self.size.width,
+        };
+        if self
+            .root
+            .split(self.current, direction, new_leaf, extent)
+            .is_err()
+        {
+            return Err("Not enough room".to_string());
+        }
+        self.next_id += 1;
+        self.current = id;
+        self.arrange().map_err(|err| err.to_string())
+    }
+    // Closes the current window and focuses the previous window.
+    pub fn close(&mut self) -> Result<(), String> {
+        if self.len() == 1 {
+            return Err("Cannot close last window".to_string());
+        }
+        let ids = self.ids();
+        let idx = ids.iter().position(|id| *id == self.current).unwrap_or(0);
+        self.root.remove(self.current);
+        if let LayoutNode::Split { children, .. } = &mut self.root {
+            if children.len() == 1 {
+                self.root = children.remove(0);
+            }
+        }
+        let ids = self.ids();
+        self.current = ids[idx.saturating_sub(1).min(ids.len() - 1)];
+        self.arrange().map_err(|err| err.to_string())
+    }
+    // Closes all windows except the current one.
+    pub fn only(&mut self) -> Result<(), std::io::Error> {
+        let mut node = std::mem::replace(
+            &mut self.root,
+            LayoutNode::empty(SplitDirection::Horizontal),
+        );
+        while let LayoutNode::Split { children, .. } = node {
+            node = children
+                .into_iter()
+                .find(|child| child.contains(self.current))
+                .expect("current window should be in the layout");
+        }
+        self.root = node;
+        self.arrange()
+    }
+    // Focuses the window `count` windows after the current one.
+    pub fn focus_next(&mut self, count: usize) {
+        let ids = self.ids();
+        let idx = ids.iter().position(|id| *id == self.current).unwrap_or(0);
+        self.current = ids[(idx + count) % ids.len()];
+    }
+    pub fn focus_prev(&mut self, count: usize) {
+        let ids = self.ids();
+        let idx = ids.iter().position(|id| *id == self.current).unwrap_or(0);
+        let len = ids.len();
+        self.current = ids[(idx + len - count % len) % len];
+    }
+    // Focuses the window next to the current one in `direction`.
+    // The window at the same row or column as the cursor is preferred.
+    pub fn focus(&mut self, direction: Direction, count: usize) {
+        for _ in 0..count {
+            let (origin, size) = self.current().get_area();
+            let cursor = self.current().get_screen_position();
+            let candidates: Vec<_> = self
+                .window_areas()
+                .into_iter()
+                .filter(|(_, (o, s))| match direction {
+                    Direction::Left => o.col + s.width == origin.col,
+                    Direction::Right => origin.col + size.width == o.col,
+                    Direction::Up => o.row + s.height == origin.row,
+                    Direction::Down => origin.row + size.height == o.row,
+                    _ => false,
+                })
+                .filter(|(_, (o, s))| match direction {
+                    Direction::Left | Direction::Right => {
+                        o.row < origin.row + size.height && origin.row < o.row + s.height
+                    }
+                    _ => o.col < origin.col + size.width && origin.col < o.col + s.width,
+                })
+                .collect();
+            let best = candidates
+                .iter()
+                .find(|(_, (o, s))| match direction {
+                    Direction::Left | Direction::Right => {
+                        o.row <= cursor.row && cursor.row < o.row + s.height
+                    }
+                    _ => o.col <= cursor.col && cursor.col < o.col + s.width,
+                })
+                .or(candidates.first());
+            match best {
+                Some((id, _)) => self.current = *id,
+                None => break,
+            }
+        }
+    }
+    fn window_areas(&self) -> Vec<(usize, (Position, Size))> {
+        let mut windows = vec![];
+        self.root.collect(&mut windows);
+        windows
+            .into_iter()
+            .map(|(id, window)| (id, window.get_area()))
+            .collect()
+    }
+    pub fn resize(
+        &mut self,
+        direction: SplitDirection,
+        delta: isize,
+    ) -> Result<(), std::io::Error> {
+        if self.root.resize(self.current, direction, delta) {
+            self.arrange()?;
+        }
+        Ok(())
+    }
+    pub fn equalize(&mut self) -> Result<(), std::io::Error> {
+        self.root.equalize();
+        self.arrange()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::super::buffer::Buffer;
+    use super::*;
+
+    fn window() -> Window {
+        Window::new(Rc::new(RefCell::new(Buffer::default())))
+    }
+
+    fn layout(height: usize, width: usize) -> Layout {
+        let mut layout = Layout::new(window());
+        layout
+            .set_area(Position::default(), Size { height, width })
+            .unwrap();
+        layout
+    }
+
+    // Checks that sizes of each split sum up to its extent and windows
+    // are placed in the areas given to them.
+    fn assert_tiled(node: &LayoutNode, origin: Position, size: Size) {
+        match node {
+            LayoutNode::Leaf { window, .. } => assert_eq!(window.get_area(), (origin, size)),
+            LayoutNode::Split {
+                direction,
+                children,
+                sizes,
+            } => {
+                assert_eq!(children.len(), sizes.len());
+                let mut offset = 0;
+                for (child, child_size) in children.iter().zip(sizes) {
+                    let (child_origin, child_area) = match direction {
+                        SplitDirection::Horizontal => (
+                            Position {
+                                col: origin.col,
+                                row: origin.row + offset,
+                            },
+                            Size {
+                                height: *child_size,
+                                width: size.width,
+                            },
+                        ),
+                        SplitDirection::Vertical => (
+                            Position {
+                                col: origin.col + offset,
+                                row: origin.row,
+                            },
+                            Size {
+                                height: size.height,
+                                width: *child_size,
+                            },
+                        ),
+                    };
+                    assert_tiled(child, child_origin, child_area);
+                    offset += child_size;
+                }
+                let extent = match direction {
+                    SplitDirection::Horizontal => size.height,
+                    SplitDirection::Vertical => size.width,
+                };
+                assert_eq!(offset, extent);
+            }
+        }
+    }
+
+    fn assert_layout(layout: &Layout) {
+        assert_tiled(&layout.root, layout.origin, layout.size);
+        for (_, (_, size)) in layout.window_areas() {
+            assert!(size.height >= MIN_WINDOW_SIZE && size.width >= MIN_WINDOW_SIZE);
+        }
+    }
+
+    #[test]
+    fn fit_sizes_keeps_minimum() {
+        let mut sizes = [1, 9];
+        fit_sizes(&mut sizes, &[2, 2], 10);
+        assert_eq!(sizes, [2, 8]);
+        // space beyond the minimum sizes is scaled
+        let mut sizes = [10, 30, 20];
+        fit_sizes(&mut sizes, &[2, 2, 2], 30);
+        assert_eq!(sizes, [5, 14, 11]);
+        let mut sizes = [2, 4, 2];
+        fit_sizes(&mut sizes, &[2, 4, 2], 12);
+        assert_eq!(sizes, [2, 4, 6]);
+        // too small to fit
+        let mut sizes = [5, 5, 5];
+        fit_sizes(&mut sizes, &[2, 2, 2], 3);
+        assert_eq!(sizes.iter().sum::<usize>(), 3);
+    }
+
+    #[test]
+    fn split_and_close() {
+        let mut layout = layout(24, 80);
+        layout.split(SplitDirection::Horizontal, window()).unwrap();
+        assert_layout(&layout);
+        layout.split(SplitDirection::Vertical, window()).unwrap();
+        assert_layout(&layout);
+        layout.split(SplitDirection::Horizontal, window()).unwrap();
+        assert_layout(&layout);
+        layout.split(SplitDirection::Horizontal, window()).unwrap();
+        assert_layout(&layout);
+        assert_eq!(layout.len(), 5);
+        while layout.len() > 1 {
+            layout.close().unwrap();
+            assert_layout(&layout);
+        }
+        assert!(layout.close().is_err());
+    }
+
+    #[test]
+    fn split_without_room() {
+        let mut layout = layout(5, 80);
+        layout.split(SplitDirection::Horizontal, window()).unwrap();
+        assert!(layout.split(SplitDirection::Horizontal, window()).is_err());
+        assert_eq!(layout.len(), 2);
+        assert_layout(&layout);
+    }
+
+    #[test]
+    fn resize() {
+        let mut layout = layout(24, 80);
+        layout.split(SplitDirection::Horizontal, window()).unwrap();
+        layout.split(SplitDirection::Vertical, window()).unwrap();
+        layout.split(SplitDirection::Horizontal, window()).unwrap();
+        layout.focus_next(2);
+        for delta in [3, 100, -5, -100] {
+            layout.resize(SplitDirection::Horizontal, delta).unwrap();
+            assert_layout(&layout);
+            layout.resize(SplitDirection::Vertical, delta).unwrap();
+            assert_layout(&layout);
+        }
+        // the split above the bottom window needs room for both of its windows
+        layout.focus_next(1);
+        layout.resize(SplitDirection::Horizontal, 100).unwrap();
+        assert_layout(&layout);
+        assert_eq!(layout.window_areas()[3].1 .1.height, 24 - 4);
+    }
+
+    #[test]
+    fn equalize() {
+        let mut layout = layout(24, 80);
+        layout.split(SplitDirection::Vertical, window()).unwrap();
+        layout.split(SplitDirection::Vertical, window()).unwrap();
+        layout.split(SplitDirection::Horizontal, window()).unwrap();
+        layout.resize(SplitDirection::Vertical, 100).unwrap();
+        layout.equalize().unwrap();
+        assert_layout(&layout);
+        let widths: Vec<usize> = layout
+            .window_areas()
+            .iter()
+            .map(|(_, (_, size))| size.width)
+            .collect();
+        assert_eq!(widths, [26, 26, 26, 28]);
+    }
+
+    #[test]
+    fn shrink_terminal() {
+        let mut layout = layout(24, 80);
+        layout.split(SplitDirection::Horizontal, window()).unwrap();
+        layout.split(SplitDirection::Horizontal, window()).unwrap();
+        layout.split(SplitDirection::Vertical, window()).unwrap();
+        layout.focus_next(3);
+        layout.resize(SplitDirection::Horizontal, 100).unwrap();
+        for (height, width) in [(10, 40), (6, 4), (24, 80)] {
+            layout
+                .set_area(Position::default(), Size { height, width })
+                .unwrap();
+            assert_layout(&layout);
+        }
+        // sizes still sum up to the extent when windows cannot fit
+        for (height, width) in [(3, 3), (24, 80)] {
+            layout
+                .set_area(Position::default(), Size { height, width })
+                .unwrap();
+            assert_tiled(&layout.root, layout.origin, layout.size);
+        }
+        assert_layout(&layout);
+    }
+}
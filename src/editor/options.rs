@@ -0,0 +1,310 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+// Full names of options accepted by `:set`, which are completed with Tab.
+pub const BOOLEAN_OPTION_NAMES: [&str; 8] = [
+    "backup",
+    "bomb",
+    "endofline",
+    "expandtab",
+    "fixendofline",
+    "ignorecase",
+    "smartcase",
+    "swapfile",
+];
+pub const VALUE_OPTION_NAMES: [&str; 4] = ["backupext", "directory", "fileformat", "shiftwidth"];
+
+// Options changed by `:set` command.
+pub struct EditorOptions {
+    // ignore case in search patterns
+    pub ignorecase: bool,
+    // override `ignorecase` if search pattern contains uppercase characters
+    pub smartcase: bool,
+    // keep a copy of the old file when writing
+    pub backup: bool,
+    // appended to file name of the backup
+    pub backupext: String,
+    // write swap files for recovery
+    pub swapfile: bool,
+    // where swap files are placed, or next to the edited file if empty
+    pub directory: String,
+    // columns of a level of indentation, or the tab width if 0
+    pub shiftwidth: usize,
+    // indent with spaces instead of tabs
+    pub expandtab: bool,
+}
+
+impl Default for EditorOptions {
+    fn default() -> Self {
+        Self {
+            ignorecase: false,
+            smartcase: false,
+            backup: false,
+            backupext: String::from("~"),
+            swapfile: true,
+            directory: String::new(),
+            shiftwidth: 8,
+            expandtab: false,
+        }
+    }
+}
+
+impl EditorOptions {
+    // Returns extension of backup files if backup is enabled.
+    pub fn get_backupext(&self) -> Option<&str> {
+        self.backup.then_some(self.backupext.as_str())
+    }
+    // Applies an argument of `:set` command, which is one of
+    // `{option}`, `no{option}`, `inv{option}`, `{option}!` and `{option}={value}`.
+    pub fn set(&mut self, arg: &str) -> Result<(), String> {
+        if let Some((name, value)) = arg.split_once('=') {
+            return match name {
+                "backupext" | "bex" if !value.is_empty() => {
+                    self.backupext = String::from(value);
+                    Ok(())
+                }
+                "backupext" | "bex" => Err(format!("Invalid argument: {}", arg)),
+                "directory" | "dir" => {
+                    self.directory = String::from(value);
+                    Ok(())
+                }
+                "shiftwidth" | "sw" => {
+                    self.shiftwidth = value
+                        .parse()
+                        .map_err(|_| format!("Invalid argument: {}", arg))?;
+                    Ok(())
+                }
+                _ => Err(format!("Unknown option: {}", arg)),
+            };
+        }
+        let (name, value) = parse_bool_arg(arg);
+        let option = self
+            .get_bool_option(name)
+            .ok_or(format!("Unknown option: {}", arg))?;
+        *option = value.unwrap_or(!*option);
+        Ok(())
+    }
+    fn get_bool_option(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "ignorecase" | "ic" => Some(&mut self.ignorecase),
+            "smartcase" | "scs" => Some(&mut self.smartcase),
+            "backup" | "bk" => Some(&mut self.backup),
+            "swapfile" | "swf" => Some(&mut self.swapfile),
+            "expandtab" | "et" => Some(&mut self.expandtab),
+            _ => None,
+        }
+    }
+}
+
+// Returns name of a boolean option and the value to set.
+// `None` means the option is toggled.
+fn parse_bool_arg(arg: &str) -> (&str, Option<bool>) {
+    if let Some(name) = arg.strip_suffix('!') {
+        (name, None)
+    } else if let Some(name) = arg.strip_prefix("inv") {
+        (name, None)
+    } else if let Some(name) = arg.strip_prefix("no") {
+        (name, Some(false))
+    } else {
+        (arg, Some(true))
+    }
+}
+
+// Line ending written after each line.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum FileFormat {
+    // `\n`
+    #[default]
+    Unix,
+    // `\r\n`
+    Dos,
+}
+
+impl FileFormat {
+    pub fn line_ending(self) -> &'static str {
+        match self {
+            Self::Unix => "\n",
+            Self::Dos => "\r\n",
+        }
+    }
+    // Returns `Dos` if all lines end with `\r\n`.
+    pub fn detect(contents: &str) -> Self {
+        let mut line_endings = contents.match_indices('\n').peekable();
+        if line_endings.peek().is_none() {
+            return Self::Unix;
+        }
+        let is_dos = line_endings.all(|(idx, _)| contents[..idx].ends_with('\r'));
+        if is_dos {
+            Self::Dos
+        } else {
+            Self::Unix
+        }
+    }
+}
+
+impl fmt::Display for FileFormat {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Unix => write!(formatter, "unix"),
+            Self::Dos => write!(formatter, "dos"),
+        }
+    }
+}
+
+// Options local to each buffer, which describe how the file is written.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BufferOptions {
+    pub fileformat: FileFormat,
+    // the last line ends with a line break
+    pub endofline: bool,
+    // write a line break after the last line even if `endofline` is off
+    pub fixendofline: bool,
+    // file starts with a byte order mark
+    pub bomb: bool,
+}
+
+impl Default for BufferOptions {
+    fn default() -> Self {
+        Self {
+            fileformat: FileFormat::Unix,
+            endofline: true,
+            fixendofline: true,
+            bomb: false,
+        }
+    }
+}
+
+impl BufferOptions {
+    // Applies an argument of `:set` command if it is a buffer-local option,
+    // such as `fileformat=dos` and `nofixeol`. Returns `None` for other options.
+    pub fn set(&mut self, arg: &str) -> Option<Result<(), String>> {
+        if let Some((name, value)) = arg.split_once('=') {
+            if name != "fileformat" && name != "ff" {
+                return None;
+            }
+            self.fileformat = match value {
+                "unix" => FileFormat::Unix,
+                "dos" => FileFormat::Dos,
+                _ => return Some(Err(format!("Invalid argument: {}", arg))),
+            };
+            return Some(Ok(()));
+        }
+        let (name, value) = parse_bool_arg(arg);
+        let option = match name {
+            "endofline" | "eol" => &mut self.endofline,
+            "fixendofline" | "fixeol" => &mut self.fixendofline,
+            "bomb" => &mut self.bomb,
+            _ => return None,
+        };
+        *option = value.unwrap_or(!*option);
+        Some(Ok(()))
+    }
+}
+
+// `$XDG_CONFIG_HOME/hecto`, or `~/.config/hecto`, where user's syntax definitions
+// and colorschemes are placed.
+pub fn config_directory() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config_home.join("hecto"))
+}
+
+// `$XDG_STATE_HOME/hecto`, or `~/.local/state/hecto`, where the command-line history is kept.
+pub fn state_directory() -> Option<PathBuf> {
+    let state_home = std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| Path::new(&home).join(".local").join("state"))
+        })?;
+    Some(state_home.join("hecto"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_file_format() {
+        assert_eq!(FileFormat::detect("a\nb\n"), FileFormat::Unix);
+        assert_eq!(FileFormat::detect("a\r\nb\r\n"), FileFormat::Dos);
+        // the last line may lack a line break
+        assert_eq!(FileFormat::detect("a\r\nb"), FileFormat::Dos);
+    }
+
+    #[test]
+    fn detect_mixed_line_endings() {
+        assert_eq!(FileFormat::detect("a\r\nb\nc\r\n"), FileFormat::Unix);
+        assert_eq!(FileFormat::detect("a\nb\r\n"), FileFormat::Unix);
+        // `\r` alone is not a line break
+        assert_eq!(FileFormat::detect("a\rb\n"), FileFormat::Unix);
+    }
+
+    #[test]
+    fn detect_without_line_endings() {
+        assert_eq!(FileFormat::detect(""), FileFormat::Unix);
+        assert_eq!(FileFormat::detect("abc"), FileFormat::Unix);
+        assert_eq!(FileFormat::detect("abc\r"), FileFormat::Unix);
+    }
+
+    #[test]
+    fn parse_bool_args() {
+        assert_eq!(parse_bool_arg("bomb"), ("bomb", Some(true)));
+        assert_eq!(parse_bool_arg("nobomb"), ("bomb", Some(false)));
+        assert_eq!(parse_bool_arg("invbomb"), ("bomb", None));
+        assert_eq!(parse_bool_arg("bomb!"), ("bomb", None));
+    }
+
+    #[test]
+    fn set_buffer_options() {
+        let mut options = BufferOptions::default();
+        assert_eq!(options.set("nofixeol"), Some(Ok(())));
+        assert!(!options.fixendofline);
+        assert_eq!(options.set("fixendofline"), Some(Ok(())));
+        assert!(options.fixendofline);
+
+        assert_eq!(options.set("invbomb"), Some(Ok(())));
+        assert!(options.bomb);
+        assert_eq!(options.set("bomb!"), Some(Ok(())));
+        assert!(!options.bomb);
+
+        assert_eq!(options.set("noeol"), Some(Ok(())));
+        assert!(!options.endofline);
+    }
+
+    #[test]
+    fn set_fileformat() {
+        let mut options = BufferOptions::default();
+        assert_eq!(options.set("ff=dos"), Some(Ok(())));
+        assert_eq!(options.fileformat, FileFormat::Dos);
+        assert_eq!(options.set("fileformat=unix"), Some(Ok(())));
+        assert_eq!(options.fileformat, FileFormat::Unix);
+
+        assert!(matches!(options.set("ff="), Some(Err(_))));
+        assert!(matches!(options.set("ff=mac"), Some(Err(_))));
+        assert_eq!(options.fileformat, FileFormat::Unix);
+    }
+
+    #[test]
+    fn other_options_are_not_buffer_options() {
+        let mut options = BufferOptions::default();
+        assert_eq!(options.set("ignorecase"), None);
+        assert_eq!(options.set("sw=4"), None);
+        assert_eq!(options, BufferOptions::default());
+    }
+
+    #[test]
+    fn set_editor_options() {
+        let mut options = EditorOptions::default();
+        assert_eq!(options.set("ic"), Ok(()));
+        assert!(options.ignorecase);
+        assert_eq!(options.set("noignorecase"), Ok(()));
+        assert!(!options.ignorecase);
+        assert_eq!(options.set("sw=4"), Ok(()));
+        assert_eq!(options.shiftwidth, 4);
+
+        assert!(options.set("sw=x").is_err());
+        assert!(options.set("bex=").is_err());
+        assert!(options.set("nosuchoption").is_err());
+    }
+}
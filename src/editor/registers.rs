@@ -0,0 +1,267 @@
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RegisterKind {
+    Characterwise,
+    Linewise,
+    // lines of a rectangular block
+    Blockwise,
+}
+
+// Text stored in a register.
+// Lines are joined with '\n', and linewise text has no trailing newline.
+#[derive(Clone, Debug)]
+pub struct RegisterContent {
+    pub text: String,
+    pub kind: RegisterKind,
+}
+
+impl RegisterContent {
+    pub fn new(text: String, kind: RegisterKind) -> Self {
+        Self { text, kind }
+    }
+    fn append(&mut self, other: &Self) {
+        if self.kind == RegisterKind::Characterwise && other.kind == RegisterKind::Characterwise {
+            self.text.push_str(&other.text);
+        } else {
+            // appending to or with linewise text starts a new line
+            self.text.push('\n');
+            self.text.push_str(&other.text);
+            if self.kind == RegisterKind::Characterwise {
+                self.kind = other.kind;
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Registers {
+    // register used when no name is given (`""`)
+    unnamed: Option<RegisterContent>,
+    // `"0` holds last yank, and `"1`-`"9` hold last deletes
+    numbered: [Option<RegisterContent>; 10],
+    // `"-` holds last delete within a line
+    small_delete: Option<RegisterContent>,
+    // `"a`-`"z`
+    named: HashMap<char, RegisterContent>,
+}
+
+impl Registers {
+    pub fn is_valid_name(name: char) -> bool {
+        name == '"' || name == '-' || name.is_ascii_digit() || name.is_ascii_alphabetic()
+    }
+    pub fn get(&self, name: Option<char>) -> Option<&RegisterContent> {
+        match name {
+            None | Some('"') => self.unnamed.as_ref(),
+            Some('-') => self.small_delete.as_ref(),
+            Some(c @ '0'..='9') => self.numbered[c as usize - '0' as usize].as_ref(),
+            Some(c) if c.is_ascii_alphabetic() => self.named.get(&c.to_ascii_lowercase()),
+            Some(_) => None,
+        }
+    }
+    pub fn yank(&mut self, name: Option<char>, content: RegisterContent) {
+        if content.text.is_empty() {
+            return;
+        }
+        let content = match self.store_named(name, &content) {
+            Some(stored) => stored,
+            None => {
+                self.numbered[0] = Some(content.clone());
+                content
+            }
+        };
+        self.unnamed = Some(content);
+    }
+    pub fn delete(&mut self, name: Option<char>, content: RegisterContent) {
+        if content.text.is_empty() {
+            return;
+        }
+        let content = match self.store_named(name, &content) {
+            Some(stored) => stored,
+            None => {
+                if content.kind == RegisterKind::Characterwise && !content.text.contains('\n') {
+                    self.small_delete = Some(content.clone());
+                } else {
+                    // shift `"1`-`"8` to `"2`-`"9`
+                    self.numbered[1..].rotate_right(1);
+                    self.numbered[1] = Some(content.clone());
+                }
+                content
+            }
+        };
+        self.unnamed = Some(content);
+    }
+    // Stores content to the register `name` if it is given explicitly.
+    // Uppercase name appends content to the register.
+    // Returns the whole content of the register, which `""` also points at,
+    // or `None` if content is not stored.
+    fn store_named(
+        &mut self,
+        name: Option<char>,
+        content: &RegisterContent,
+    ) -> Option<RegisterContent> {
+        match name {
+            Some(c) if c.is_ascii_lowercase() => {
+                self.named.insert(c, content.clone());
+                Some(content.clone())
+            }
+            Some(c) if c.is_ascii_uppercase() => {
+                let register = self
+                    .named
+                    .entry(c.to_ascii_lowercase())
+                    .and_modify(|register| register.append(content))
+                    .or_insert_with(|| content.clone());
+                Some(register.clone())
+            }
+            Some(c @ '0'..='9') => {
+                self.numbered[c as usize - '0' as usize] = Some(content.clone());
+                Some(content.clone())
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(text: &str) -> RegisterContent {
+        RegisterContent::new(String::from(text), RegisterKind::Characterwise)
+    }
+
+    fn lines(text: &str) -> RegisterContent {
+        RegisterContent::new(String::from(text), RegisterKind::Linewise)
+    }
+
+    fn text(registers: &Registers, name: Option<char>) -> Option<(&str, RegisterKind)> {
+        registers
+            .get(name)
+            .map(|content| (content.text.as_str(), content.kind))
+    }
+
+    #[test]
+    fn yank_to_unnamed_and_zero() {
+        let mut registers = Registers::default();
+        registers.yank(None, chars("foo"));
+        assert_eq!(
+            text(&registers, None),
+            Some(("foo", RegisterKind::Characterwise))
+        );
+        assert_eq!(text(&registers, Some('"')), text(&registers, None));
+        assert_eq!(text(&registers, Some('0')), text(&registers, None));
+        assert_eq!(text(&registers, Some('1')), None);
+        // empty text is not stored
+        registers.yank(None, chars(""));
+        assert_eq!(
+            text(&registers, None),
+            Some(("foo", RegisterKind::Characterwise))
+        );
+    }
+
+    #[test]
+    fn named_registers() {
+        let mut registers = Registers::default();
+        registers.yank(Some('a'), chars("foo"));
+        assert_eq!(
+            text(&registers, Some('a')),
+            Some(("foo", RegisterKind::Characterwise))
+        );
+        assert_eq!(text(&registers, Some('A')), text(&registers, Some('a')));
+        assert_eq!(text(&registers, None), text(&registers, Some('a')));
+        // named registers leave numbered registers as they are
+        assert_eq!(text(&registers, Some('0')), None);
+        registers.delete(Some('b'), lines("bar"));
+        assert_eq!(
+            text(&registers, Some('b')),
+            Some(("bar", RegisterKind::Linewise))
+        );
+        assert_eq!(text(&registers, Some('1')), None);
+        assert_eq!(text(&registers, Some('-')), None);
+    }
+
+    #[test]
+    fn append_to_named_register() {
+        let mut registers = Registers::default();
+        registers.yank(Some('A'), chars("foo"));
+        assert_eq!(
+            text(&registers, Some('a')),
+            Some(("foo", RegisterKind::Characterwise))
+        );
+        registers.yank(Some('A'), chars("bar"));
+        assert_eq!(
+            text(&registers, Some('a')),
+            Some(("foobar", RegisterKind::Characterwise))
+        );
+        // `""` points at the whole register
+        assert_eq!(text(&registers, None), text(&registers, Some('a')));
+        // appending linewise text makes the register linewise
+        registers.delete(Some('A'), lines("baz"));
+        assert_eq!(
+            text(&registers, Some('a')),
+            Some(("foobar\nbaz", RegisterKind::Linewise))
+        );
+        assert_eq!(text(&registers, None), text(&registers, Some('a')));
+        registers.yank(Some('A'), chars("qux"));
+        assert_eq!(
+            text(&registers, Some('a')),
+            Some(("foobar\nbaz\nqux", RegisterKind::Linewise))
+        );
+    }
+
+    #[test]
+    fn deletes_shift_numbered_registers() {
+        let mut registers = Registers::default();
+        registers.yank(None, lines("yanked"));
+        for i in 1..=10 {
+            registers.delete(None, lines(&i.to_string()));
+        }
+        assert_eq!(
+            text(&registers, Some('1')),
+            Some(("10", RegisterKind::Linewise))
+        );
+        assert_eq!(
+            text(&registers, Some('2')),
+            Some(("9", RegisterKind::Linewise))
+        );
+        assert_eq!(
+            text(&registers, Some('9')),
+            Some(("2", RegisterKind::Linewise))
+        );
+        assert_eq!(text(&registers, None), text(&registers, Some('1')));
+        // the last yank is kept
+        assert_eq!(
+            text(&registers, Some('0')),
+            Some(("yanked", RegisterKind::Linewise))
+        );
+        // characterwise text over lines is not a small delete
+        registers.delete(None, chars("a\nb"));
+        assert_eq!(
+            text(&registers, Some('1')),
+            Some(("a\nb", RegisterKind::Characterwise))
+        );
+        assert_eq!(
+            text(&registers, Some('2')),
+            Some(("10", RegisterKind::Linewise))
+        );
+        assert_eq!(text(&registers, Some('-')), None);
+    }
+
+    #[test]
+    fn small_deletes() {
+        let mut registers = Registers::default();
+        registers.delete(None, lines("line"));
+        registers.delete(None, chars("word"));
+        assert_eq!(
+            text(&registers, Some('-')),
+            Some(("word", RegisterKind::Characterwise))
+        );
+        assert_eq!(text(&registers, None), text(&registers, Some('-')));
+        // small deletes do not shift numbered registers
+        assert_eq!(
+            text(&registers, Some('1')),
+            Some(("line", RegisterKind::Linewise))
+        );
+        assert_eq!(text(&registers, Some('2')), None);
+    }
+}
@@ -0,0 +1,242 @@
+use regex::{Captures, Regex, RegexBuilder};
+
+use super::options::EditorOptions;
+
+// Compiled search pattern.
+// Patterns are written in vim's "magic" syntax, and translated to `regex` syntax.
+#[derive(Clone)]
+pub struct SearchPattern {
+    pattern: String,
+    regex: Regex,
+}
+
+impl SearchPattern {
+    pub fn new(pattern: &str, options: &EditorOptions) -> Result<Self, String> {
+        let (translated, case_flag) = translate(pattern);
+        let ignore_case = case_flag.unwrap_or_else(|| {
+            let has_upper = pattern.chars().any(|c| c.is_uppercase());
+            options.ignorecase && !(options.smartcase && has_upper)
+        });
+        let regex = RegexBuilder::new(&translated)
+            .case_insensitive(ignore_case)
+            .build()
+            .map_err(|_| format!("Invalid pattern: {}", pattern))?;
+        Ok(Self {
+            pattern: String::from(pattern),
+            regex,
+        })
+    }
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+    // Returns (start, end) byte indices of all matches in `s`.
+    pub fn find_all(&self, s: &str) -> Vec<(usize, usize)> {
+        self.regex
+            .find_iter(s)
+            .map(|m| (m.start(), m.end()))
+            .collect()
+    }
+    // Returns the first match in `s` at or after byte index `start`.
+    // Anchors such as `^` are evaluated against whole `s`.
+    pub fn captures_at<'h>(&self, s: &'h str, start: usize) -> Option<Captures<'h>> {
+        self.regex.captures_at(s, start)
+    }
+}
+
+// Translates vim pattern to `regex` syntax.
+// Returns translated pattern and case sensitivity given by `\c` or `\C`.
+fn translate(pattern: &str) -> (String, Option<bool>) {
+    let mut result = String::new();
+    let mut ignore_case = None;
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('<') => result.push_str(r"\b{start}"),
+                Some('>') => result.push_str(r"\b{end}"),
+                Some('c') => ignore_case = Some(true),
+                Some('C') => ignore_case = Some(false),
+                Some('{') => {
+                    // `\{n,m}`, also closed by `\}`
+                    let mut counts = String::new();
+                    let mut is_closed = false;
+                    while let Some(c) = chars.next() {
+                        if c == '}' || (c == '\\' && chars.next_if_eq(&'}').is_some()) {
+                            is_closed = true;
+                            break;
+                        }
+                        counts.push(c);
+                    }
+                    if is_closed {
+                        result.push_str(&translate_counts(&counts));
+                    } else {
+                        // left unclosed to be rejected by `regex`
+                        result.push('{');
+                        result.push_str(&counts);
+                    }
+                }
+                Some(c @ ('(' | ')' | '|' | '+' | '}')) => result.push(c),
+                Some('=' | '?') => result.push('?'),
+                Some(c @ ('s' | 'S' | 'd' | 'D' | 'w' | 'W' | 'n' | 't')) => {
+                    result.push('\\');
+                    result.push(c);
+                }
+                Some(c) => result.push_str(&regex::escape(&c.to_string())),
+                None => result.push_str(r"\\"),
+            },
+            '[' => {
+                // copy bracket expression as is
+                result.push('[');
+                if chars.peek() == Some(&'^') {
+                    result.push(chars.next().unwrap_or_default());
+                }
+                if chars.peek() == Some(&']') {
+                    // `]` just after `[` or `[^` is a literal
+                    chars.next();
+                    result.push_str(r"\]");
+                }
+                while let Some(c) = chars.next() {
+                    if c == '[' && chars.peek() == Some(&':') {
+                        // character class such as `[:alpha:]`
+                        result.push('[');
+                        for c in chars.by_ref() {
+                            result.push(c);
+                            if c == ']' {
+                                break;
+                            }
+                        }
+                        continue;
+                    }
+                    if c == '[' {
+                        result.push_str(r"\[");
+                        continue;
+                    }
+                    result.push(c);
+                    if c == ']' {
+                        break;
+                    }
+                }
+            }
+            // characters which are literal in vim's magic syntax
+            '(' | ')' | '|' | '+' | '?' | '{' | '}' => {
+                result.push('\\');
+                result.push(c);
+            }
+            _ => result.push(c),
+        }
+    }
+    (result, ignore_case)
+}
+
+// Translates counts of `\{n,m}` to a repetition operator.
+// `-` before the counts makes it non-greedy, and the counts may be omitted.
+fn translate_counts(counts: &str) -> String {
+    let (counts, lazy) = match counts.strip_prefix('-') {
+        Some(counts) => (counts, "?"),
+        None => (counts, ""),
+    };
+    let repetition = match counts.split_once(',') {
+        _ if counts.is_empty() => String::from("*"),
+        None => format!("{{{}}}", counts),
+        Some((min, max)) => {
+            let min = if min.is_empty() { "0" } else { min };
+            format!("{{{},{}}}", min, max)
+        }
+    };
+    repetition + lazy
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn translated(pattern: &str) -> String {
+        translate(pattern).0
+    }
+
+    fn matches(pattern: &str, options: &EditorOptions, s: &str) -> Vec<(usize, usize)> {
+        SearchPattern::new(pattern, options).unwrap().find_all(s)
+    }
+
+    #[test]
+    fn word_boundaries() {
+        assert_eq!(translated(r"\<foo\>"), r"\b{start}foo\b{end}");
+        let options = EditorOptions::default();
+        assert_eq!(
+            matches(r"\<foo\>", &options, "foo foobar barfoo foo"),
+            [(0, 3), (18, 21)]
+        );
+    }
+
+    #[test]
+    fn magic_syntax() {
+        assert_eq!(translated(r"\(a\|b\)\+c\=d\?"), "(a|b)+c?d?");
+        // literal in vim's magic syntax
+        assert_eq!(translated("(a|b)+?{1}"), r"\(a\|b\)\+\?\{1\}");
+        assert_eq!(translated(r"\s\d\w\t\.\*\/"), r"\s\d\w\t\.\*/");
+        assert_eq!(translated("a\\"), r"a\\");
+    }
+
+    #[test]
+    fn case_flags() {
+        assert_eq!(translate(r"foo\c"), (String::from("foo"), Some(true)));
+        assert_eq!(translate(r"\Cfoo"), (String::from("foo"), Some(false)));
+        assert_eq!(translate("foo"), (String::from("foo"), None));
+        let options = EditorOptions::default();
+        assert_eq!(matches(r"foo\c", &options, "FOO foo"), [(0, 3), (4, 7)]);
+        let options = EditorOptions {
+            ignorecase: true,
+            ..EditorOptions::default()
+        };
+        assert_eq!(matches(r"foo\C", &options, "FOO foo"), [(4, 7)]);
+    }
+
+    #[test]
+    fn smartcase() {
+        let options = EditorOptions {
+            ignorecase: true,
+            smartcase: true,
+            ..EditorOptions::default()
+        };
+        assert_eq!(matches("foo", &options, "Foo foo"), [(0, 3), (4, 7)]);
+        // uppercase letters make the pattern case sensitive
+        assert_eq!(matches("Foo", &options, "Foo foo"), [(0, 3)]);
+        assert_eq!(matches(r"Foo\c", &options, "Foo foo"), [(0, 3), (4, 7)]);
+        let options = EditorOptions {
+            smartcase: true,
+            ..EditorOptions::default()
+        };
+        assert_eq!(matches("foo", &options, "Foo foo"), [(4, 7)]);
+    }
+
+    #[test]
+    fn bracket_expressions() {
+        assert_eq!(translated("[a-z]+"), r"[a-z]\+");
+        assert_eq!(translated("[]a]"), r"[\]a]");
+        assert_eq!(translated("[^]a]"), r"[^\]a]");
+        assert_eq!(translated("[[:alpha:]_]"), "[[:alpha:]_]");
+        assert_eq!(translated("[a[b]"), r"[a\[b]");
+        let options = EditorOptions::default();
+        assert_eq!(matches("[]a]", &options, "b]a"), [(1, 2), (2, 3)]);
+        assert_eq!(matches("[[:digit:]x]", &options, "a1x"), [(1, 2), (2, 3)]);
+    }
+
+    #[test]
+    fn counts() {
+        assert_eq!(translated(r"a\{2}"), "a{2}");
+        assert_eq!(translated(r"a\{2,3\}"), "a{2,3}");
+        assert_eq!(translated(r"a\{2,}"), "a{2,}");
+        assert_eq!(translated(r"a\{,3}"), "a{0,3}");
+        assert_eq!(translated(r"a\{}"), "a*");
+        assert_eq!(translated(r"a\{-1,}"), "a{1,}?");
+        assert_eq!(translated(r"a\{-}"), "a*?");
+        let options = EditorOptions::default();
+        assert_eq!(matches(r"a\{2}", &options, "aaaaa"), [(0, 2), (2, 4)]);
+        assert_eq!(matches(r"a\{2,3\}", &options, "aaaaa"), [(0, 3), (3, 5)]);
+        assert_eq!(
+            matches(r"a\{-1,}", &options, "aaa"),
+            [(0, 1), (1, 2), (2, 3)]
+        );
+        assert!(SearchPattern::new(r"a\{2", &options).is_err());
+    }
+}
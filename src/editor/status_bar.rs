@@ -1,22 +1,29 @@
+use super::annotated_string::{AnnotatedString, Annotation, Style};
 use super::terminal::Position;
 use super::DocumentStatus;
 use super::Terminal;
 
+// Status line at the bottom of a window.
+#[derive(Default)]
 pub struct StatusBar {
     current_status: DocumentStatus,
     needs_redraw: bool,
+    // whether the window is the current one
+    is_current: bool,
     width: usize,
-    position_y: usize,
+    position: Position,
 }
 
 impl StatusBar {
-    pub fn new(position_y: usize) -> Self {
-        let size = Terminal::size().expect("Failed to get terminal size");
-        StatusBar {
-            current_status: DocumentStatus::default(),
-            needs_redraw: true,
-            width: size.width,
-            position_y,
+    pub fn set_area(&mut self, position: Position, width: usize) {
+        self.position = position;
+        self.width = width;
+        self.needs_redraw = true;
+    }
+    pub fn set_current(&mut self, is_current: bool) {
+        if is_current != self.is_current {
+            self.is_current = is_current;
+            self.needs_redraw = true;
         }
     }
     pub fn update_status(&mut self, new_stat: DocumentStatus) {
@@ -25,6 +32,9 @@ impl StatusBar {
             self.needs_redraw = true;
         }
     }
+    pub fn set_needs_redraw(&mut self) {
+        self.needs_redraw = true;
+    }
     pub fn render(&mut self) -> Result<(), std::io::Error> {
         if !self.needs_redraw {
             return Ok(());
@@ -39,10 +49,29 @@ impl StatusBar {
         } else {
             ""
         };
-        let left_status = format!("{} {}", buffer_name, modified_status_str);
+        let readonly_status_str = if self.current_status.is_readonly {
+            "[RO]"
+        } else {
+            ""
+        };
+        let left_status = format!(
+            "{} {}{}",
+            buffer_name, modified_status_str, readonly_status_str
+        );
+        let file_options = self.current_status.file_options;
+        // flags are shown only if they differ from usual files
+        let mut file_flags = String::new();
+        if !file_options.endofline {
+            file_flags.push_str("[noeol]");
+        }
+        if file_options.bomb {
+            file_flags.push_str("[BOM]");
+        }
         let right_status = format!(
-            "[{}] {}/{}",
-            self.current_status.file_type.to_string(),
+            "{}[{}] [{}] {}/{}",
+            file_flags,
+            file_options.fileformat,
+            self.current_status.file_type,
             self.current_status.current_line_index + 1, // 0-idx to 1-idx
             usize::max(1, self.current_status.total_lines), // If the buffer is empty, it is treated as a single line.
         );
@@ -52,11 +81,18 @@ impl StatusBar {
             .saturating_sub(right_status.len());
         let mut status_str = left_status + &" ".repeat(padding_len) + &right_status;
         status_str.truncate(self.width);
-        Terminal::move_cursor_to(Position {
-            col: 0,
-            row: self.position_y,
-        })?;
-        Terminal::print(&status_str)?;
+        Terminal::move_cursor_to(self.position)?;
+        if self.is_current {
+            Terminal::print(&status_str)?;
+        } else {
+            let mut annotated = AnnotatedString::from_str(&status_str);
+            annotated.add_annotation(Annotation::new(
+                Style::InactiveStatusLine,
+                0,
+                status_str.len(),
+            ));
+            Terminal::print_annotated_str(&annotated)?;
+        }
         self.needs_redraw = false;
         Ok(())
     }
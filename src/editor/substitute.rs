@@ -0,0 +1,295 @@
+use super::buffer::Buffer;
+use super::search_pattern::SearchPattern;
+use super::window::TextLocation;
+
+// A match found by `:substitute` command.
+// `start` and `end` are byte indices in the line.
+pub struct SubstituteMatch {
+    pub line_idx: usize,
+    pub start: usize,
+    pub end: usize,
+    // replacement text with backreferences expanded
+    pub replacement: String,
+}
+
+// Progress of `:substitute` command, which replaces matches one by one
+// so that each substitution can be confirmed.
+pub struct SubstituteSession {
+    pattern: SearchPattern,
+    // replacement in `regex` syntax
+    template: String,
+    global: bool,
+    // position to search the next match from
+    line_idx: usize,
+    byte_idx: usize,
+    last_line_idx: usize,
+    has_matched: bool,
+    n_substitutions: usize,
+    n_lines: usize,
+    last_substituted_line: Option<usize>,
+}
+
+impl SubstituteSession {
+    pub fn new(
+        pattern: SearchPattern,
+        replacement: &str,
+        global: bool,
+        (first_line_idx, last_line_idx): (usize, usize),
+    ) -> Self {
+        Self {
+            pattern,
+            template: translate_replacement(replacement),
+            global,
+            line_idx: first_line_idx,
+            byte_idx: 0,
+            last_line_idx,
+            has_matched: false,
+            n_substitutions: 0,
+            n_lines: 0,
+            last_substituted_line: None,
+        }
+    }
+    pub fn pattern(&self) -> &SearchPattern {
+        &self.pattern
+    }
+    pub fn last_substituted_line(&self) -> Option<usize> {
+        self.last_substituted_line
+    }
+    pub fn find_next(&mut self, buffer: &Buffer) -> Option<SubstituteMatch> {
+        while self.line_idx <= self.last_line_idx {
+            let line = buffer.get_line(self.line_idx)?;
+            let line = line.get_raw_str();
+            if self.byte_idx <= line.len() {
+                if let Some(captures) = self.pattern.captures_at(line, self.byte_idx) {
+                    if let Some(whole) = captures.get(0) {
+                        let mut replacement = String::new();
+                        captures.expand(&self.template, &mut replacement);
+                        self.has_matched = true;
+                        return Some(SubstituteMatch {
+                            line_idx: self.line_idx,
+                            start: whole.start(),
+                            end: whole.end(),
+                            replacement,
+                        });
+                    }
+                }
+            }
+            self.line_idx += 1;
+            self.byte_idx = 0;
+        }
+        None
+    }
+    // Skips the match without replacing it.
+    pub fn skip(&mut self, buffer: &Buffer, m: &SubstituteMatch) {
+        self.advance(buffer, m.line_idx, m.end, m.start == m.end);
+    }
+    // Replaces the match and moves search position past the replacement.
+    pub fn replace(&mut self, buffer: &mut Buffer, m: &SubstituteMatch) {
+        let Some(line) = buffer.get_line(m.line_idx) else {
+            return;
+        };
+        let start = TextLocation {
+            grapheme_idx: line.to_grapheme_idx(m.start),
+            line_idx: m.line_idx,
+        };
+        let end = TextLocation {
+            grapheme_idx: line.to_grapheme_idx(m.end),
+            line_idx: m.line_idx,
+        };
+        buffer.delete_range(start, end);
+        let replacement_end = buffer.insert_text(start, &m.replacement);
+        // replacement may contain line breaks
+        self.last_line_idx += replacement_end.line_idx - m.line_idx;
+        self.n_substitutions += 1;
+        if self.last_substituted_line != Some(m.line_idx) {
+            self.n_lines += 1;
+        }
+        self.last_substituted_line = Some(replacement_end.line_idx);
+        let byte_idx = buffer
+            .get_line(replacement_end.line_idx)
+            .map_or(0, |line| line.to_byte_idx(replacement_end.grapheme_idx));
+        self.advance(buffer, replacement_end.line_idx, byte_idx, m.start == m.end);
+    }
+    // Moves search position to `byte_idx`, or the next line if only
+    // the first match in a line is replaced.
+    // After an empty match, a character is skipped not to match it again.
+    fn advance(&mut self, buffer: &Buffer, line_idx: usize, byte_idx: usize, empty_match: bool) {
+        self.line_idx = line_idx;
+        self.byte_idx = byte_idx;
+        let line = buffer.get_line(line_idx).unwrap_or_default();
+        let line = line.get_raw_str();
+        if empty_match {
+            match line.get(byte_idx..).and_then(|rest| rest.chars().next()) {
+                Some(c) => self.byte_idx += c.len_utf8(),
+                None => self.byte_idx = usize::MAX,
+            }
+        }
+        if !self.global || self.byte_idx > line.len() {
+            self.line_idx += 1;
+            self.byte_idx = 0;
+        }
+    }
+    // Message shown after the command.
+    pub fn report(&self) -> String {
+        let substitutions = if self.n_substitutions == 1 {
+            "substitution"
+        } else {
+            "substitutions"
+        };
+        let lines = if self.n_lines == 1 { "line" } else { "lines" };
+        format!(
+            "{} {} on {} {}",
+            self.n_substitutions, substitutions, self.n_lines, lines
+        )
+    }
+    pub fn is_substituted(&self) -> bool {
+        self.n_substitutions > 0
+    }
+    pub fn has_matched(&self) -> bool {
+        self.has_matched
+    }
+}
+
+// Translates replacement string of vim to `regex` syntax.
+// `&` and `\0` are the whole match, `\1`-`\9` are captured groups,
+// and `\r` and `\n` insert a line break.
+fn translate_replacement(replacement: &str) -> String {
+    let mut result = String::new();
+    let mut chars = replacement.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '&' => result.push_str("${0}"),
+            '$' => result.push_str("$$"),
+            '\\' => match chars.next() {
+                Some(c @ '0'..='9') => result.push_str(&format!("${{{}}}", c)),
+                Some('r' | 'n') => result.push('\n'),
+                Some('t') => result.push('\t'),
+                Some('$') => result.push_str("$$"),
+                Some(c) => result.push(c),
+                None => result.push('\\'),
+            },
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::options::EditorOptions;
+
+    fn buffer_with(text: &str) -> Buffer {
+        let mut buffer = Buffer::default();
+        buffer.insert_text(TextLocation::default(), text);
+        buffer
+    }
+
+    fn session(
+        pattern: &str,
+        replacement: &str,
+        global: bool,
+        lines: (usize, usize),
+    ) -> SubstituteSession {
+        let pattern = SearchPattern::new(pattern, &EditorOptions::default()).unwrap();
+        SubstituteSession::new(pattern, replacement, global, lines)
+    }
+
+    // Replaces all matches, and returns the text and the report.
+    fn substitute(text: &str, pattern: &str, replacement: &str, global: bool) -> (String, String) {
+        let mut buffer = buffer_with(text);
+        let last_line_idx = buffer.get_n_lines() - 1;
+        let mut session = session(pattern, replacement, global, (0, last_line_idx));
+        while let Some(m) = session.find_next(&buffer) {
+            session.replace(&mut buffer, &m);
+        }
+        (buffer.chunks().collect(), session.report())
+    }
+
+    #[test]
+    fn translate_whole_match_and_groups() {
+        assert_eq!(translate_replacement("[&]"), "[${0}]");
+        assert_eq!(translate_replacement(r"\0"), "${0}");
+        assert_eq!(translate_replacement(r"\2\1\9"), "${2}${1}${9}");
+        assert_eq!(translate_replacement(r"\&"), "&");
+    }
+
+    #[test]
+    fn translate_literals() {
+        // `$` is not a group reference in vim
+        assert_eq!(translate_replacement("$1"), "$$1");
+        assert_eq!(translate_replacement(r"\$"), "$$");
+        assert_eq!(translate_replacement(r"a\r\nb\t"), "a\n\nb\t");
+        assert_eq!(translate_replacement(r"\\"), r"\");
+        assert_eq!(translate_replacement(r"a\"), r"a\");
+    }
+
+    #[test]
+    fn substitute_first_match_in_each_line() {
+        let (text, report) = substitute("a a\na a", "a", "b", false);
+        assert_eq!(text, "b a\nb a");
+        assert_eq!(report, "2 substitutions on 2 lines");
+    }
+
+    #[test]
+    fn substitute_all_matches_with_global() {
+        let (text, report) = substitute("a a\nc\na", "a", "b", true);
+        assert_eq!(text, "b b\nc\nb");
+        assert_eq!(report, "3 substitutions on 2 lines");
+    }
+
+    #[test]
+    fn substitute_with_groups() {
+        let (text, _) = substitute("foo bar", r"\(\w\+\) \(\w\+\)", r"\2 \1 [&] $1", false);
+        assert_eq!(text, "bar foo [foo bar] $1");
+    }
+
+    #[test]
+    fn substitute_empty_matches_with_global() {
+        let (text, _) = substitute("abc", "x*", "-", true);
+        assert_eq!(text, "-a-b-c-");
+        let (text, _) = substitute("日本", "", "-", true);
+        assert_eq!(text, "-日-本-");
+        let (text, _) = substitute("ab\ncd", "^", "> ", true);
+        assert_eq!(text, "> ab\n> cd");
+    }
+
+    #[test]
+    fn substitute_line_breaks() {
+        // the range grows with inserted lines
+        let (text, report) = substitute("a,b\nc,d", ",", r"\r", true);
+        assert_eq!(text, "a\nb\nc\nd");
+        assert_eq!(report, "2 substitutions on 2 lines");
+    }
+
+    #[test]
+    fn substitute_replacement_is_not_searched_again() {
+        let (text, _) = substitute("aa", "a", "aa", true);
+        assert_eq!(text, "aaaa");
+    }
+
+    #[test]
+    fn skip_match() {
+        let mut buffer = buffer_with("a a a");
+        let mut session = session("a", "b", true, (0, 0));
+        let m = session.find_next(&buffer).unwrap();
+        session.skip(&buffer, &m);
+        let m = session.find_next(&buffer).unwrap();
+        assert_eq!((m.start, m.end), (2, 3));
+        session.replace(&mut buffer, &m);
+        let m = session.find_next(&buffer).unwrap();
+        session.skip(&buffer, &m);
+        assert!(session.find_next(&buffer).is_none());
+        assert_eq!(buffer.chunks().collect::<String>(), "a b a");
+        assert_eq!(session.report(), "1 substitution on 1 line");
+    }
+
+    #[test]
+    fn no_match_in_range() {
+        let buffer = buffer_with("a\nb\na");
+        let mut session = session("a", "b", false, (1, 1));
+        assert!(session.find_next(&buffer).is_none());
+        assert!(!session.has_matched());
+        assert!(!session.is_substituted());
+    }
+}
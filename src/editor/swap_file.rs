@@ -0,0 +1,278 @@
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use super::buffer::{write_atomically, Buffer};
+
+// First line of swap files, which is followed by fields `pid={id}` of the
+// editor writing it and `lines={count}`, and then by lines of the buffer.
+const HEADER: &str = "hecto swap file";
+// Swap file is updated after this number of changes...
+const UPDATE_COUNT: usize = 200;
+// ...or this time has passed since the last update.
+pub const UPDATE_TIME: Duration = Duration::from_secs(4);
+
+// Returns path of the swap file for `filename`.
+// If `directory` is empty, the swap file is placed next to the file as `.{name}.swp`.
+// Otherwise, the full path is encoded in the name, e.g. `%home%user%a.txt.swp`.
+pub fn swap_path(filename: &str, directory: &str) -> PathBuf {
+    let path = Path::new(filename);
+    if directory.is_empty() {
+        let mut name = String::from(".");
+        name.push_str(&path.file_name().unwrap_or_default().to_string_lossy());
+        name.push_str(".swp");
+        return path.with_file_name(name);
+    }
+    let full_path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let name = full_path
+        .to_string_lossy()
+        .replace(std::path::MAIN_SEPARATOR, "%");
+    Path::new(directory).join(format!("{}.swp", name))
+}
+
+// Reads lines of the buffer recorded in a swap file.
+pub fn read_lines(path: &Path) -> Result<Vec<String>, Error> {
+    let contents = std::fs::read_to_string(path)?;
+    let invalid = || Error::new(ErrorKind::InvalidData, "Not a swap file");
+    let mut body = strip_header(&contents).ok_or_else(invalid)?;
+    let _: u32 = read_field(&mut body, "pid").ok_or_else(invalid)?;
+    // an empty buffer and a buffer with an empty line have the same body
+    let n_lines: usize = read_field(&mut body, "lines").ok_or_else(invalid)?;
+    if n_lines == 0 {
+        return Ok(vec![]);
+    }
+    let lines: Vec<String> = body.split('\n').map(String::from).collect();
+    if lines.len() != n_lines {
+        return Err(Error::new(ErrorKind::InvalidData, "Swap file is broken"));
+    }
+    Ok(lines)
+}
+
+// Returns ID of the process writing the swap file if it is still running,
+// in which case the file is being edited by another editor.
+pub fn running_owner(path: &Path) -> Option<u32> {
+    let contents = std::fs::read_to_string(path).ok()?;
+    let mut fields = strip_header(&contents)?;
+    let pid = read_field(&mut fields, "pid")?;
+    is_running(pid).then_some(pid)
+}
+
+fn strip_header(contents: &str) -> Option<&str> {
+    contents.strip_prefix(HEADER)?.strip_prefix('\n')
+}
+
+// Reads `{name}={value}` line at the start of `contents`, and advances `contents`.
+fn read_field<T: FromStr>(contents: &mut &str, name: &str) -> Option<T> {
+    let (line, rest) = contents.split_once('\n')?;
+    let value = line.strip_prefix(name)?.strip_prefix('=')?.parse().ok()?;
+    *contents = rest;
+    Some(value)
+}
+
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    if pid <= 0 {
+        return false;
+    }
+    // SAFETY: signal 0 only checks whether the process exists
+    let result = unsafe { libc::kill(pid, 0) };
+    // the process exists but belongs to another user
+    result == 0 || Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+// Without a way to tell, the owner is assumed to be gone and recovery is offered.
+#[cfg(not(unix))]
+fn is_running(_pid: u32) -> bool {
+    false
+}
+
+// Swap file written for a modified buffer, so that changes can be recovered
+// when the editor is terminated without saving.
+pub struct SwapFile {
+    path: PathBuf,
+    // `Buffer::get_changedtick()` when written last time
+    changedtick: Option<usize>,
+    written_at: Instant,
+    // the last write failed
+    is_failing: bool,
+}
+
+impl SwapFile {
+    // The file is not written until `write()` is called.
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            changedtick: None,
+            written_at: Instant::now(),
+            is_failing: false,
+        }
+    }
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+    pub fn is_failing(&self) -> bool {
+        self.is_failing
+    }
+    // Returns whether the buffer has changed enough to update the swap file.
+    // While the user is idle, any change is written.
+    pub fn needs_update(&self, buffer: &Buffer, idle: bool) -> bool {
+        let Some(changedtick) = self.changedtick else {
+            return true;
+        };
+        let n_changes = buffer.get_changedtick().wrapping_sub(changedtick);
+        n_changes > 0
+            && (idle || n_changes >= UPDATE_COUNT || self.written_at.elapsed() >= UPDATE_TIME)
+    }
+    pub fn write(&mut self, buffer: &Buffer) -> Result<(), Error> {
+        // retried after a while even if failed
+        self.changedtick = Some(buffer.get_changedtick());
+        self.written_at = Instant::now();
+        // the previous swap file is kept until the new one is written
+        let mut contents = format!(
+            "{}\npid={}\nlines={}\n",
+            HEADER,
+            std::process::id(),
+            buffer.get_n_lines()
+        );
+        contents.extend(buffer.chunks());
+        let result = write_atomically(&self.path, contents.as_bytes(), None);
+        self.is_failing = result.is_err();
+        result
+    }
+    pub fn remove(self) {
+        // explicitly ignore errors, since the file may be removed by the user
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::window::TextLocation;
+
+    #[test]
+    fn swap_path_next_to_file() {
+        assert_eq!(
+            swap_path("/home/user/a.txt", ""),
+            PathBuf::from("/home/user/.a.txt.swp")
+        );
+        assert_eq!(swap_path("a.txt", ""), PathBuf::from(".a.txt.swp"));
+    }
+
+    #[test]
+    fn swap_path_in_directory() {
+        assert_eq!(
+            swap_path("/home/user/a.txt", "/tmp/swap"),
+            PathBuf::from("/tmp/swap/%home%user%a.txt.swp")
+        );
+        // relative paths are made absolute
+        let expected = std::env::current_dir()
+            .unwrap()
+            .join("a.txt")
+            .to_string_lossy()
+            .replace(std::path::MAIN_SEPARATOR, "%");
+        assert_eq!(
+            swap_path("a.txt", "/tmp/swap"),
+            Path::new("/tmp/swap").join(format!("{}.swp", expected))
+        );
+    }
+
+    // Returns an empty directory for files of the test.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hecto-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn write_and_read_lines() {
+        let dir = test_dir("write_and_read_lines");
+        let mut swap_file = SwapFile::new(dir.join(".a.txt.swp"));
+
+        let mut buffer = Buffer::default();
+        swap_file.write(&buffer).unwrap();
+        assert_eq!(
+            read_lines(swap_file.get_path()).unwrap(),
+            Vec::<String>::new()
+        );
+
+        buffer.insert_text(TextLocation::default(), "foo\n\nbär ");
+        swap_file.write(&buffer).unwrap();
+        assert!(!swap_file.is_failing());
+        assert_eq!(
+            read_lines(swap_file.get_path()).unwrap(),
+            ["foo", "", "bär "]
+        );
+
+        swap_file.remove();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn write_and_read_empty_line() {
+        let dir = test_dir("write_and_read_empty_line");
+        let mut swap_file = SwapFile::new(dir.join(".a.txt.swp"));
+        // a single empty line is distinct from no lines
+        let mut buffer = Buffer::default();
+        buffer.recover(vec![String::new()]);
+        swap_file.write(&buffer).unwrap();
+        let lines = read_lines(swap_file.get_path()).unwrap();
+        assert_eq!(lines, [""]);
+        let mut recovered = Buffer::default();
+        recovered.recover(lines);
+        assert_eq!(recovered.get_n_lines(), 1);
+
+        swap_file.remove();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn read_invalid_swap_files() {
+        let dir = test_dir("read_invalid_swap_files");
+        let path = dir.join(".a.txt.swp");
+        for contents in [
+            "foo\n",
+            "hecto swap file\nfoo\n",
+            "hecto swap file\nlines=1\nfoo",
+            "hecto swap file\npid=1\nlines=x\nfoo",
+            "hecto swap file\npid=1\nlines=3\nfoo\nbar",
+        ] {
+            std::fs::write(&path, contents).unwrap();
+            assert_eq!(
+                read_lines(&path).unwrap_err().kind(),
+                ErrorKind::InvalidData,
+                "{}",
+                contents
+            );
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn owner_of_swap_file() {
+        let dir = test_dir("owner_of_swap_file");
+        let mut swap_file = SwapFile::new(dir.join(".a.txt.swp"));
+        swap_file.write(&Buffer::default()).unwrap();
+        assert_eq!(
+            running_owner(swap_file.get_path()),
+            Some(std::process::id())
+        );
+        // a process which has exited
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        let path = dir.join(".b.txt.swp");
+        std::fs::write(&path, format!("hecto swap file\npid={}\nlines=0\n", pid)).unwrap();
+        assert_eq!(running_owner(&path), None);
+        assert_eq!(read_lines(&path).unwrap(), Vec::<String>::new());
+
+        swap_file.remove();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,401 @@
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use super::annotated_string::Style;
+use super::options;
+
+// Syntax definitions shipped with the editor.
+const BUILTIN_DEFINITIONS: [(&str, &str); 8] = [
+    ("c.syntax", include_str!("../../syntax/c.syntax")),
+    ("python.syntax", include_str!("../../syntax/python.syntax")),
+    ("go.syntax", include_str!("../../syntax/go.syntax")),
+    (
+        "javascript.syntax",
+        include_str!("../../syntax/javascript.syntax"),
+    ),
+    ("toml.syntax", include_str!("../../syntax/toml.syntax")),
+    ("json.syntax", include_str!("../../syntax/json.syntax")),
+    (
+        "markdown.syntax",
+        include_str!("../../syntax/markdown.syntax"),
+    ),
+    ("sh.syntax", include_str!("../../syntax/sh.syntax")),
+];
+
+// Delimiters of a string literal.
+#[derive(Debug)]
+pub struct StringRule {
+    pub open: String,
+    pub close: String,
+    // the string may continue to following lines
+    pub multiline: bool,
+    // backslash does not escape the closing delimiter
+    pub raw: bool,
+}
+
+// Forms of number literals recognized in addition to decimal integers.
+#[derive(Default, Debug)]
+pub struct NumberRule {
+    pub hex: bool,
+    pub binary: bool,
+    pub octal: bool,
+    pub float: bool,
+    // character allowed between digits, such as `_` in `1_000`
+    pub separator: Option<char>,
+}
+
+// Lines starting with `prefix` after indentation are drawn in `style` as a whole.
+#[derive(Debug)]
+pub struct LineRule {
+    pub prefix: String,
+    pub style: Style,
+}
+
+// Syntax of a language, which is read from a definition file such as:
+//
+//     # comment
+//     name = c
+//     extensions = c h
+//     keywords = if else for while return
+//     types = int char void
+//     constants = NULL
+//     line_comment = //
+//     block_comment = /* */
+//     string = " "
+//     string = """ """ multiline raw
+//     numbers = hex float
+//     number_separator = '
+//     word_chars = $
+//     line_start = # keyword
+//
+// Keys taking a list can be repeated to extend the list.
+#[derive(Default, Debug)]
+pub struct SyntaxDefinition {
+    pub name: String,
+    pub extensions: Vec<String>,
+    // file names matched as a whole, such as `.bashrc`
+    pub filenames: Vec<String>,
+    pub keywords: Vec<String>,
+    pub types: Vec<String>,
+    pub constants: Vec<String>,
+    pub line_comments: Vec<String>,
+    pub block_comments: Vec<(String, String)>,
+    pub strings: Vec<StringRule>,
+    // `None` if numbers are not highlighted
+    pub numbers: Option<NumberRule>,
+    // characters other than letters, digits and `_` which may be part of words
+    pub word_chars: Vec<char>,
+    pub line_rules: Vec<LineRule>,
+}
+
+impl SyntaxDefinition {
+    // Returns an error message with the line number if the definition is invalid.
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut definition = Self::default();
+        for (idx, line) in source.lines().enumerate() {
+            definition
+                .parse_line(line)
+                .map_err(|err| format!("line {}: {}", idx + 1, err))?;
+        }
+        if definition.name.is_empty() {
+            return Err(String::from("name is not defined"));
+        }
+        Ok(definition)
+    }
+    fn parse_line(&mut self, line: &str) -> Result<(), String> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(());
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(format!("Expected `key = value`: {}", line));
+        };
+        let key = key.trim();
+        let words: Vec<String> = value.split_whitespace().map(String::from).collect();
+        match key {
+            "name" => match words.as_slice() {
+                [name] => self.name = name.clone(),
+                _ => return Err(String::from("name must be a single word")),
+            },
+            "extensions" => self.extensions.extend(words),
+            "filenames" => self.filenames.extend(words),
+            "keywords" => self.keywords.extend(words),
+            "types" => self.types.extend(words),
+            "constants" => self.constants.extend(words),
+            "line_comment" => self.line_comments.extend(words),
+            "block_comment" => match words.as_slice() {
+                [open, close] => self.block_comments.push((open.clone(), close.clone())),
+                _ => {
+                    return Err(String::from(
+                        "block_comment takes open and close delimiters",
+                    ))
+                }
+            },
+            "string" => match words.as_slice() {
+                [open, close, flags @ ..] => {
+                    let mut rule = StringRule {
+                        open: open.clone(),
+                        close: close.clone(),
+                        multiline: false,
+                        raw: false,
+                    };
+                    for flag in flags {
+                        match flag.as_str() {
+                            "multiline" => rule.multiline = true,
+                            "raw" => rule.raw = true,
+                            _ => return Err(format!("Unknown string flag: {}", flag)),
+                        }
+                    }
+                    self.strings.push(rule);
+                }
+                _ => return Err(String::from("string takes open and close delimiters")),
+            },
+            "numbers" => {
+                let rule = self.numbers.get_or_insert_with(NumberRule::default);
+                for word in &words {
+                    match word.as_str() {
+                        "decimal" => {}
+                        "hex" => rule.hex = true,
+                        "binary" => rule.binary = true,
+                        "octal" => rule.octal = true,
+                        "float" => rule.float = true,
+                        _ => return Err(format!("Unknown number form: {}", word)),
+                    }
+                }
+            }
+            "number_separator" => {
+                let rule = self.numbers.get_or_insert_with(NumberRule::default);
+                let mut chars = value.trim().chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => rule.separator = Some(c),
+                    _ => return Err(String::from("number_separator must be a character")),
+                }
+            }
+            "word_chars" => self
+                .word_chars
+                .extend(value.trim().chars().filter(|c| *c != ' ')),
+            "line_start" => match words.as_slice() {
+                [prefix, style] => {
+                    let style = Style::from_name(style)
+                        .ok_or_else(|| format!("Unknown style: {}", style))?;
+                    self.line_rules.push(LineRule {
+                        prefix: prefix.clone(),
+                        style,
+                    });
+                }
+                _ => return Err(String::from("line_start takes a prefix and a style")),
+            },
+            _ => return Err(format!("Unknown key: {}", key)),
+        }
+        Ok(())
+    }
+    fn matches_filename(&self, filename: &str) -> bool {
+        let path = Path::new(filename);
+        let name = path.file_name().and_then(|name| name.to_str());
+        let ext = path.extension().and_then(|ext| ext.to_str());
+        name.is_some_and(|name| self.filenames.iter().any(|f| f == name))
+            || ext.is_some_and(|ext| self.extensions.iter().any(|e| e == ext))
+    }
+}
+
+// Definitions shipped with the editor and read from the user's directory.
+#[derive(Default)]
+struct Registry {
+    definitions: Vec<SyntaxDefinition>,
+    errors: Vec<String>,
+}
+
+impl Registry {
+    fn load() -> Self {
+        let mut registry = Self::default();
+        for (filename, source) in BUILTIN_DEFINITIONS {
+            registry.add(filename, source);
+        }
+        // user definitions take precedence over built-in ones of the same name
+        if let Some(entries) = user_directory().and_then(|dir| std::fs::read_dir(dir).ok()) {
+            let mut paths: Vec<_> = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "syntax"))
+                .collect();
+            paths.sort();
+            for path in paths {
+                let filename = path.display().to_string();
+                match std::fs::read_to_string(&path) {
+                    Ok(source) => registry.add(&filename, &source),
+                    Err(err) => registry.errors.push(format!("{}: {}", filename, err)),
+                }
+            }
+        }
+        registry
+    }
+    fn add(&mut self, filename: &str, source: &str) {
+        match SyntaxDefinition::parse(source) {
+            Ok(definition) => {
+                self.definitions.retain(|d| d.name != definition.name);
+                self.definitions.push(definition);
+            }
+            Err(err) => self.errors.push(format!("{}: {}", filename, err)),
+        }
+    }
+}
+
+// `$XDG_CONFIG_HOME/hecto/syntax`, or `~/.config/hecto/syntax`
+fn user_directory() -> Option<PathBuf> {
+    Some(options::config_directory()?.join("syntax"))
+}
+
+fn registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(Registry::load)
+}
+
+pub fn find_by_filename(filename: &str) -> Option<&'static SyntaxDefinition> {
+    registry()
+        .definitions
+        .iter()
+        .rev()
+        .find(|definition| definition.matches_filename(filename))
+}
+
+pub fn find_by_name(name: &str) -> Option<&'static SyntaxDefinition> {
+    registry()
+        .definitions
+        .iter()
+        .find(|definition| definition.name == name)
+}
+
+// Errors in definition files, which are reported once at startup.
+pub fn load_errors() -> &'static [String] {
+    &registry().errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_definitions_are_valid() {
+        for (filename, source) in BUILTIN_DEFINITIONS {
+            let definition = SyntaxDefinition::parse(source)
+                .unwrap_or_else(|err| panic!("{}: {}", filename, err));
+            assert_eq!(format!("{}.syntax", definition.name), filename);
+            assert!(!definition.extensions.is_empty(), "{}", filename);
+        }
+    }
+
+    #[test]
+    fn parse_definition() {
+        let source = "\
+# comment
+name = test
+extensions = t
+extensions = tt
+filenames = .testrc
+keywords = if else
+keywords = for
+types = int
+constants = NULL
+line_comment = // #
+block_comment = /* */
+block_comment = {- -}
+string = \" \"
+string = ''' ''' multiline raw
+numbers = decimal hex
+numbers = float
+number_separator = '
+word_chars = $ @
+line_start = > comment
+";
+        let definition = SyntaxDefinition::parse(source).unwrap();
+        assert_eq!(definition.name, "test");
+        assert_eq!(definition.extensions, ["t", "tt"]);
+        assert_eq!(definition.filenames, [".testrc"]);
+        assert_eq!(definition.keywords, ["if", "else", "for"]);
+        assert_eq!(definition.types, ["int"]);
+        assert_eq!(definition.constants, ["NULL"]);
+        assert_eq!(definition.line_comments, ["//", "#"]);
+        assert_eq!(
+            definition.block_comments,
+            [
+                (String::from("/*"), String::from("*/")),
+                (String::from("{-"), String::from("-}"))
+            ]
+        );
+        let strings: Vec<_> = definition
+            .strings
+            .iter()
+            .map(|rule| {
+                (
+                    rule.open.as_str(),
+                    rule.close.as_str(),
+                    rule.multiline,
+                    rule.raw,
+                )
+            })
+            .collect();
+        assert_eq!(
+            strings,
+            [("\"", "\"", false, false), ("'''", "'''", true, true)]
+        );
+        let numbers = definition.numbers.unwrap();
+        assert!(numbers.hex && numbers.float && !numbers.binary && !numbers.octal);
+        assert_eq!(numbers.separator, Some('\''));
+        assert_eq!(definition.word_chars, ['$', '@']);
+        assert_eq!(definition.line_rules[0].prefix, ">");
+        assert_eq!(definition.line_rules[0].style, Style::Comment);
+    }
+
+    #[test]
+    fn numbers_are_not_highlighted_by_default() {
+        let definition = SyntaxDefinition::parse("name = test").unwrap();
+        assert!(definition.numbers.is_none());
+        let definition = SyntaxDefinition::parse("name = test\nnumbers = decimal").unwrap();
+        assert!(definition.numbers.is_some());
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |source: &str| SyntaxDefinition::parse(source).unwrap_err();
+        assert_eq!(error("keywords = if"), "name is not defined");
+        assert_eq!(error("name = a b"), "line 1: name must be a single word");
+        assert_eq!(
+            error("name = t\nfoo"),
+            "line 2: Expected `key = value`: foo"
+        );
+        assert_eq!(
+            error("name = t\nkeyword = if"),
+            "line 2: Unknown key: keyword"
+        );
+        assert_eq!(
+            error("name = t\n\nblock_comment = /*"),
+            "line 3: block_comment takes open and close delimiters"
+        );
+        assert_eq!(
+            error("name = t\nstring = \" \" nested"),
+            "line 2: Unknown string flag: nested"
+        );
+        assert_eq!(
+            error("name = t\nnumbers = roman"),
+            "line 2: Unknown number form: roman"
+        );
+        assert_eq!(
+            error("name = t\nnumber_separator = _'"),
+            "line 2: number_separator must be a character"
+        );
+        assert_eq!(
+            error("name = t\nline_start = # keywords"),
+            "line 2: Unknown style: keywords"
+        );
+    }
+
+    #[test]
+    fn match_filenames() {
+        let definition =
+            SyntaxDefinition::parse("name = sh\nextensions = sh\nfilenames = .bashrc").unwrap();
+        assert!(definition.matches_filename("a.sh"));
+        assert!(definition.matches_filename("/home/user/.bashrc"));
+        assert!(!definition.matches_filename("a.bash"));
+        assert!(!definition.matches_filename("sh"));
+        assert!(!definition.matches_filename("bashrc"));
+    }
+}
@@ -1,21 +1,193 @@
-use crossterm::cursor::MoveTo;
-use crossterm::execute;
-use crossterm::style::{Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use std::cell::RefCell;
+use std::io::Write;
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::style::{Attribute, Print, SetAttribute, SetBackgroundColor, SetForegroundColor};
 use crossterm::terminal::{
     disable_raw_mode, enable_raw_mode, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen,
 };
+use crossterm::{execute, queue};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
 use super::annotated_string::{AnnotatedString, DrawingOptions};
 
+mod color;
+use color::ColorSupport;
+
+// Drawing functions write to a frame in memory, and `Terminal::flush()`
+// sends only the cells changed since the last frame to the terminal.
 pub struct Terminal {}
 
-#[derive(Copy, Clone)]
+#[derive(Clone, PartialEq, Eq)]
+struct Cell {
+    // empty for the right half of a wide character
+    symbol: String,
+    options: DrawingOptions,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            symbol: String::from(" "),
+            options: DrawingOptions::default(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Screen {
+    size: Size,
+    // frame being drawn
+    back: Vec<Cell>,
+    // frame shown on the terminal, or empty if the terminal must be cleared
+    front: Vec<Cell>,
+    cursor: Position,
+    // colors are downgraded to the palette when flushed
+    color_support: ColorSupport,
+}
+
+impl Screen {
+    fn reset(&mut self, size: Size) {
+        self.size = size;
+        self.back = vec![Cell::default(); size.height * size.width];
+        self.front.clear();
+    }
+    fn clear_row(&mut self, row: usize) {
+        if row < self.size.height {
+            let start = row * self.size.width;
+            self.back[start..start + self.size.width].fill(Cell::default());
+        }
+    }
+    // Writes `s` at the cursor. Text beyond the right edge is dropped.
+    fn put_str(&mut self, s: &str, options: DrawingOptions) {
+        for grapheme in s.graphemes(true) {
+            match grapheme {
+                "\r" => self.cursor.col = 0,
+                "\n" | "\r\n" => {
+                    self.cursor.col = 0;
+                    self.cursor.row += 1;
+                }
+                _ => {
+                    let width = grapheme.width_cjk().max(1);
+                    let Position { row, col } = self.cursor;
+                    self.cursor.col += width;
+                    if row >= self.size.height || col + width > self.size.width {
+                        continue;
+                    }
+                    let cell = |symbol: &str| Cell {
+                        symbol: String::from(symbol),
+                        options,
+                    };
+                    self.put_cell(row, col, cell(grapheme));
+                    for offset in 1..width {
+                        self.put_cell(row, col + offset, cell(""));
+                    }
+                }
+            }
+        }
+    }
+    // Wide characters partially overwritten are replaced with spaces.
+    fn put_cell(&mut self, row: usize, col: usize, cell: Cell) {
+        let idx = row * self.size.width + col;
+        if self.back[idx].symbol.is_empty() && !cell.symbol.is_empty() && col > 0 {
+            self.back[idx - 1].symbol = String::from(" ");
+        }
+        if col + 1 < self.size.width && self.back[idx + 1].symbol.is_empty() {
+            self.back[idx + 1].symbol = String::from(" ");
+        }
+        self.back[idx] = cell;
+    }
+    fn flush(&mut self) -> Result<(), std::io::Error> {
+        let mut stdout = std::io::stdout().lock();
+        queue!(stdout, Hide)?;
+        if self.front.len() != self.back.len() {
+            queue!(
+                stdout,
+                SetAttribute(Attribute::Reset),
+                Clear(ClearType::All)
+            )?;
+            self.front = vec![Cell::default(); self.back.len()];
+        }
+        let mut options = None;
+        for row in 0..self.size.height {
+            let mut col = 0;
+            while col < self.size.width {
+                let idx = row * self.size.width + col;
+                if self.back[idx] == self.front[idx] {
+                    col += 1;
+                    continue;
+                }
+                // a changed span starts from the left half of a wide character
+                if self.back[idx].symbol.is_empty() && col > 0 {
+                    col -= 1;
+                }
+                queue!(stdout, MoveTo(col as u16, row as u16))?;
+                loop {
+                    let cell = &self.back[row * self.size.width + col];
+                    if options != Some(cell.options) {
+                        self.set_drawing_options(&mut stdout, cell.options)?;
+                        options = Some(cell.options);
+                    }
+                    queue!(stdout, Print(&cell.symbol))?;
+                    col += 1;
+                    // the span continues over the right half of a wide character
+                    let idx = row * self.size.width + col;
+                    if col >= self.size.width
+                        || (self.back[idx] == self.front[idx] && !self.back[idx].symbol.is_empty())
+                    {
+                        break;
+                    }
+                }
+            }
+        }
+        let Position { row, col } = self.cursor;
+        queue!(
+            stdout,
+            SetAttribute(Attribute::Reset),
+            MoveTo(col as u16, row as u16),
+            Show
+        )?;
+        stdout.flush()?;
+        self.front.clone_from(&self.back);
+        Ok(())
+    }
+    fn set_drawing_options(
+        &self,
+        stdout: &mut impl Write,
+        options: DrawingOptions,
+    ) -> Result<(), std::io::Error> {
+        // resetting attributes also resets colors
+        queue!(
+            stdout,
+            SetAttribute(Attribute::Reset),
+            SetForegroundColor(self.color_support.downgrade(options.foreground_color)),
+            SetBackgroundColor(self.color_support.downgrade(options.background_color))
+        )?;
+        if options.bold {
+            queue!(stdout, SetAttribute(Attribute::Bold))?;
+        }
+        if options.italic {
+            queue!(stdout, SetAttribute(Attribute::Italic))?;
+        }
+        if options.underline {
+            queue!(stdout, SetAttribute(Attribute::Underlined))?;
+        }
+        Ok(())
+    }
+}
+
+thread_local! {
+    static SCREEN: RefCell<Screen> = RefCell::new(Screen::default());
+}
+
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
 pub struct Size {
     pub height: usize,
     pub width: usize,
 }
 
-#[derive(Copy, Clone, Default, Debug)]
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
 pub struct Position {
     pub col: usize,
     pub row: usize,
@@ -25,6 +197,7 @@ impl Terminal {
     pub fn initialize() -> Result<(), std::io::Error> {
         Self::enter_alternate_screen()?;
         enable_raw_mode()?;
+        SCREEN.with_borrow_mut(|screen| screen.color_support = ColorSupport::detect());
         Self::clear_screen()?;
         Ok(())
     }
@@ -33,14 +206,20 @@ impl Terminal {
         disable_raw_mode()?;
         Ok(())
     }
+    // The terminal is cleared when the frame is flushed next time.
     pub fn clear_screen() -> Result<(), std::io::Error> {
-        execute!(std::io::stdout(), Clear(ClearType::All))?;
+        let size = Self::size()?;
+        SCREEN.with_borrow_mut(|screen| screen.reset(size));
         Ok(())
     }
     pub fn clear_line() -> Result<(), std::io::Error> {
-        execute!(std::io::stdout(), Clear(ClearType::CurrentLine))?;
+        SCREEN.with_borrow_mut(|screen| screen.clear_row(screen.cursor.row));
         Ok(())
     }
+    // Sends changes of the frame to the terminal.
+    pub fn flush() -> Result<(), std::io::Error> {
+        SCREEN.with_borrow_mut(Screen::flush)
+    }
     pub fn enter_alternate_screen() -> Result<(), std::io::Error> {
         execute!(std::io::stdout(), EnterAlternateScreen)?;
         Ok(())
@@ -50,54 +229,23 @@ impl Terminal {
         Ok(())
     }
     pub fn move_cursor_to(position: Position) -> Result<(), std::io::Error> {
-        execute!(
-            std::io::stdout(),
-            MoveTo(position.col as u16, position.row as u16)
-        )?;
+        SCREEN.with_borrow_mut(|screen| screen.cursor = position);
         Ok(())
     }
     pub fn print(s: &str) -> Result<(), std::io::Error> {
-        execute!(std::io::stdout(), Print(s))?;
+        SCREEN.with_borrow_mut(|screen| screen.put_str(s, DrawingOptions::default()));
         Ok(())
     }
     pub fn print_annotated_str(s: &AnnotatedString) -> Result<(), std::io::Error> {
-        let segments = s.into_segments();
-        for seg in &segments {
-            if let Some(style) = &seg.style {
-                let DrawingOptions {
-                    foreground_color,
-                    background_color,
-                } = style.get_drawing_options();
-                execute!(
-                    std::io::stdout(),
-                    SetForegroundColor(foreground_color),
-                    SetBackgroundColor(background_color)
-                )?;
+        let segments = s.to_segments();
+        SCREEN.with_borrow_mut(|screen| {
+            for seg in &segments {
+                let options = seg.style.map_or(DrawingOptions::default(), |style| {
+                    style.get_drawing_options()
+                });
+                screen.put_str(&seg.string, options);
             }
-            execute!(std::io::stdout(), Print(&seg.string))?;
-            if seg.style.is_some() {
-                execute!(std::io::stdout(), ResetColor)?;
-            }
-        }
-        Ok(())
-    }
-    pub fn print_log(s: &str) -> Result<(), std::io::Error> {
-        // Leave alternate screen temporarily, and send log
-        // message to normal screen.
-        execute!(
-            std::io::stdout(),
-            // Temporarily leave alternate screen.
-            Print("\x1b[?1049l"), // Same as `LeaveAlternateScreen`
-            // Write log message.
-            Print(s),
-            Print("\r\n"),
-            // Re-enter to alternate screen without clearing screen buffer.
-            // `EnterAlternateScreen` (CSI ?1049h) does not work because it will
-            // clear the screen buffer, when entering to alternate screen.
-            // see xterm manual: https://invisible-island.net/xterm/ctlseqs/ctlseqs.html#h3-Functions-using-CSI-_-ordered-by-the-final-character_s_
-            Print("\x1b[?1048h"), // Save cursor (DECSC)
-            Print("\x1b[?1047h"), // Enter alternate screen (without clearing buffer)
-        )?;
+        });
         Ok(())
     }
     pub fn size() -> Result<Size, std::io::Error> {
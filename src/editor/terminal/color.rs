@@ -0,0 +1,187 @@
+use crossterm::style::Color;
+
+// Colors the terminal can display.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum ColorSupport {
+    // 24-bit RGB colors
+    #[default]
+    TrueColor,
+    // xterm's 256-color palette
+    Ansi256,
+    // the 16 basic colors
+    Ansi16,
+}
+
+impl ColorSupport {
+    // Guesses from `$COLORTERM` and `$TERM` like other terminal programs do.
+    pub fn detect() -> Self {
+        let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+        let term = std::env::var("TERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" || term.ends_with("-direct") {
+            Self::TrueColor
+        } else if term.contains("256color") {
+            Self::Ansi256
+        } else {
+            Self::Ansi16
+        }
+    }
+    // Replaces colors the terminal cannot display with the nearest ones in its palette.
+    pub fn downgrade(self, color: Color) -> Color {
+        match (self, color) {
+            (Self::Ansi256, Color::Rgb { r, g, b }) => Color::AnsiValue(nearest_ansi256(r, g, b)),
+            (Self::Ansi16, Color::Rgb { r, g, b }) => nearest_ansi16(r, g, b),
+            (Self::Ansi16, Color::AnsiValue(value)) => match ANSI16.get(usize::from(value)) {
+                Some(color) => *color,
+                None => {
+                    let (r, g, b) = ansi256_to_rgb(value);
+                    nearest_ansi16(r, g, b)
+                }
+            },
+            _ => color,
+        }
+    }
+}
+
+// Basic colors in the order of their indices.
+const ANSI16: [Color; 16] = [
+    Color::Black,
+    Color::DarkRed,
+    Color::DarkGreen,
+    Color::DarkYellow,
+    Color::DarkBlue,
+    Color::DarkMagenta,
+    Color::DarkCyan,
+    Color::Grey,
+    Color::DarkGrey,
+    Color::Red,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::White,
+];
+
+// Levels of each component in the 6x6x6 color cube of the 256-color palette.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let diff = |a: u8, b: u8| (i32::from(a) - i32::from(b)).unsigned_abs();
+    diff(r1, r2).pow(2) + diff(g1, g2).pow(2) + diff(b1, b2).pow(2)
+}
+
+// Returns RGB of a color in the color cube (16 to 231) or the grayscale ramp (232 to 255).
+fn ansi256_to_rgb(value: u8) -> (u8, u8, u8) {
+    if value >= 232 {
+        let level = 8 + (value - 232) * 10;
+        return (level, level, level);
+    }
+    let idx = value.saturating_sub(16);
+    (
+        CUBE_LEVELS[usize::from(idx / 36)],
+        CUBE_LEVELS[usize::from(idx / 6 % 6)],
+        CUBE_LEVELS[usize::from(idx % 6)],
+    )
+}
+
+// Picks the nearest color from the color cube and the grayscale ramp,
+// leaving the basic colors which vary among terminals.
+fn nearest_ansi256(r: u8, g: u8, b: u8) -> u8 {
+    (16..=255)
+        .min_by_key(|value| distance((r, g, b), ansi256_to_rgb(*value)))
+        .unwrap_or_default()
+}
+
+// Picks a basic color by hue and brightness, since the distance in RGB
+// maps most muted colors to gray.
+fn nearest_ansi16(r: u8, g: u8, b: u8) -> Color {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    if max - min < 32 {
+        let lightness = (u16::from(max) + u16::from(min)) / 2;
+        return match lightness {
+            0..=63 => Color::Black,
+            64..=159 => Color::DarkGrey,
+            160..=223 => Color::Grey,
+            _ => Color::White,
+        };
+    }
+    // components brighter than the middle make up the hue
+    let middle = (u16::from(max) + u16::from(min)) / 2;
+    let is_on = |c: u8| u16::from(c) > middle;
+    let idx = usize::from(is_on(r)) | usize::from(is_on(g)) << 1 | usize::from(is_on(b)) << 2;
+    let bright = if max >= 192 { 8 } else { 0 };
+    ANSI16[idx + bright]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truecolor_keeps_colors() {
+        for color in [
+            Color::Rgb {
+                r: 0x97,
+                g: 0x76,
+                b: 0xd8,
+            },
+            Color::AnsiValue(196),
+            Color::Red,
+            Color::Reset,
+        ] {
+            assert_eq!(ColorSupport::TrueColor.downgrade(color), color);
+        }
+    }
+
+    #[test]
+    fn truecolor_to_256() {
+        let downgrade = |r, g, b| ColorSupport::Ansi256.downgrade(Color::Rgb { r, g, b });
+        // color cube
+        assert_eq!(downgrade(255, 0, 0), Color::AnsiValue(196));
+        assert_eq!(downgrade(95, 135, 175), Color::AnsiValue(67));
+        assert_eq!(downgrade(250, 10, 100), Color::AnsiValue(197));
+        assert_eq!(downgrade(0, 0, 0), Color::AnsiValue(16));
+        assert_eq!(downgrade(255, 255, 255), Color::AnsiValue(231));
+        // grayscale ramp
+        assert_eq!(downgrade(128, 128, 128), Color::AnsiValue(244));
+        assert_eq!(downgrade(30, 32, 28), Color::AnsiValue(234));
+        // colors of the palette are kept
+        assert_eq!(
+            ColorSupport::Ansi256.downgrade(Color::AnsiValue(3)),
+            Color::AnsiValue(3)
+        );
+        assert_eq!(ColorSupport::Ansi256.downgrade(Color::Reset), Color::Reset);
+    }
+
+    #[test]
+    fn ansi256_to_16() {
+        let downgrade = |value| ColorSupport::Ansi16.downgrade(Color::AnsiValue(value));
+        // basic colors
+        assert_eq!(downgrade(1), Color::DarkRed);
+        assert_eq!(downgrade(8), Color::DarkGrey);
+        assert_eq!(downgrade(15), Color::White);
+        // color cube
+        assert_eq!(downgrade(196), Color::Red);
+        assert_eq!(downgrade(21), Color::Blue);
+        assert_eq!(downgrade(28), Color::DarkGreen);
+        assert_eq!(downgrade(16), Color::Black);
+        // grayscale ramp
+        assert_eq!(downgrade(232), Color::Black);
+        assert_eq!(downgrade(244), Color::DarkGrey);
+        assert_eq!(downgrade(250), Color::Grey);
+        assert_eq!(downgrade(255), Color::White);
+    }
+
+    #[test]
+    fn truecolor_to_16() {
+        let downgrade = |r, g, b| ColorSupport::Ansi16.downgrade(Color::Rgb { r, g, b });
+        assert_eq!(downgrade(0x61, 0xaf, 0xef), Color::Cyan);
+        assert_eq!(downgrade(0x98, 0xc3, 0x79), Color::Green);
+        assert_eq!(downgrade(0xe0, 0x6c, 0x75), Color::Red);
+        assert_eq!(downgrade(0x80, 0x00, 0x80), Color::DarkMagenta);
+        assert_eq!(downgrade(0x28, 0x2c, 0x34), Color::Black);
+        assert_eq!(ColorSupport::Ansi16.downgrade(Color::Red), Color::Red);
+        assert_eq!(ColorSupport::Ansi16.downgrade(Color::Reset), Color::Reset);
+    }
+}
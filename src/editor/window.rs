@@ -1,26 +1,80 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
 use super::terminal::{Position, Size, Terminal};
 
 use super::annotated_string::AnnotatedString;
 use super::RenderContext;
 
-use super::editor_command::Direction;
+use super::editor_command::{Direction, Operator, OperatorTarget, VisualSubmode};
 use super::SearchDirection;
 
-use super::buffer::grapheme::Grapheme;
-use super::buffer::Buffer;
+use super::registers::{RegisterContent, RegisterKind};
+use super::search_pattern::SearchPattern;
+use super::substitute::{SubstituteMatch, SubstituteSession};
+
+use super::buffer::grapheme::{Grapheme, TABSTOP};
+use super::buffer::{Buffer, BufferError, Edit};
 
+use super::status_bar::StatusBar;
 use super::DocumentStatus;
 
-use super::buffer::LineView;
+use super::buffer::{Line, LineView};
 
 use super::highlighter::{HighlighterBundler, LineHighlighter};
 
-#[derive(Copy, Clone, Default, Debug)]
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
 pub struct TextLocation {
     pub grapheme_idx: usize,
     pub line_idx: usize,
 }
 
+#[derive(Copy, Clone)]
+pub struct Selection {
+    pub kind: VisualSubmode,
+    // `start` and `end` are ordered, and `end` is inclusive.
+    pub start: TextLocation,
+    pub end: TextLocation,
+    // terminal column range [left, right) for blockwise selection
+    pub columns: (usize, usize),
+}
+
+impl Selection {
+    pub fn register_kind(&self) -> RegisterKind {
+        match self.kind {
+            VisualSubmode::Characterwise => RegisterKind::Characterwise,
+            VisualSubmode::Linewise => RegisterKind::Linewise,
+            VisualSubmode::Blockwise => RegisterKind::Blockwise,
+        }
+    }
+    // Returns selected grapheme range [start, end) in the line.
+    pub fn grapheme_range(&self, line_idx: usize, line: &Line) -> Option<(usize, usize)> {
+        if line_idx < self.start.line_idx || self.end.line_idx < line_idx {
+            return None;
+        }
+        match self.kind {
+            VisualSubmode::Characterwise => {
+                let start = if line_idx == self.start.line_idx {
+                    self.start.grapheme_idx
+                } else {
+                    0
+                };
+                let end = if line_idx == self.end.line_idx {
+                    usize::min(self.end.grapheme_idx + 1, line.len())
+                } else {
+                    line.len()
+                };
+                Some((start, end))
+            }
+            VisualSubmode::Linewise => Some((0, line.len())),
+            VisualSubmode::Blockwise => {
+                let (left, right) = self.columns;
+                Some(line.graphemes_in_columns(left, right))
+            }
+        }
+    }
+}
+
 struct CursorInfo {
     // grapheme at cursor position
     // maybe `None` if cursor is at empty line
@@ -32,58 +86,165 @@ struct CursorInfo {
 }
 
 pub struct Window {
-    buffer: Buffer,
+    // shared with other windows showing the same buffer
+    buffer: Rc<RefCell<Buffer>>,
     needs_redraw: bool,
     cursor_location: TextLocation,
     scroll_offset: Position,
+    // top-left of the window on the terminal
+    origin: Position,
+    // size of the text area, excluding the status line and the separator
     size: Size,
+    // whether a vertical separator is drawn on the right
+    has_separator: bool,
+    status_bar: StatusBar,
+    // anchor of visual mode selection; the other end is the cursor.
+    visual_anchor: Option<(TextLocation, VisualSubmode)>,
+    // first and last lines of the last selection, used by `'<` and `'>`
+    visual_lines: Option<(usize, usize)>,
 }
 
 impl Window {
     const NAME: &'static str = env!("CARGO_PKG_NAME");
     const VERSION: &'static str = env!("CARGO_PKG_VERSION");
 
-    pub fn new(margin: usize) -> Self {
-        let size = Terminal::size().expect("Failed to get terminal size.");
+    pub fn new(buffer: Rc<RefCell<Buffer>>) -> Self {
         Self {
-            buffer: Buffer::default(),
+            buffer,
             needs_redraw: true,
             cursor_location: TextLocation::default(),
             scroll_offset: Position::default(),
-            size: Size {
-                width: size.width,
-                height: size.height - margin,
+            origin: Position::default(),
+            size: Size::default(),
+            has_separator: false,
+            status_bar: StatusBar::default(),
+            visual_anchor: None,
+            visual_lines: None,
+        }
+    }
+    // Creates a window showing the same buffer at the same location.
+    pub fn duplicate(&self) -> Self {
+        let mut window = Self::new(Rc::clone(&self.buffer));
+        window.cursor_location = self.cursor_location;
+        window.scroll_offset = self.scroll_offset;
+        window
+    }
+    pub fn get_buffer(&self) -> &Rc<RefCell<Buffer>> {
+        &self.buffer
+    }
+    // Places the window in the area of the terminal.
+    // The area includes the status line, and the separator if `has_separator` is true.
+    pub fn set_area(
+        &mut self,
+        origin: Position,
+        size: Size,
+        has_separator: bool,
+    ) -> Result<(), std::io::Error> {
+        self.origin = origin;
+        self.size = Size {
+            height: size.height.saturating_sub(1),
+            width: size.width.saturating_sub(usize::from(has_separator)),
+        };
+        self.has_separator = has_separator;
+        self.status_bar.set_area(
+            Position {
+                col: origin.col,
+                row: origin.row + self.size.height,
             },
+            self.size.width,
+        );
+        self.needs_redraw = true;
+        self.update_scroll_offset()
+    }
+    // Returns the area including the status line and the separator.
+    pub fn get_area(&self) -> (Position, Size) {
+        let size = Size {
+            height: self.size.height + 1,
+            width: self.size.width + usize::from(self.has_separator),
+        };
+        (self.origin, size)
+    }
+    // Follows edits made in another window showing the same buffer.
+    pub fn adjust_for_changes(&mut self, changes: &[Edit]) -> Result<(), std::io::Error> {
+        if changes.is_empty() {
+            return Ok(());
         }
+        let mut top = TextLocation {
+            grapheme_idx: 0,
+            line_idx: self.scroll_offset.row,
+        };
+        for change in changes {
+            self.cursor_location = change.adjust_location(self.cursor_location);
+            top = change.adjust_location(top);
+        }
+        self.scroll_offset.row = top.line_idx;
+        self.normalize_cursor_position(false)?;
+        self.needs_redraw = true;
+        Ok(())
     }
-    pub fn load_file(&mut self, filename: &str) {
-        self.buffer.load_file(filename);
+    // Shows another buffer with the cursor and scroll offset restored.
+    pub fn set_buffer(
+        &mut self,
+        buffer: Rc<RefCell<Buffer>>,
+        cursor_location: TextLocation,
+        scroll_offset: Position,
+    ) -> Result<(), std::io::Error> {
+        self.buffer = buffer;
+        self.cursor_location = cursor_location;
+        self.scroll_offset = scroll_offset;
+        self.visual_anchor = None;
+        self.visual_lines = None;
+        self.normalize_cursor_position(false)?;
         self.needs_redraw = true;
+        Ok(())
+    }
+    pub fn get_cursor_location(&self) -> TextLocation {
+        self.cursor_location
+    }
+    pub fn get_scroll_offset(&self) -> Position {
+        self.scroll_offset
     }
     pub fn get_status(&self) -> DocumentStatus {
+        let buffer = self.buffer.borrow();
         DocumentStatus {
-            total_lines: self.buffer.get_n_lines(),
+            total_lines: buffer.get_n_lines(),
             current_line_index: self.cursor_location.line_idx,
-            file_type: self.buffer.get_filetype(),
-            is_modified: self.buffer.modified,
-            file_name: self.buffer.get_filename(),
+            file_type: buffer.get_filetype(),
+            is_modified: buffer.modified,
+            is_readonly: buffer.readonly,
+            file_name: buffer.get_filename(),
+            file_options: buffer.options,
         }
     }
     pub fn set_needs_redraw(&mut self) {
         self.needs_redraw = true;
+        self.status_bar.set_needs_redraw();
     }
-    pub fn render(&mut self, context: &RenderContext) -> Result<(), std::io::Error> {
+    pub fn render(
+        &mut self,
+        context: &RenderContext,
+        is_current: bool,
+    ) -> Result<(), std::io::Error> {
+        self.status_bar.update_status(self.get_status());
+        self.status_bar.set_current(is_current);
+        self.render_text(context)?;
+        self.status_bar.render()
+    }
+    fn render_text(&mut self, context: &RenderContext) -> Result<(), std::io::Error> {
         // TODO: separate implementation of render()
         // according to whether buffer is empty or not.
         if !self.needs_redraw {
             return Ok(());
         }
-        let mut highlighter = HighlighterBundler::new(context);
-        self.buffer.highlight(&mut highlighter);
         let top = self.scroll_offset.row;
         let Size { height, width } = self.size;
+        let mut highlighter = HighlighterBundler::new(context, top);
+        self.buffer
+            .borrow()
+            .highlight(top + height, &mut highlighter);
         for i in 0..height {
-            if let Some(line) = self.buffer.lines.get(i + top) {
+            let line = self.buffer.borrow().get_line(i + top);
+            if let Some(line) = line {
                 let left = self.scroll_offset.col;
                 let right = left + width;
                 let view = LineView::new(&line, left, right);
@@ -94,96 +255,358 @@ impl Window {
                 self.render_line(i, &AnnotatedString::from_str("~"))?;
             }
         }
-        if self.buffer.is_empty() {
+        if self.buffer.borrow().is_empty() {
             self.draw_welcom_message()?;
         }
+        if self.has_separator {
+            self.draw_separator()?;
+        }
         self.needs_redraw = false;
         Ok(())
     }
-    pub fn save_buffer(&mut self) -> Result<(), std::io::Error> {
-        self.buffer.save()?;
-        Ok(())
+    pub fn set_buffer_option(&mut self, arg: &str) -> Option<Result<(), String>> {
+        self.buffer.borrow_mut().set_option(arg)
     }
-    pub fn save_buffer_with_filename(&mut self, filename: &str) -> Result<(), std::io::Error> {
-        self.buffer.save_as_filename(filename)?;
-        Ok(())
+    pub fn save_buffer(&mut self, force: bool, backupext: Option<&str>) -> Result<(), BufferError> {
+        self.buffer.borrow_mut().save(force, backupext)
+    }
+    pub fn save_buffer_with_filename(
+        &mut self,
+        filename: &str,
+        backupext: Option<&str>,
+    ) -> Result<(), BufferError> {
+        self.buffer
+            .borrow_mut()
+            .save_as_filename(filename, backupext)
     }
-    pub fn search(
+    // Moves the cursor to the next or previous match, wrapping around at the end of the buffer,
+    // and returns whether there is one.
+    pub fn move_to_match(
         &mut self,
-        pattern: Option<&str>,
+        pattern: &SearchPattern,
         direction: SearchDirection,
+    ) -> Result<bool, std::io::Error> {
+        self.needs_redraw = true;
+        let found = self
+            .buffer
+            .borrow()
+            .find_next_match(pattern, self.cursor_location, direction);
+        let Some(loc) = found else {
+            return Ok(false);
+        };
+        self.cursor_location = loc;
+        self.update_scroll_offset()?;
+        Ok(true)
+    }
+    // Puts the cursor and scroll offset back, such as when a search is canceled.
+    pub fn restore_view(
+        &mut self,
+        cursor_location: TextLocation,
+        scroll_offset: Position,
     ) -> Result<(), std::io::Error> {
-        // Always redraw to update search highlight
+        self.cursor_location = cursor_location;
+        self.scroll_offset = scroll_offset;
+        self.normalize_cursor_position(false)?;
         self.needs_redraw = true;
-        if pattern.is_none() {
-            return Ok(());
+        Ok(())
+    }
+    // Finds the next match of `:substitute` and moves the cursor to it.
+    pub fn find_substitute_match(
+        &mut self,
+        session: &mut SubstituteSession,
+    ) -> Result<Option<SubstituteMatch>, std::io::Error> {
+        let Some(m) = session.find_next(&self.buffer.borrow()) else {
+            return Ok(None);
+        };
+        let line = self
+            .buffer
+            .borrow()
+            .get_line(m.line_idx)
+            .unwrap_or_default();
+        self.cursor_location = TextLocation {
+            grapheme_idx: line.to_grapheme_idx(m.start),
+            line_idx: m.line_idx,
+        };
+        self.update_scroll_offset()?;
+        self.needs_redraw = true;
+        Ok(Some(m))
+    }
+    pub fn replace_substitute_match(
+        &mut self,
+        session: &mut SubstituteSession,
+        m: &SubstituteMatch,
+    ) {
+        session.replace(&mut self.buffer.borrow_mut(), m);
+        self.needs_redraw = true;
+    }
+    pub fn skip_substitute_match(&self, session: &mut SubstituteSession, m: &SubstituteMatch) {
+        session.skip(&self.buffer.borrow(), m);
+    }
+    pub fn replace_all_substitute_matches(&mut self, session: &mut SubstituteSession) {
+        let mut buffer = self.buffer.borrow_mut();
+        while let Some(m) = session.find_next(&buffer) {
+            session.replace(&mut buffer, &m);
         }
-        let pattern = pattern.unwrap();
-        let result_list = self.buffer.search(pattern);
-        if !result_list.is_empty() {
-            match direction {
-                SearchDirection::Forward => {
-                    for loc in result_list {
-                        if self.cursor_location.line_idx < loc.line_idx
-                            || (self.cursor_location.line_idx == loc.line_idx
-                                && self.cursor_location.grapheme_idx < loc.grapheme_idx)
-                        {
-                            self.cursor_location = loc;
-                            self.update_scroll_offset()?;
-                            break;
-                        }
-                    }
-                }
-                SearchDirection::Backward => {
-                    for loc in result_list.iter().rev() {
-                        if loc.line_idx < self.cursor_location.line_idx
-                            || (loc.line_idx == self.cursor_location.line_idx
-                                && loc.grapheme_idx < self.cursor_location.grapheme_idx)
-                        {
-                            self.cursor_location = *loc;
-                            self.update_scroll_offset()?;
-                            break;
-                        }
-                    }
-                }
-            }
-            Ok(())
-        } else {
-            Terminal::print_log(&format!("Pattern not found: {}", pattern))?;
-            Ok(())
+        drop(buffer);
+        self.needs_redraw = true;
+    }
+    // Moves the cursor to the last substituted line.
+    pub fn finish_substitute(&mut self, session: &SubstituteSession) -> Result<(), std::io::Error> {
+        if let Some(line_idx) = session.last_substituted_line() {
+            self.cursor_location.line_idx = line_idx;
+            self.handle_move(Direction::FirstNonBlank, 1, false)?;
         }
+        self.needs_redraw = true;
+        Ok(())
     }
     pub fn handle_move(
         &mut self,
         direction: Direction,
+        count: usize,
         allow_past_end: bool,
     ) -> Result<(), std::io::Error> {
+        self.cursor_location = self.calc_motion_target(direction, count);
+        self.normalize_cursor_position(allow_past_end)?;
+        self.update_scroll_offset()?;
+        if self.visual_anchor.is_some() {
+            // selection follows the cursor
+            self.needs_redraw = true;
+        }
+        Ok(())
+    }
+    // Returns location where the cursor moves by `count` times of motion.
+    // Returned location may point out of text, and should be normalized.
+    fn calc_motion_target(&self, direction: Direction, count: usize) -> TextLocation {
         let TextLocation {
             mut grapheme_idx,
             mut line_idx,
         } = self.cursor_location;
         match direction {
             Direction::Left => {
-                grapheme_idx = grapheme_idx.saturating_sub(1);
+                grapheme_idx = grapheme_idx.saturating_sub(count);
             }
             Direction::Down => {
-                line_idx = line_idx.saturating_add(1);
+                line_idx = line_idx.saturating_add(count);
             }
             Direction::Up => {
-                line_idx = line_idx.saturating_sub(1);
+                line_idx = line_idx.saturating_sub(count);
             }
             Direction::Right => {
-                grapheme_idx = grapheme_idx.saturating_add(1);
+                grapheme_idx = grapheme_idx.saturating_add(count);
+            }
+            Direction::WordForward
+            | Direction::BigWordForward
+            | Direction::WordBackward
+            | Direction::BigWordBackward
+            | Direction::WordEnd
+            | Direction::BigWordEnd
+            | Direction::ParagraphForward
+            | Direction::ParagraphBackward => {
+                let mut loc = self.cursor_location;
+                for _ in 0..count {
+                    loc = match direction {
+                        Direction::WordForward => self.buffer.borrow().next_word_start(loc, false),
+                        Direction::BigWordForward => {
+                            self.buffer.borrow().next_word_start(loc, true)
+                        }
+                        Direction::WordBackward => self.buffer.borrow().prev_word_start(loc, false),
+                        Direction::BigWordBackward => {
+                            self.buffer.borrow().prev_word_start(loc, true)
+                        }
+                        Direction::WordEnd => self.buffer.borrow().next_word_end(loc, false),
+                        Direction::BigWordEnd => self.buffer.borrow().next_word_end(loc, true),
+                        Direction::ParagraphForward => {
+                            self.buffer.borrow().next_paragraph_boundary(loc)
+                        }
+                        _ => self.buffer.borrow().prev_paragraph_boundary(loc),
+                    };
+                }
+                return loc;
+            }
+            Direction::LineStart => {
+                grapheme_idx = 0;
+            }
+            Direction::FirstNonBlank => {
+                grapheme_idx = self
+                    .buffer
+                    .borrow()
+                    .get_line(line_idx)
+                    .map_or(0, |line| line.first_non_blank());
+            }
+            Direction::LineEnd => {
+                // `2$` moves to the end of next line
+                line_idx = line_idx.saturating_add(count - 1);
+                let line_idx = usize::min(
+                    line_idx,
+                    self.buffer.borrow().get_n_lines().saturating_sub(1),
+                );
+                grapheme_idx = self
+                    .buffer
+                    .borrow()
+                    .get_line_length(line_idx)
+                    .saturating_sub(1);
+            }
+            Direction::GotoLine(line_number) => {
+                line_idx = usize::min(
+                    line_number.saturating_sub(1),
+                    self.buffer.borrow().get_n_lines().saturating_sub(1),
+                );
+                grapheme_idx = self
+                    .buffer
+                    .borrow()
+                    .get_line(line_idx)
+                    .map_or(0, |line| line.first_non_blank());
+            }
+            Direction::MatchingBracket => {
+                return self
+                    .buffer
+                    .borrow()
+                    .matching_bracket(self.cursor_location)
+                    .unwrap_or(self.cursor_location);
             }
         }
-
-        self.cursor_location = TextLocation {
+        TextLocation {
             grapheme_idx,
             line_idx,
+        }
+    }
+    // Returns range [start, end) where an operator is applied, and
+    // whether the range is linewise.
+    fn calc_operator_range(
+        &self,
+        operator: Operator,
+        target: OperatorTarget,
+        count: usize,
+    ) -> Option<(TextLocation, TextLocation, bool)> {
+        let cursor = self.cursor_location;
+        let last_line_idx = self.buffer.borrow().get_n_lines().checked_sub(1)?;
+        let direction = match target {
+            OperatorTarget::Lines => {
+                let last = usize::min(cursor.line_idx + count - 1, last_line_idx);
+                return Some((
+                    TextLocation {
+                        grapheme_idx: 0,
+                        line_idx: cursor.line_idx,
+                    },
+                    TextLocation {
+                        grapheme_idx: self.buffer.borrow().get_line_length(last),
+                        line_idx: last,
+                    },
+                    true,
+                ));
+            }
+            OperatorTarget::Motion(direction) => direction,
         };
-        self.normalize_cursor_position(allow_past_end)?;
-        self.update_scroll_offset()?;
-        Ok(())
+        // `cw` on a non-blank character works like `ce`
+        let on_blank = self
+            .buffer
+            .borrow()
+            .get_line(cursor.line_idx)
+            .and_then(|line| line.get_nth_grapheme(cursor.grapheme_idx))
+            .is_none_or(|grapheme| grapheme.to_string().trim().is_empty());
+        let direction = match direction {
+            Direction::WordForward if operator == Operator::Change && !on_blank => {
+                Direction::WordEnd
+            }
+            Direction::BigWordForward if operator == Operator::Change && !on_blank => {
+                Direction::BigWordEnd
+            }
+            direction => direction,
+        };
+        let target = self.calc_motion_target(direction, count);
+        if direction.is_linewise() {
+            let target_line_idx = usize::min(target.line_idx, last_line_idx);
+            if target_line_idx == cursor.line_idx {
+                // motion failed, e.g. `dj` at the last line
+                return None;
+            }
+            let first = usize::min(cursor.line_idx, target_line_idx);
+            let last = usize::max(cursor.line_idx, target_line_idx);
+            return Some((
+                TextLocation {
+                    grapheme_idx: 0,
+                    line_idx: first,
+                },
+                TextLocation {
+                    grapheme_idx: self.buffer.borrow().get_line_length(last),
+                    line_idx: last,
+                },
+                true,
+            ));
+        }
+        let (start, mut end) =
+            if (target.line_idx, target.grapheme_idx) < (cursor.line_idx, cursor.grapheme_idx) {
+                (target, cursor)
+            } else {
+                (cursor, target)
+            };
+        if direction.is_inclusive() {
+            end.grapheme_idx += 1;
+        }
+        let is_word_forward = matches!(
+            direction,
+            Direction::WordForward | Direction::BigWordForward
+        );
+        if is_word_forward && end.line_idx > start.line_idx {
+            // `dw` at the last word in a line does not join lines
+            end.line_idx -= 1;
+            end.grapheme_idx = usize::MAX;
+        }
+        end.grapheme_idx = usize::min(
+            end.grapheme_idx,
+            self.buffer.borrow().get_line_length(end.line_idx),
+        );
+        if start == end {
+            return None;
+        }
+        Some((start, end, false))
+    }
+    // Applies operator to the text between the cursor and target,
+    // and returns the affected text.
+    pub fn apply_operator(
+        &mut self,
+        operator: Operator,
+        target: OperatorTarget,
+        count: usize,
+    ) -> Result<RegisterContent, std::io::Error> {
+        let Some((start, end, linewise)) = self.calc_operator_range(operator, target, count) else {
+            return Ok(RegisterContent::new(
+                String::new(),
+                RegisterKind::Characterwise,
+            ));
+        };
+        self.buffer
+            .borrow_mut()
+            .begin_undo_group(self.cursor_location);
+        let text = match (operator, linewise) {
+            (Operator::Yank, true) => self.buffer.borrow().get_lines(start.line_idx, end.line_idx),
+            (Operator::Yank, false) => self.buffer.borrow().get_text(start, end),
+            (Operator::Delete, true) => self
+                .buffer
+                .borrow_mut()
+                .delete_lines(start.line_idx, end.line_idx),
+            (Operator::Change, true) => self
+                .buffer
+                .borrow_mut()
+                .clear_lines(start.line_idx, end.line_idx),
+            (_, false) => self.buffer.borrow_mut().delete_range(start, end),
+        };
+        self.buffer.borrow_mut().end_undo_group();
+        self.cursor_location = match (operator, linewise) {
+            (Operator::Yank, true) => TextLocation {
+                grapheme_idx: self.cursor_location.grapheme_idx,
+                line_idx: start.line_idx,
+            },
+            _ => start,
+        };
+        self.normalize_cursor_position(operator == Operator::Change)?;
+        self.needs_redraw = true;
+        let kind = if linewise {
+            RegisterKind::Linewise
+        } else {
+            RegisterKind::Characterwise
+        };
+        Ok(RegisterContent::new(text, kind))
     }
     pub fn normalize_cursor_position(
         &mut self,
@@ -194,10 +617,10 @@ impl Window {
             mut grapheme_idx,
             mut line_idx,
         } = self.cursor_location;
-        let n_line = self.buffer.lines.len();
+        let n_line = self.buffer.borrow().get_n_lines();
         line_idx = std::cmp::min(line_idx, n_line.saturating_sub(1));
 
-        let line_length = self.buffer.lines.get(line_idx).map_or(0, |s| s.len());
+        let line_length = self.buffer.borrow().get_line_length(line_idx);
         let idx_lim = if allow_past_end {
             line_length
         } else {
@@ -213,9 +636,17 @@ impl Window {
         Ok(())
     }
     pub fn insert_char(&mut self, c: char) -> Result<(), std::io::Error> {
-        let orig_len = self.buffer.get_line_length(self.cursor_location.line_idx);
-        self.buffer.insert_char(c, self.cursor_location);
-        let new_len = self.buffer.get_line_length(self.cursor_location.line_idx);
+        let orig_len = self
+            .buffer
+            .borrow()
+            .get_line_length(self.cursor_location.line_idx);
+        self.buffer
+            .borrow_mut()
+            .insert_char(c, self.cursor_location);
+        let new_len = self
+            .buffer
+            .borrow()
+            .get_line_length(self.cursor_location.line_idx);
         if new_len > orig_len {
             self.cursor_location.grapheme_idx += 1;
         }
@@ -226,7 +657,9 @@ impl Window {
     pub fn handle_backspace(&mut self) -> Result<(), std::io::Error> {
         if self.cursor_location.grapheme_idx > 0 {
             self.cursor_location.grapheme_idx -= 1;
-            self.buffer.delete_grapheme(self.cursor_location);
+            self.buffer
+                .borrow_mut()
+                .delete_grapheme(self.cursor_location);
             self.update_scroll_offset()?;
             self.needs_redraw = true;
         } else if self.cursor_location.grapheme_idx == 0 {
@@ -235,8 +668,10 @@ impl Window {
             }
             let orig_len = self
                 .buffer
+                .borrow()
                 .get_line_length(self.cursor_location.line_idx - 1);
             self.buffer
+                .borrow_mut()
                 .join_adjacent_rows(self.cursor_location.line_idx - 1);
             self.cursor_location = TextLocation {
                 grapheme_idx: orig_len,
@@ -247,18 +682,537 @@ impl Window {
         }
         Ok(())
     }
+    // Returns the word under or after the cursor in the cursor line.
+    pub fn get_word_under_cursor(&self) -> Option<String> {
+        let TextLocation {
+            grapheme_idx,
+            line_idx,
+        } = self.cursor_location;
+        let line = self.buffer.borrow().get_line(line_idx)?;
+        line.word_spans(false)
+            .into_iter()
+            .filter(|(_, end)| *end > grapheme_idx)
+            .map(|(start, end)| line.get_substr(start, end))
+            .find(|word| word.chars().any(|c| c.is_alphanumeric() || c == '_'))
+            .map(String::from)
+    }
+    // Deletes the character under the cursor, or joins the next line at the end of line.
+    pub fn handle_delete(&mut self) -> Result<(), std::io::Error> {
+        let TextLocation {
+            grapheme_idx,
+            line_idx,
+        } = self.cursor_location;
+        let line_length = self.buffer.borrow().get_line_length(line_idx);
+        if grapheme_idx < line_length {
+            self.buffer
+                .borrow_mut()
+                .delete_grapheme(self.cursor_location);
+        } else {
+            self.buffer.borrow_mut().join_adjacent_rows(line_idx);
+        }
+        self.needs_redraw = true;
+        Ok(())
+    }
+    // Deletes the word before the cursor with blanks after it, as `CTRL-W` in insert mode.
+    pub fn delete_word_before_cursor(&mut self) -> Result<(), std::io::Error> {
+        let TextLocation {
+            grapheme_idx,
+            line_idx,
+        } = self.cursor_location;
+        let line = self.buffer.borrow().get_line(line_idx).unwrap_or_default();
+        let start = line
+            .word_spans(false)
+            .into_iter()
+            .map(|(start, _)| start)
+            .rev()
+            .find(|start| *start < grapheme_idx)
+            .unwrap_or(0);
+        self.delete_before_cursor(start)
+    }
+    // Deletes text before the cursor up to the indentation, or up to the line start
+    // if the cursor is in the indentation, as `CTRL-U` in insert mode.
+    pub fn delete_line_before_cursor(&mut self) -> Result<(), std::io::Error> {
+        let TextLocation {
+            grapheme_idx,
+            line_idx,
+        } = self.cursor_location;
+        let first_non_blank = self
+            .buffer
+            .borrow()
+            .get_line(line_idx)
+            .map_or(0, |line| line.first_non_blank());
+        let start = if first_non_blank < grapheme_idx {
+            first_non_blank
+        } else {
+            0
+        };
+        self.delete_before_cursor(start)
+    }
+    // Deletes text from `start` to the cursor in the cursor line.
+    // At the line start, the line is joined to the previous one like backspace.
+    fn delete_before_cursor(&mut self, start: usize) -> Result<(), std::io::Error> {
+        if self.cursor_location.grapheme_idx == 0 {
+            return self.handle_backspace();
+        }
+        let start = TextLocation {
+            grapheme_idx: start,
+            line_idx: self.cursor_location.line_idx,
+        };
+        self.buffer
+            .borrow_mut()
+            .delete_range(start, self.cursor_location);
+        self.cursor_location = start;
+        self.update_scroll_offset()?;
+        self.needs_redraw = true;
+        Ok(())
+    }
+    // Adds or removes a level of indentation of the cursor line, keeping the cursor
+    // on the same character. Indentation is rounded to a multiple of `shiftwidth`.
+    pub fn shift_indent(
+        &mut self,
+        increase: bool,
+        shiftwidth: usize,
+        expandtab: bool,
+    ) -> Result<(), std::io::Error> {
+        let line_idx = self.cursor_location.line_idx;
+        let Some(line) = self.buffer.borrow().get_line(line_idx) else {
+            return Ok(());
+        };
+        let shiftwidth = if shiftwidth == 0 { TABSTOP } else { shiftwidth };
+        let indent_len = line
+            .get_raw_str()
+            .chars()
+            .take_while(|c| *c == ' ' || *c == '\t')
+            .count();
+        let width = line.calc_width_until_grapheme_index(indent_len);
+        let new_width = if increase {
+            (width / shiftwidth + 1) * shiftwidth
+        } else {
+            width.saturating_sub(1) / shiftwidth * shiftwidth
+        };
+        if new_width == width {
+            return Ok(());
+        }
+        let indent = if expandtab {
+            " ".repeat(new_width)
+        } else {
+            "\t".repeat(new_width / TABSTOP) + &" ".repeat(new_width % TABSTOP)
+        };
+        let line_start = TextLocation {
+            grapheme_idx: 0,
+            line_idx,
+        };
+        let mut buffer = self.buffer.borrow_mut();
+        buffer.delete_range(
+            line_start,
+            TextLocation {
+                grapheme_idx: indent_len,
+                line_idx,
+            },
+        );
+        buffer.insert_text(line_start, &indent);
+        drop(buffer);
+        self.cursor_location.grapheme_idx =
+            indent.len() + self.cursor_location.grapheme_idx.saturating_sub(indent_len);
+        self.update_scroll_offset()?;
+        self.needs_redraw = true;
+        Ok(())
+    }
+    // Moves past the last character of the line, as End in insert mode.
+    pub fn move_past_line_end(&mut self) -> Result<(), std::io::Error> {
+        self.cursor_location.grapheme_idx = self
+            .buffer
+            .borrow()
+            .get_line_length(self.cursor_location.line_idx);
+        self.update_scroll_offset()
+    }
+    // Scrolls the text by a page, keeping two lines of the previous page,
+    // and moves the cursor by the same number of lines.
+    pub fn scroll_page(
+        &mut self,
+        forward: bool,
+        allow_past_end: bool,
+    ) -> Result<(), std::io::Error> {
+        let amount = self.size.height.saturating_sub(2).max(1);
+        let last_line_idx = self.buffer.borrow().get_n_lines().saturating_sub(1);
+        let (row, direction) = if forward {
+            (
+                usize::min(self.scroll_offset.row + amount, last_line_idx),
+                Direction::Down,
+            )
+        } else {
+            (self.scroll_offset.row.saturating_sub(amount), Direction::Up)
+        };
+        if row != self.scroll_offset.row {
+            self.scroll_offset.row = row;
+            self.needs_redraw = true;
+        }
+        self.handle_move(direction, amount, allow_past_end)
+    }
+    // Puts register content `count` times after or before the cursor.
+    pub fn put(
+        &mut self,
+        content: &RegisterContent,
+        after: bool,
+        count: usize,
+    ) -> Result<(), std::io::Error> {
+        self.buffer.borrow_mut().init_if_empty();
+        let cursor = self.cursor_location;
+        let line_length = self.buffer.borrow().get_line_length(cursor.line_idx);
+        self.buffer.borrow_mut().begin_undo_group(cursor);
+        match content.kind {
+            RegisterKind::Characterwise => {
+                let text = content.text.repeat(count);
+                let start = TextLocation {
+                    grapheme_idx: if after && line_length > 0 {
+                        cursor.grapheme_idx + 1
+                    } else {
+                        cursor.grapheme_idx
+                    },
+                    line_idx: cursor.line_idx,
+                };
+                let end = self.buffer.borrow_mut().insert_text(start, &text);
+                self.cursor_location = if text.contains('\n') {
+                    start
+                } else {
+                    // cursor is placed on the last character of put text
+                    TextLocation {
+                        grapheme_idx: end.grapheme_idx.saturating_sub(1),
+                        line_idx: end.line_idx,
+                    }
+                };
+            }
+            RegisterKind::Linewise => {
+                let lines = vec![content.text.as_str(); count].join("\n");
+                let line_idx = if after {
+                    self.buffer.borrow_mut().insert_text(
+                        TextLocation {
+                            grapheme_idx: line_length,
+                            line_idx: cursor.line_idx,
+                        },
+                        &format!("\n{lines}"),
+                    );
+                    cursor.line_idx + 1
+                } else {
+                    self.buffer.borrow_mut().insert_text(
+                        TextLocation {
+                            grapheme_idx: 0,
+                            line_idx: cursor.line_idx,
+                        },
+                        &format!("{lines}\n"),
+                    );
+                    cursor.line_idx
+                };
+                self.cursor_location = TextLocation {
+                    grapheme_idx: 0,
+                    line_idx,
+                };
+            }
+            RegisterKind::Blockwise => {
+                let (left, right) = self.get_column_range(cursor);
+                let column = if after && line_length > 0 {
+                    right
+                } else {
+                    left
+                };
+                for (i, piece) in content.text.split('\n').enumerate() {
+                    let line_idx = cursor.line_idx + i;
+                    if line_idx >= self.buffer.borrow().get_n_lines() {
+                        let last = self.buffer.borrow().get_n_lines() - 1;
+                        let grapheme_idx = self.buffer.borrow().get_line_length(last);
+                        self.buffer.borrow_mut().insert_text(
+                            TextLocation {
+                                grapheme_idx,
+                                line_idx: last,
+                            },
+                            "\n",
+                        );
+                    }
+                    self.put_at_column(line_idx, column, &piece.repeat(count));
+                }
+                let line = self.buffer.borrow().get_line(cursor.line_idx);
+                self.cursor_location = TextLocation {
+                    grapheme_idx: line
+                        .map_or(0, |line| line.graphemes_in_columns(column, column + 1).0),
+                    line_idx: cursor.line_idx,
+                };
+            }
+        }
+        self.buffer.borrow_mut().end_undo_group();
+        self.normalize_cursor_position(false)?;
+        self.needs_redraw = true;
+        Ok(())
+    }
+    // Inserts text at terminal column `column` in the line.
+    // Short lines are padded with spaces.
+    fn put_at_column(&mut self, line_idx: usize, column: usize, text: &str) {
+        let line = self.buffer.borrow().get_line(line_idx).unwrap_or_default();
+        let width = line.calc_width_until_grapheme_index(line.len());
+        let (grapheme_idx, _) = line.graphemes_in_columns(column, column + 1);
+        let text = if width < column {
+            " ".repeat(column - width) + text
+        } else {
+            String::from(text)
+        };
+        self.buffer.borrow_mut().insert_text(
+            TextLocation {
+                grapheme_idx,
+                line_idx,
+            },
+            &text,
+        );
+    }
+    pub fn start_selection(&mut self, kind: VisualSubmode) {
+        self.visual_anchor = Some((self.cursor_location, kind));
+        self.needs_redraw = true;
+    }
+    pub fn set_selection_kind(&mut self, kind: VisualSubmode) {
+        if let Some((anchor, _)) = self.visual_anchor {
+            self.visual_anchor = Some((anchor, kind));
+            self.needs_redraw = true;
+        }
+    }
+    pub fn clear_selection(&mut self) {
+        self.take_selection();
+        self.needs_redraw = true;
+    }
+    // Ends the selection and returns it.
+    fn take_selection(&mut self) -> Option<Selection> {
+        let selection = self.get_selection()?;
+        self.visual_anchor = None;
+        self.visual_lines = Some((selection.start.line_idx, selection.end.line_idx));
+        Some(selection)
+    }
+    pub fn get_visual_lines(&self) -> Option<(usize, usize)> {
+        self.visual_lines
+    }
+    pub fn swap_selection_anchor(&mut self) -> Result<(), std::io::Error> {
+        if let Some((anchor, kind)) = self.visual_anchor {
+            self.visual_anchor = Some((self.cursor_location, kind));
+            self.cursor_location = anchor;
+            self.update_scroll_offset()?;
+            self.needs_redraw = true;
+        }
+        Ok(())
+    }
+    pub fn get_selection(&self) -> Option<Selection> {
+        let (anchor, kind) = self.visual_anchor?;
+        let cursor = self.cursor_location;
+        let (start, end) =
+            if (anchor.line_idx, anchor.grapheme_idx) <= (cursor.line_idx, cursor.grapheme_idx) {
+                (anchor, cursor)
+            } else {
+                (cursor, anchor)
+            };
+        let (anchor_left, anchor_right) = self.get_column_range(anchor);
+        let (cursor_left, cursor_right) = self.get_column_range(cursor);
+        let columns = (
+            usize::min(anchor_left, cursor_left),
+            usize::max(anchor_right, cursor_right),
+        );
+        Some(match kind {
+            VisualSubmode::Blockwise => Selection {
+                kind,
+                start: TextLocation {
+                    grapheme_idx: usize::min(start.grapheme_idx, end.grapheme_idx),
+                    line_idx: start.line_idx,
+                },
+                end: TextLocation {
+                    grapheme_idx: usize::max(start.grapheme_idx, end.grapheme_idx),
+                    line_idx: end.line_idx,
+                },
+                columns,
+            },
+            _ => Selection {
+                kind,
+                start,
+                end,
+                columns,
+            },
+        })
+    }
+    // Returns text in the selection.
+    // Lines of blockwise selection are joined with '\n'.
+    pub fn yank_selection(&mut self) -> Result<RegisterContent, std::io::Error> {
+        let Some(selection) = self.take_selection() else {
+            return Ok(RegisterContent::new(
+                String::new(),
+                RegisterKind::Characterwise,
+            ));
+        };
+        let text = match selection.kind {
+            VisualSubmode::Characterwise => self
+                .buffer
+                .borrow()
+                .get_text(selection.start, self.selection_end_exclusive(&selection)),
+            VisualSubmode::Linewise => self
+                .buffer
+                .borrow()
+                .get_lines(selection.start.line_idx, selection.end.line_idx),
+            VisualSubmode::Blockwise => {
+                let mut pieces = vec![];
+                for line_idx in selection.start.line_idx..=selection.end.line_idx {
+                    let line = self.buffer.borrow().get_line(line_idx).unwrap_or_default();
+                    let (start, end) = selection.grapheme_range(line_idx, &line).unwrap_or((0, 0));
+                    pieces.push(String::from(line.get_substr(start, end)));
+                }
+                pieces.join("\n")
+            }
+        };
+        self.move_to_selection_start(&selection, false)?;
+        Ok(RegisterContent::new(text, selection.register_kind()))
+    }
+    // Deletes text in the selection and returns deleted text.
+    // Lines of blockwise selection are joined with '\n'.
+    pub fn delete_selection(&mut self) -> Result<RegisterContent, std::io::Error> {
+        self.remove_selection(false)
+    }
+    // Same as `delete_selection()`, but leaves an empty line for linewise
+    // selection and places the cursor to start inserting text.
+    pub fn change_selection(&mut self) -> Result<RegisterContent, std::io::Error> {
+        self.remove_selection(true)
+    }
+    fn remove_selection(&mut self, for_change: bool) -> Result<RegisterContent, std::io::Error> {
+        let Some(selection) = self.take_selection() else {
+            return Ok(RegisterContent::new(
+                String::new(),
+                RegisterKind::Characterwise,
+            ));
+        };
+        self.buffer
+            .borrow_mut()
+            .begin_undo_group(self.cursor_location);
+        let text = match selection.kind {
+            VisualSubmode::Characterwise => {
+                let end = self.selection_end_exclusive(&selection);
+                self.buffer.borrow_mut().delete_range(selection.start, end)
+            }
+            VisualSubmode::Linewise if for_change => self
+                .buffer
+                .borrow_mut()
+                .clear_lines(selection.start.line_idx, selection.end.line_idx),
+            VisualSubmode::Linewise => self
+                .buffer
+                .borrow_mut()
+                .delete_lines(selection.start.line_idx, selection.end.line_idx),
+            VisualSubmode::Blockwise => {
+                let mut pieces = vec![];
+                for line_idx in selection.start.line_idx..=selection.end.line_idx {
+                    let line = self.buffer.borrow().get_line(line_idx).unwrap_or_default();
+                    let (start, end) = selection.grapheme_range(line_idx, &line).unwrap_or((0, 0));
+                    pieces.push(self.buffer.borrow_mut().delete_range(
+                        TextLocation {
+                            grapheme_idx: start,
+                            line_idx,
+                        },
+                        TextLocation {
+                            grapheme_idx: end,
+                            line_idx,
+                        },
+                    ));
+                }
+                pieces.join("\n")
+            }
+        };
+        self.buffer.borrow_mut().end_undo_group();
+        self.move_to_selection_start(&selection, for_change)?;
+        self.needs_redraw = true;
+        Ok(RegisterContent::new(text, selection.register_kind()))
+    }
+    fn selection_end_exclusive(&self, selection: &Selection) -> TextLocation {
+        let TextLocation {
+            grapheme_idx,
+            line_idx,
+        } = selection.end;
+        if grapheme_idx < self.buffer.borrow().get_line_length(line_idx) {
+            TextLocation {
+                grapheme_idx: grapheme_idx + 1,
+                line_idx,
+            }
+        } else if line_idx + 1 < self.buffer.borrow().get_n_lines() {
+            // selection includes newline at the end of line
+            TextLocation {
+                grapheme_idx: 0,
+                line_idx: line_idx + 1,
+            }
+        } else {
+            TextLocation {
+                grapheme_idx,
+                line_idx,
+            }
+        }
+    }
+    fn move_to_selection_start(
+        &mut self,
+        selection: &Selection,
+        allow_past_end: bool,
+    ) -> Result<(), std::io::Error> {
+        self.cursor_location = match selection.kind {
+            VisualSubmode::Linewise => TextLocation {
+                grapheme_idx: 0,
+                line_idx: selection.start.line_idx,
+            },
+            _ => selection.start,
+        };
+        self.normalize_cursor_position(allow_past_end)?;
+        self.needs_redraw = true;
+        Ok(())
+    }
+    fn get_column_range(&self, loc: TextLocation) -> (usize, usize) {
+        let line = self.buffer.borrow().get_line(loc.line_idx);
+        let line = line.as_deref();
+        let left = line.map_or(0, |line| {
+            line.calc_width_until_grapheme_index(loc.grapheme_idx)
+        });
+        let right = line.map_or(0, |line| {
+            line.calc_width_until_grapheme_index(loc.grapheme_idx + 1)
+        });
+        (left, usize::max(right, left + 1))
+    }
+    pub fn begin_undo_group(&mut self) {
+        self.buffer
+            .borrow_mut()
+            .begin_undo_group(self.cursor_location);
+    }
+    pub fn end_undo_group(&mut self) {
+        self.buffer.borrow_mut().end_undo_group();
+    }
+    pub fn undo(&mut self) -> Result<(), std::io::Error> {
+        let loc = self.buffer.borrow_mut().undo();
+        if let Some(loc) = loc {
+            self.cursor_location = loc;
+            self.normalize_cursor_position(false)?;
+            self.needs_redraw = true;
+        }
+        Ok(())
+    }
+    pub fn redo(&mut self) -> Result<(), std::io::Error> {
+        let loc = self.buffer.borrow_mut().redo();
+        if let Some(loc) = loc {
+            self.cursor_location = loc;
+            self.normalize_cursor_position(false)?;
+            self.needs_redraw = true;
+        }
+        Ok(())
+    }
     pub fn begin_newline_above(&mut self) -> Result<(), std::io::Error> {
-        self.buffer.begin_newline_above(self.cursor_location);
+        self.buffer
+            .borrow_mut()
+            .begin_newline_above(self.cursor_location);
         self.jump_to_line_start(self.cursor_location.line_idx)?;
         Ok(())
     }
     pub fn begin_newline_below(&mut self) -> Result<(), std::io::Error> {
-        self.buffer.begin_newline_below(self.cursor_location);
+        self.buffer
+            .borrow_mut()
+            .begin_newline_below(self.cursor_location);
         self.jump_to_line_start(self.cursor_location.line_idx + 1)?;
         Ok(())
     }
     pub fn insert_newline(&mut self) -> Result<(), std::io::Error> {
-        self.buffer.insert_newline(self.cursor_location);
+        self.buffer
+            .borrow_mut()
+            .insert_newline(self.cursor_location);
         self.jump_to_line_start(self.cursor_location.line_idx + 1)?;
         Ok(())
     }
@@ -271,18 +1225,19 @@ impl Window {
         self.needs_redraw = true;
         Ok(())
     }
-    pub fn get_relative_position(&self) -> Position {
+    // Returns cursor position on the terminal.
+    pub fn get_screen_position(&self) -> Position {
         let Position { row, col } = self.get_absolute_position();
         Position {
-            col: col - self.scroll_offset.col,
-            row: row - self.scroll_offset.row,
+            col: self.origin.col + col - self.scroll_offset.col,
+            row: self.origin.row + row - self.scroll_offset.row,
         }
     }
     pub fn get_absolute_position(&self) -> Position {
         let cursor_info = self.get_cursor_info();
         if cursor_info
             .grapheme
-            .map_or(false, |grapheme| grapheme.is_tab())
+            .is_some_and(|grapheme| grapheme.is_tab())
         {
             // renders cursor at right end of TAB character
             Position {
@@ -301,13 +1256,14 @@ impl Window {
             grapheme_idx,
             line_idx,
         } = self.cursor_location;
-        let line = self.buffer.lines.get(line_idx);
+        let line = self.buffer.borrow().get_line(line_idx);
+        let line = line.as_deref();
         let col_start = line.map_or(0, |line| line.calc_width_until_grapheme_index(grapheme_idx));
         let col_end = line.map_or(0, |line| {
             line.calc_width_until_grapheme_index(grapheme_idx + 1)
         });
         CursorInfo {
-            grapheme: line.map_or(None, |line| line.get_nth_grapheme(grapheme_idx)),
+            grapheme: line.and_then(|line| line.get_nth_grapheme(grapheme_idx)),
             row: line_idx,
             col_start,
             col_end,
@@ -318,12 +1274,28 @@ impl Window {
         row: usize,
         annotated_text: &AnnotatedString,
     ) -> Result<(), std::io::Error> {
-        let pos = Position { row, col: 0 };
+        let pos = Position {
+            row: self.origin.row + row,
+            col: self.origin.col,
+        };
+        // clear only the width of this window
+        Terminal::move_cursor_to(pos)?;
+        Terminal::print(&" ".repeat(self.size.width))?;
         Terminal::move_cursor_to(pos)?;
-        Terminal::clear_line()?;
         Terminal::print_annotated_str(annotated_text)?;
         Ok(())
     }
+    fn draw_separator(&self) -> Result<(), std::io::Error> {
+        // separator also runs beside the status line
+        for row in 0..=self.size.height {
+            Terminal::move_cursor_to(Position {
+                row: self.origin.row + row,
+                col: self.origin.col + self.size.width,
+            })?;
+            Terminal::print("|")?;
+        }
+        Ok(())
+    }
     fn update_scroll_offset(&mut self) -> Result<(), std::io::Error> {
         let Size { width, height } = self.size;
         let mut offset_changed = false;
@@ -359,8 +1331,11 @@ impl Window {
         let message = format!("{} editor -- v{}", Self::NAME, Self::VERSION);
         // calculate draw position
         let Size { height, width } = self.size;
-        let row = height / 3;
-        let col = (width - message.len()) / 2;
+        if width < message.len() {
+            return Ok(());
+        }
+        let row = self.origin.row + height / 3;
+        let col = self.origin.col + (width - message.len()) / 2;
         // draw messages and column of tildes
         let pos = Position { row, col };
         Terminal::move_cursor_to(pos)?;